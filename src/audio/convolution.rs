@@ -0,0 +1,423 @@
+//! Convolution reverb: plays the synth through the acoustics of a real room,
+//! captured as an impulse response WAV file.
+//!
+//! The impulse response is split into blocks of `BLOCK` samples and convolved in
+//! the frequency domain (uniformly partitioned overlap-save), so the cost per
+//! sample grows with the impulse response length divided by `BLOCK`, not with the
+//! full length. The wet signal comes out one block late; that latency is taken
+//! out of the pre-delay, so it's only audible with pre-delays shorter than a block.
+//! Only the newest block waits for the end of the block; the older blocks'
+//! products are summed a few partitions per frame while the next one fills, so
+//! the work doesn't all land on one sample.
+
+use super::wav;
+use anyhow::{bail, Result};
+use realfft::num_complex::Complex;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Partition size in frames, about 21 ms at 48 kHz.
+pub const BLOCK: usize = 1024;
+/// Longer impulse responses are cut off here, to stay within the real-time budget.
+pub const MAX_IMPULSE_SECONDS: f32 = 6.0;
+pub const MAX_PRE_DELAY_MS: f32 = 250.0;
+/// Mix and on/off changes fade over this long instead of clicking.
+const FADE_SECONDS: f32 = 0.01;
+
+/// Left and right impulse responses at the engine's sample rate. A mono file
+/// is used for both sides.
+#[derive(Debug, Clone)]
+pub struct ImpulseResponse {
+    channels: [Vec<f32>; 2],
+}
+
+impl ImpulseResponse {
+    pub fn load(path: &Path, sample_rate: u32) -> Result<Self> {
+        Self::from_audio(wav::read(path)?, sample_rate)
+    }
+
+    /// Resamples to `sample_rate`, trims to `MAX_IMPULSE_SECONDS` and normalizes,
+    /// so a wet signal at full mix is about as loud as the dry one.
+    pub fn from_audio(audio: wav::Audio, sample_rate: u32) -> Result<Self> {
+        let (left, right) = match audio.channels.as_slice() {
+            [mono] => (mono, mono),
+            [left, right] => (left, right),
+            _ => bail!(
+                "impulse responses must be mono or stereo, this one has {} channels",
+                audio.channels.len()
+            ),
+        };
+        if left.is_empty() {
+            bail!("the impulse response is empty");
+        }
+
+        let max_len = (MAX_IMPULSE_SECONDS * sample_rate as f32) as usize;
+        let prepare = |samples: &[f32]| {
+            let mut samples = resample(samples, audio.sample_rate, sample_rate);
+            samples.truncate(max_len);
+            samples
+        };
+        let mut channels = [prepare(left), prepare(right)];
+
+        let energy = channels
+            .iter()
+            .map(|c| c.iter().map(|s| s * s).sum::<f32>())
+            .fold(0.0, f32::max);
+        if energy <= 0.0 {
+            bail!("the impulse response is silent");
+        }
+        let scale = 1.0 / energy.sqrt();
+        for channel in &mut channels {
+            channel.iter_mut().for_each(|s| *s *= scale);
+        }
+
+        Ok(ImpulseResponse { channels })
+    }
+
+    pub fn len(&self) -> usize {
+        self.channels[0].len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Linear interpolation is plenty for a reverb tail.
+fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to {
+        return samples.to_vec();
+    }
+    let step = from as f64 / to as f64;
+    let len = ((samples.len() as f64) / step).floor() as usize;
+    (0..len)
+        .map(|i| {
+            let position = i as f64 * step;
+            let index = position as usize;
+            let frac = (position - index as f64) as f32;
+            let a = samples[index];
+            let b = samples.get(index + 1).copied().unwrap_or(0.0);
+            a + (b - a) * frac
+        })
+        .collect()
+}
+
+/// One channel's partitioned convolution.
+struct Partitioned {
+    forward: Arc<dyn RealToComplex<f32>>,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+    /// Spectrum of each `BLOCK`-long piece of the impulse response
+    partitions: Vec<Vec<Complex<f32>>>,
+    /// Spectra of recent input blocks, newest at `head`
+    history: Vec<Vec<Complex<f32>>>,
+    head: usize,
+    /// Next partition to add to `sum` for the block being filled
+    next_partition: usize,
+    // Previous and current input block, the overlap-save window
+    window: Vec<f32>,
+    // Scratch space, so processing never allocates
+    time: Vec<f32>,
+    sum: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl Partitioned {
+    fn new(impulse: &[f32], planner: &mut RealFftPlanner<f32>) -> Self {
+        let forward = planner.plan_fft_forward(2 * BLOCK);
+        let inverse = planner.plan_fft_inverse(2 * BLOCK);
+        let scratch_len = forward.get_scratch_len().max(inverse.get_scratch_len());
+
+        let partitions: Vec<Vec<Complex<f32>>> = impulse
+            .chunks(BLOCK)
+            .map(|piece| {
+                let mut time = vec![0.0; 2 * BLOCK];
+                time[..piece.len()].copy_from_slice(piece);
+                let mut spectrum = forward.make_output_vec();
+                forward.process(&mut time, &mut spectrum).expect("buffer sizes come from the plan");
+                spectrum
+            })
+            .collect();
+        let history = vec![forward.make_output_vec(); partitions.len()];
+
+        Partitioned {
+            sum: forward.make_output_vec(),
+            forward,
+            inverse,
+            partitions,
+            history,
+            head: 0,
+            next_partition: 1,
+            window: vec![0.0; 2 * BLOCK],
+            time: vec![0.0; 2 * BLOCK],
+            scratch: vec![Complex::default(); scratch_len],
+        }
+    }
+
+    /// Adds the products of the older blocks, up to partition `end`, into the
+    /// sum for the block being filled. They're all known before it starts.
+    fn accumulate(&mut self, end: usize) {
+        // The block being filled will be at `head + 1`, the one before against the second partition, ...
+        let count = self.history.len();
+        for i in self.next_partition..end.min(count) {
+            let spectrum = &self.history[(self.head + 1 + count - i) % count];
+            for ((sum, x), h) in self.sum.iter_mut().zip(spectrum).zip(&self.partitions[i]) {
+                *sum += x * h;
+            }
+        }
+        self.next_partition = self.next_partition.max(end.min(count));
+    }
+
+    /// Does its share of the older blocks' products once `filled` frames of
+    /// the block are in, so they're all done by the end of it.
+    fn spread(&mut self, filled: usize) {
+        let tail = self.partitions.len() - 1;
+        self.accumulate(1 + tail * filled / BLOCK);
+    }
+
+    /// Convolves the next `BLOCK` input samples, writing `BLOCK` output samples.
+    fn process(&mut self, input: &[f32], output: &mut [f32]) {
+        self.accumulate(self.partitions.len());
+
+        self.window.copy_within(BLOCK.., 0);
+        self.window[BLOCK..].copy_from_slice(input);
+
+        self.head = (self.head + 1) % self.history.len();
+        self.time.copy_from_slice(&self.window);
+        self.forward
+            .process_with_scratch(&mut self.time, &mut self.history[self.head], &mut self.scratch)
+            .expect("buffer sizes come from the plan");
+
+        // Newest input against the first partition
+        for ((sum, x), h) in self.sum.iter_mut().zip(&self.history[self.head]).zip(&self.partitions[0]) {
+            *sum += x * h;
+        }
+
+        // A real signal's DC and Nyquist bins are real; rounding mustn't say otherwise
+        let last = self.sum.len() - 1;
+        self.sum[0].im = 0.0;
+        self.sum[last].im = 0.0;
+        self.inverse
+            .process_with_scratch(&mut self.sum, &mut self.time, &mut self.scratch)
+            .expect("buffer sizes come from the plan");
+
+        // Overlap-save: the second half is the clean part
+        let scale = 1.0 / (2 * BLOCK) as f32;
+        for (out, value) in output.iter_mut().zip(&self.time[BLOCK..]) {
+            *out = value * scale;
+        }
+
+        // The inverse transform used the sum as scratch; start the next one afresh
+        self.sum.fill(Complex::default());
+        self.next_partition = 1;
+    }
+
+    fn reset(&mut self) {
+        for spectrum in &mut self.history {
+            spectrum.fill(Complex::default());
+        }
+        self.window.fill(0.0);
+        self.sum.fill(Complex::default());
+        self.next_partition = 1;
+    }
+}
+
+/// Stereo convolver that takes any number of frames at a time.
+pub struct Convolver {
+    channels: [Partitioned; 2],
+    input: [Vec<f32>; 2],
+    output: [Vec<f32>; 2],
+    position: usize,
+}
+
+impl Convolver {
+    pub fn new(impulse: &ImpulseResponse) -> Self {
+        let mut planner = RealFftPlanner::new();
+        Convolver {
+            channels: [
+                Partitioned::new(&impulse.channels[0], &mut planner),
+                Partitioned::new(&impulse.channels[1], &mut planner),
+            ],
+            input: [vec![0.0; BLOCK], vec![0.0; BLOCK]],
+            output: [vec![0.0; BLOCK], vec![0.0; BLOCK]],
+            position: 0,
+        }
+    }
+
+    /// Feeds one frame in and returns the wet frame from `BLOCK` frames ago.
+    fn next(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let i = self.position;
+        self.input[0][i] = left;
+        self.input[1][i] = right;
+        let wet = (self.output[0][i], self.output[1][i]);
+
+        self.position += 1;
+        for channel in &mut self.channels {
+            channel.spread(self.position);
+        }
+        if self.position == BLOCK {
+            self.position = 0;
+            for (c, channel) in self.channels.iter_mut().enumerate() {
+                channel.process(&self.input[c], &mut self.output[c]);
+            }
+        }
+        wet
+    }
+
+    fn reset(&mut self) {
+        self.channels.iter_mut().for_each(Partitioned::reset);
+        self.output.iter_mut().for_each(|o| o.fill(0.0));
+        self.position = 0;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConvolutionSettings {
+    pub enabled: bool,
+    /// 0.0 is all dry, 1.0 all wet. The dry level only starts dropping past halfway.
+    pub mix: f32,
+    pub pre_delay_ms: f32,
+}
+
+impl Default for ConvolutionSettings {
+    fn default() -> Self {
+        ConvolutionSettings {
+            enabled: false,
+            mix: 0.3,
+            pre_delay_ms: 20.0,
+        }
+    }
+}
+
+impl ConvolutionSettings {
+    fn gains(&self) -> (f32, f32) {
+        if !self.enabled {
+            return (1.0, 0.0);
+        }
+        let mix = self.mix.clamp(0.0, 1.0);
+        ((2.0 - 2.0 * mix).min(1.0), (2.0 * mix).min(1.0))
+    }
+}
+
+/// The convolution reverb as it sits in the render loop.
+pub struct ConvolutionStage {
+    convolver: Option<Box<Convolver>>,
+    settings: ConvolutionSettings,
+    sample_rate: u32,
+    // Current gains, fading toward the settings' at `fade_step` per frame
+    dry_gain: f32,
+    wet_gain: f32,
+    fade_step: f32,
+    // Pre-delay line for the convolver's input
+    delay: [Vec<f32>; 2],
+    delay_position: usize,
+}
+
+impl ConvolutionStage {
+    pub fn new(sample_rate: u32) -> Self {
+        let delay_len = (MAX_PRE_DELAY_MS / 1000.0 * sample_rate as f32) as usize + 1;
+        ConvolutionStage {
+            convolver: None,
+            settings: ConvolutionSettings::default(),
+            sample_rate,
+            dry_gain: 1.0,
+            wet_gain: 0.0,
+            fade_step: 1.0 / (FADE_SECONDS * sample_rate as f32),
+            delay: [vec![0.0; delay_len], vec![0.0; delay_len]],
+            delay_position: 0,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Swaps in a new convolver, returning the old one so it can be dropped
+    /// somewhere other than the audio thread.
+    pub fn set_convolver(&mut self, convolver: Option<Box<Convolver>>) -> Option<Box<Convolver>> {
+        std::mem::replace(&mut self.convolver, convolver)
+    }
+
+    pub fn has_impulse_response(&self) -> bool {
+        self.convolver.is_some()
+    }
+
+    pub fn set_settings(&mut self, settings: ConvolutionSettings) {
+        if settings.enabled && !self.settings.enabled {
+            // Don't replay whatever tail was left from the last time it was on
+            if let Some(convolver) = &mut self.convolver {
+                convolver.reset();
+            }
+            self.delay.iter_mut().for_each(|d| d.fill(0.0));
+        }
+        self.settings = settings;
+    }
+
+    pub fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let Some(convolver) = &mut self.convolver else {
+            return;
+        };
+        let (dry_target, wet_target) = self.settings.gains();
+        if wet_target == 0.0 && self.wet_gain == 0.0 {
+            return;
+        }
+
+        // The convolver's block of latency counts toward the pre-delay
+        let pre_delay = (self.settings.pre_delay_ms.clamp(0.0, MAX_PRE_DELAY_MS) / 1000.0 * self.sample_rate as f32)
+            as usize;
+        let delay = pre_delay.saturating_sub(BLOCK);
+        let delay_len = self.delay[0].len();
+
+        let fade = |gain: f32, target: f32, step: f32| {
+            if gain < target { (gain + step).min(target) } else { (gain - step).max(target) }
+        };
+
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            self.delay[0][self.delay_position] = *l;
+            self.delay[1][self.delay_position] = *r;
+            let read = (self.delay_position + delay_len - delay) % delay_len;
+            self.delay_position = (self.delay_position + 1) % delay_len;
+
+            let (wet_l, wet_r) = convolver.next(self.delay[0][read], self.delay[1][read]);
+            self.dry_gain = fade(self.dry_gain, dry_target, self.fade_step);
+            self.wet_gain = fade(self.wet_gain, wet_target, self.fade_step);
+            *l = *l * self.dry_gain + wet_l * self.wet_gain;
+            *r = *r * self.dry_gain + wet_r * self.wet_gain;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic noise, so the test needs no random number crate.
+    fn noise(len: usize, mut seed: u32) -> Vec<f32> {
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (seed >> 8) as f32 / (1 << 24) as f32 - 0.5
+            })
+            .collect()
+    }
+
+    #[test]
+    fn partitioned_matches_the_direct_sum() {
+        // Several partitions, the last one partly filled
+        let impulse = noise(3 * BLOCK + 300, 1);
+        let input = noise(6 * BLOCK, 2);
+        let mut convolver =
+            Convolver::new(&ImpulseResponse { channels: [impulse.clone(), impulse.iter().map(|s| -s).collect()] });
+
+        let wet: Vec<(f32, f32)> = input.iter().map(|&s| convolver.next(s, s)).collect();
+
+        for (n, &(left, right)) in wet.iter().enumerate().skip(BLOCK) {
+            // One block late
+            let t = n - BLOCK;
+            let expected: f32 = (0..=t.min(impulse.len() - 1)).map(|k| impulse[k] * input[t - k]).sum();
+            assert!((left - expected).abs() < 1.0e-3, "frame {}: {} vs {}", n, left, expected);
+            assert!((right + expected).abs() < 1.0e-3, "frame {}: {} vs {}", n, right, -expected);
+        }
+        assert!(wet[..BLOCK].iter().all(|&(l, r)| l == 0.0 && r == 0.0));
+    }
+}
@@ -0,0 +1,120 @@
+//! Stereo-linked feed-forward compressor with a soft knee, for evening out
+//! loud and quiet playing before the limiter has to step in.
+
+use super::{Effect, MasterEffects};
+
+/// Width of the soft knee around the threshold, in dB.
+const KNEE_DB: f32 = 6.0;
+
+pub const MIN_THRESHOLD_DB: f32 = -50.0;
+pub const MAX_RATIO: f32 = 20.0;
+pub const MAX_ATTACK_MS: f32 = 200.0;
+pub const MAX_RELEASE_MS: f32 = 2000.0;
+pub const MAX_MAKEUP_DB: f32 = 24.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompressorSettings {
+    pub bypass: bool,
+    pub threshold_db: f32,
+    /// Input dB over the threshold per output dB, 1.0 does nothing
+    pub ratio: f32,
+    pub attack_ms: f32,
+    pub release_ms: f32,
+    pub makeup_db: f32,
+}
+
+impl Default for CompressorSettings {
+    /// Gentle piano compression, bypassed until asked for.
+    fn default() -> Self {
+        CompressorSettings {
+            bypass: true,
+            threshold_db: -18.0,
+            ratio: 3.0,
+            attack_ms: 10.0,
+            release_ms: 200.0,
+            makeup_db: 0.0,
+        }
+    }
+}
+
+/// One-pole smoothing coefficient for a time constant in milliseconds.
+fn coefficient(ms: f32, sample_rate: u32) -> f32 {
+    (-1.0 / (ms.max(0.1) / 1000.0 * sample_rate as f32)).exp()
+}
+
+pub struct Compressor {
+    sample_rate: u32,
+    settings: CompressorSettings,
+    attack: f32,
+    release: f32,
+    makeup: f32,
+    /// Gain reduction being applied, in dB (0 or negative)
+    reduction_db: f32,
+}
+
+impl Compressor {
+    pub fn new(sample_rate: u32) -> Self {
+        let mut compressor = Compressor {
+            sample_rate,
+            settings: CompressorSettings::default(),
+            attack: 0.0,
+            release: 0.0,
+            makeup: 1.0,
+            reduction_db: 0.0,
+        };
+        compressor.set(CompressorSettings::default());
+        compressor
+    }
+
+    fn set(&mut self, settings: CompressorSettings) {
+        self.settings = settings;
+        self.attack = coefficient(settings.attack_ms, self.sample_rate);
+        self.release = coefficient(settings.release_ms, self.sample_rate);
+        self.makeup = 10f32.powf(settings.makeup_db / 20.0);
+    }
+
+    /// How far the static curve pulls a level of `level_db` down, in dB.
+    fn curve(&self, level_db: f32) -> f32 {
+        let slope = 1.0 / self.settings.ratio.max(1.0) - 1.0;
+        let over = level_db - self.settings.threshold_db;
+        if over <= -KNEE_DB / 2.0 {
+            0.0
+        } else if over < KNEE_DB / 2.0 {
+            let into_knee = over + KNEE_DB / 2.0;
+            slope * into_knee * into_knee / (2.0 * KNEE_DB)
+        } else {
+            slope * over
+        }
+    }
+}
+
+impl Effect for Compressor {
+    fn name(&self) -> &'static str {
+        "Compressor"
+    }
+
+    fn update(&mut self, settings: &MasterEffects) {
+        if settings.compressor != self.settings {
+            self.set(settings.compressor);
+        }
+    }
+
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            // Linked: both channels get the same gain, so the image doesn't shift
+            let level = l.abs().max(r.abs());
+            let level_db = if level > 1.0e-6 { 20.0 * level.log10() } else { -120.0 };
+            let wanted = self.curve(level_db);
+            let smoothing = if wanted < self.reduction_db { self.attack } else { self.release };
+            self.reduction_db = wanted + (self.reduction_db - wanted) * smoothing;
+
+            let gain = 10f32.powf(self.reduction_db / 20.0) * self.makeup;
+            *l *= gain;
+            *r *= gain;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.reduction_db = 0.0;
+    }
+}
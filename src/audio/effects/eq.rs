@@ -0,0 +1,194 @@
+//! Four-band parametric EQ: a low shelf, two peaking bands and a high shelf,
+//! each a biquad from the Audio EQ Cookbook.
+
+use super::{Effect, MasterEffects};
+use std::f32::consts::PI;
+use std::fmt;
+
+pub const MIN_FREQUENCY: f32 = 20.0;
+pub const MAX_FREQUENCY: f32 = 20000.0;
+pub const MAX_GAIN_DB: f32 = 15.0;
+pub const MIN_Q: f32 = 0.3;
+pub const MAX_Q: f32 = 8.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BandKind {
+    LowShelf,
+    Peak,
+    HighShelf,
+}
+
+impl fmt::Display for BandKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BandKind::LowShelf => "Low shelf",
+            BandKind::Peak => "Peak",
+            BandKind::HighShelf => "High shelf",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EqBand {
+    pub kind: BandKind,
+    /// Hz: the centre of a peak, the midpoint of a shelf
+    pub frequency: f32,
+    pub gain_db: f32,
+    /// Bandwidth of a peak, steepness of a shelf
+    pub q: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EqSettings {
+    pub bypass: bool,
+    pub bands: [EqBand; 4],
+}
+
+impl Default for EqSettings {
+    /// Flat, so turning it on changes nothing until a band is moved.
+    fn default() -> Self {
+        let band = |kind, frequency, q| EqBand { kind, frequency, gain_db: 0.0, q };
+        EqSettings {
+            bypass: true,
+            bands: [
+                band(BandKind::LowShelf, 120.0, 0.7),
+                band(BandKind::Peak, 500.0, 1.0),
+                band(BandKind::Peak, 2500.0, 1.0),
+                band(BandKind::HighShelf, 8000.0, 0.7),
+            ],
+        }
+    }
+}
+
+/// Normalized biquad coefficients (a0 = 1).
+#[derive(Debug, Clone, Copy, Default)]
+struct Coefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Coefficients {
+    fn new(band: &EqBand, sample_rate: u32) -> Self {
+        let frequency = band.frequency.clamp(MIN_FREQUENCY, 0.45 * sample_rate as f32);
+        let a = 10f32.powf(band.gain_db.clamp(-MAX_GAIN_DB, MAX_GAIN_DB) / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * band.q.clamp(MIN_Q, MAX_Q));
+        let shelf = 2.0 * a.sqrt() * alpha;
+
+        let (b0, b1, b2, a0, a1, a2) = match band.kind {
+            BandKind::Peak => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            BandKind::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos + shelf),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - shelf),
+                (a + 1.0) + (a - 1.0) * cos + shelf,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - shelf,
+            ),
+            BandKind::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos + shelf),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - shelf),
+                (a + 1.0) - (a - 1.0) * cos + shelf,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - shelf,
+            ),
+        };
+        Coefficients {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+}
+
+/// Transposed direct form II state for one channel of one band.
+#[derive(Debug, Clone, Copy, Default)]
+struct BiquadState {
+    z1: f32,
+    z2: f32,
+}
+
+impl BiquadState {
+    fn process(&mut self, c: &Coefficients, x: f32) -> f32 {
+        let y = c.b0 * x + self.z1;
+        self.z1 = c.b1 * x - c.a1 * y + self.z2;
+        self.z2 = c.b2 * x - c.a2 * y;
+        y
+    }
+}
+
+pub struct Equalizer {
+    sample_rate: u32,
+    bands: [EqBand; 4],
+    coefficients: [Coefficients; 4],
+    // Per band, left and right
+    state: [[BiquadState; 2]; 4],
+}
+
+impl Equalizer {
+    pub fn new(sample_rate: u32) -> Self {
+        let mut eq = Equalizer {
+            sample_rate,
+            bands: EqSettings::default().bands,
+            coefficients: [Coefficients::default(); 4],
+            state: [[BiquadState::default(); 2]; 4],
+        };
+        eq.set_bands(EqSettings::default().bands);
+        eq
+    }
+
+    fn set_bands(&mut self, bands: [EqBand; 4]) {
+        for (i, band) in bands.iter().enumerate() {
+            // A band that was skipped while flat starts over
+            if self.bands[i].gain_db == 0.0 && band.gain_db != 0.0 {
+                self.state[i] = [BiquadState::default(); 2];
+            }
+        }
+        self.bands = bands;
+        self.coefficients = bands.map(|band| Coefficients::new(&band, self.sample_rate));
+    }
+}
+
+impl Effect for Equalizer {
+    fn name(&self) -> &'static str {
+        "EQ"
+    }
+
+    fn update(&mut self, settings: &MasterEffects) {
+        if settings.eq.bands != self.bands {
+            self.set_bands(settings.eq.bands);
+        }
+    }
+
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        for (band, c) in self.coefficients.iter().enumerate() {
+            // A flat band is a no-op, skip it
+            if self.bands[band].gain_db == 0.0 {
+                continue;
+            }
+            let [state_left, state_right] = &mut self.state[band];
+            for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+                *l = state_left.process(c, *l);
+                *r = state_right.process(c, *r);
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = [[BiquadState::default(); 2]; 4];
+    }
+}
@@ -0,0 +1,126 @@
+//! The master effects chain: tone shaping and dynamics between the synth (and
+//! its room) and the master volume. Each effect implements `Effect`; the chain
+//! runs them in order, skipping the bypassed ones.
+//!
+//! To add an effect, implement `Effect` for it, give `MasterEffects` a field for
+//! its settings, and add it to the list in `EffectChain::new`.
+
+pub mod compressor;
+pub mod eq;
+
+use log::debug;
+use compressor::{Compressor, CompressorSettings};
+use eq::{EqSettings, Equalizer};
+
+/// Bypass changes crossfade over this long instead of clicking.
+const BYPASS_FADE_SECONDS: f32 = 0.01;
+
+pub trait Effect: Send {
+    fn name(&self) -> &'static str;
+
+    /// Picks this effect's parameters out of the chain settings. Called off
+    /// the audio thread's hot path, so it may recompute coefficients.
+    fn update(&mut self, settings: &MasterEffects);
+
+    /// Processes a block of stereo audio in place.
+    fn process(&mut self, left: &mut [f32], right: &mut [f32]);
+
+    /// Forgets filter and envelope state, so coming back from bypass doesn't
+    /// replay whatever was left from before.
+    fn reset(&mut self);
+}
+
+/// Settings of every effect in the chain, in chain order.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct MasterEffects {
+    pub eq: EqSettings,
+    pub compressor: CompressorSettings,
+}
+
+struct Slot {
+    effect: Box<dyn Effect>,
+    /// Where this effect's bypass switch lives in the settings
+    bypass_setting: fn(&MasterEffects) -> bool,
+    bypass: bool,
+    /// 1.0 when fully in the signal path, 0.0 when fully bypassed
+    presence: f32,
+}
+
+/// The effects in processing order, owned by the audio thread's renderer.
+pub struct EffectChain {
+    slots: Vec<Slot>,
+    fade_step: f32,
+    // Copy of each effect's input, for crossfading in and out of bypass
+    dry_left: Vec<f32>,
+    dry_right: Vec<f32>,
+}
+
+impl EffectChain {
+    pub fn new(sample_rate: u32, settings: &MasterEffects) -> Self {
+        let slot = |effect: Box<dyn Effect>, bypass_setting: fn(&MasterEffects) -> bool| Slot {
+            effect,
+            bypass_setting,
+            bypass: true,
+            presence: 0.0,
+        };
+        let mut chain = EffectChain {
+            slots: vec![
+                slot(Box::new(Equalizer::new(sample_rate)), |s| s.eq.bypass),
+                slot(Box::new(Compressor::new(sample_rate)), |s| s.compressor.bypass),
+            ],
+            fade_step: 1.0 / (BYPASS_FADE_SECONDS * sample_rate as f32),
+            dry_left: Vec::new(),
+            dry_right: Vec::new(),
+        };
+        chain.update(settings);
+        // Start out where the settings say rather than fading in
+        for slot in &mut chain.slots {
+            slot.presence = if slot.bypass { 0.0 } else { 1.0 };
+        }
+        chain
+    }
+
+    pub fn update(&mut self, settings: &MasterEffects) {
+        for slot in &mut self.slots {
+            slot.effect.update(settings);
+            let bypass = (slot.bypass_setting)(settings);
+            if bypass != slot.bypass {
+                debug!("{} {}", slot.effect.name(), if bypass { "bypassed" } else { "in" });
+                if !bypass && slot.presence == 0.0 {
+                    slot.effect.reset();
+                }
+            }
+            slot.bypass = bypass;
+        }
+    }
+
+    pub fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        if self.dry_left.len() < left.len() {
+            self.dry_left.resize(left.len(), 0.0);
+            self.dry_right.resize(left.len(), 0.0);
+        }
+
+        for slot in &mut self.slots {
+            let target = if slot.bypass { 0.0 } else { 1.0 };
+            if slot.presence == target {
+                if !slot.bypass {
+                    slot.effect.process(left, right);
+                }
+                continue;
+            }
+
+            // Switching: run the effect and crossfade with its input
+            let dry_left = &mut self.dry_left[..left.len()];
+            let dry_right = &mut self.dry_right[..right.len()];
+            dry_left.copy_from_slice(left);
+            dry_right.copy_from_slice(right);
+            slot.effect.process(left, right);
+            let step = if slot.bypass { -self.fade_step } else { self.fade_step };
+            for (((l, r), dl), dr) in left.iter_mut().zip(right.iter_mut()).zip(dry_left.iter()).zip(dry_right.iter()) {
+                slot.presence = (slot.presence + step).clamp(0.0, 1.0);
+                *l = dl + (*l - dl) * slot.presence;
+                *r = dr + (*r - dr) * slot.presence;
+            }
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! The little phrase played at startup, so you know the sound works. It can
+//! be turned off, or swapped for a short MIDI file of your own. Either way it
+//! goes through the sequencer and the normal MIDI path, so it plays with the
+//! chosen sound, tuning and volume.
+
+use crate::midi::file::{MidiFile, TimedMessage};
+use anyhow::{bail, Result};
+use std::path::PathBuf;
+
+/// Longest MIDI file accepted as a jingle; it's meant to be a few notes.
+pub const MAX_JINGLE_SECONDS: f64 = 10.0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StartupJingle {
+    pub enabled: bool,
+    /// MIDI file played instead of the built-in phrase
+    pub file: Option<PathBuf>,
+}
+
+impl Default for StartupJingle {
+    fn default() -> Self {
+        StartupJingle { enabled: true, file: None }
+    }
+}
+
+impl StartupJingle {
+    /// What to play: nothing if it's off, else the file's notes or the built-in phrase.
+    pub fn messages(&self) -> Result<Vec<TimedMessage>> {
+        if !self.enabled {
+            return Ok(Vec::new());
+        }
+        let Some(path) = &self.file else {
+            return Ok(built_in());
+        };
+        let file = MidiFile::load(path)?;
+        if file.duration() > MAX_JINGLE_SECONDS {
+            bail!(
+                "{:?} is {:.0} seconds long, a startup jingle can be at most {:.0}",
+                path,
+                file.duration(),
+                MAX_JINGLE_SECONDS
+            );
+        }
+        // Just the notes: the pedal would hold whatever the player starts with
+        let notes = file.messages().iter().filter(|m| matches!(m.message[0] & 0xF0, 0x80 | 0x90));
+        Ok(notes.copied().collect())
+    }
+}
+
+/// A little "question-answer" motif: G4-B4-D5-G5 (leap up) -> F5-E5-D5 (step
+/// down to resolve), louder towards the peak.
+fn built_in() -> Vec<TimedMessage> {
+    const NOTE_SECONDS: f64 = 0.1;
+    let notes_and_velocities = [(67, 70), (71, 80), (74, 90), (79, 100), (77, 85), (76, 75), (74, 65)];
+    notes_and_velocities
+        .into_iter()
+        .enumerate()
+        .flat_map(|(i, (note, velocity))| {
+            let start = i as f64 * NOTE_SECONDS;
+            [
+                TimedMessage { time: start, message: [0x90, note, velocity] },
+                TimedMessage { time: start + NOTE_SECONDS, message: [0x80, note, 0] },
+            ]
+        })
+        .collect()
+}
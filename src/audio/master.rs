@@ -0,0 +1,117 @@
+//! The last stage before the sound card: master volume, then a limiter that
+//! keeps a full-keyboard chord from clipping the output.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// Seconds for a volume change to mostly take effect, so moving the slider doesn't click.
+const VOLUME_SMOOTHING: f64 = 0.02;
+
+/// The limiter holds peaks to this level (-0.5 dBFS)...
+const LIMIT_DB: f32 = -0.5;
+/// ...and starts easing in this many dB below it.
+const KNEE_DB: f32 = 6.0;
+/// Seconds to recover after a peak.
+const LIMITER_RELEASE: f64 = 0.15;
+
+/// Master volume, shared between the UI and the audio thread without a lock.
+/// 1.0 is unity gain.
+#[derive(Debug)]
+pub struct MasterVolume(AtomicU32);
+
+impl MasterVolume {
+    pub fn new(volume: f32) -> Self {
+        MasterVolume(AtomicU32::new(volume.to_bits()))
+    }
+
+    pub fn set(&self, volume: f32) {
+        self.0.store(volume.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+impl Default for MasterVolume {
+    fn default() -> Self {
+        MasterVolume::new(1.0)
+    }
+}
+
+/// One-pole smoothing coefficient for a time constant in seconds.
+fn coefficient(seconds: f64, sample_rate: u32) -> f32 {
+    (-1.0 / (seconds * sample_rate as f64)).exp() as f32
+}
+
+/// Gain stage and limiter state, owned by the audio thread.
+pub struct MasterStage {
+    volume: Arc<MasterVolume>,
+    gain: f32,
+    gain_smoothing: f32,
+    // Gain the limiter is currently applying, 1.0 when idle
+    reduction: f32,
+    release: f32,
+    knee_start: f32,
+}
+
+impl MasterStage {
+    pub fn new(volume: Arc<MasterVolume>, sample_rate: u32) -> Self {
+        MasterStage {
+            gain: volume.get(),
+            volume,
+            gain_smoothing: coefficient(VOLUME_SMOOTHING, sample_rate),
+            reduction: 1.0,
+            release: coefficient(LIMITER_RELEASE, sample_rate),
+            knee_start: db_to_gain(LIMIT_DB - KNEE_DB / 2.0),
+        }
+    }
+
+    /// Applies volume and limiting in place. Returns, per channel, whether the
+    /// signal went over full scale before limiting, i.e. would have clipped.
+    pub fn process(&mut self, left: &mut [f32], right: &mut [f32]) -> [bool; 2] {
+        let target = self.volume.get();
+        let mut clipped = [false; 2];
+
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            self.gain = target + (self.gain - target) * self.gain_smoothing;
+            *l *= self.gain;
+            *r *= self.gain;
+            clipped[0] |= l.abs() > 1.0;
+            clipped[1] |= r.abs() > 1.0;
+
+            // Instant attack so nothing gets through, smooth release so it doesn't pump
+            let wanted = limiter_gain(l.abs().max(r.abs()), self.knee_start);
+            self.reduction = if wanted < self.reduction {
+                wanted
+            } else {
+                wanted + (self.reduction - wanted) * self.release
+            };
+
+            // Safety net only, the limiter already keeps peaks below full scale
+            *l = (*l * self.reduction).clamp(-1.0, 1.0);
+            *r = (*r * self.reduction).clamp(-1.0, 1.0);
+        }
+        clipped
+    }
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Gain that brings a peak of `level` down onto the soft-knee limiting curve.
+fn limiter_gain(level: f32, knee_start: f32) -> f32 {
+    if level <= knee_start {
+        return 1.0;
+    }
+    let level_db = 20.0 * level.log10();
+    let over = level_db - (LIMIT_DB - KNEE_DB / 2.0);
+    let out_db = if over < KNEE_DB {
+        // Quadratic knee: slope eases from 1:1 down to flat
+        level_db - over * over / (2.0 * KNEE_DB)
+    } else {
+        LIMIT_DB
+    };
+    db_to_gain(out_db - level_db)
+}
@@ -0,0 +1,59 @@
+//! Output level metering. The audio thread measures every block it renders and
+//! publishes the result through atomics, so reading the meter never blocks audio.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Levels of one output channel, linear (1.0 is full scale).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChannelLevel {
+    pub peak: f32,
+    pub rms: f32,
+    /// The signal went over full scale before the limiter caught it
+    pub clipped: bool,
+}
+
+#[derive(Debug, Default)]
+struct ChannelMeter {
+    // f32 bits. Levels are never negative, and for those the bit patterns
+    // sort like the floats, so `fetch_max` keeps the highest peak.
+    peak: AtomicU32,
+    rms: AtomicU32,
+    clipped: AtomicBool,
+}
+
+/// Left and right output levels, written by the audio thread.
+#[derive(Debug, Default)]
+pub struct LevelMeter {
+    channels: [ChannelMeter; 2],
+}
+
+impl LevelMeter {
+    /// Measures one rendered block. `clipped` comes from the master stage.
+    pub(super) fn measure(&self, left: &[f32], right: &[f32], clipped: [bool; 2]) {
+        for ((meter, samples), clipped) in self.channels.iter().zip([left, right]).zip(clipped) {
+            let mut peak = 0.0f32;
+            let mut sum = 0.0f32;
+            for s in samples {
+                peak = peak.max(s.abs());
+                sum += s * s;
+            }
+            let rms = if samples.is_empty() { 0.0 } else { (sum / samples.len() as f32).sqrt() };
+
+            meter.peak.fetch_max(peak.to_bits(), Ordering::Relaxed);
+            meter.rms.store(rms.to_bits(), Ordering::Relaxed);
+            if clipped {
+                meter.clipped.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Levels since the last call: the highest peak, the latest block's RMS,
+    /// and whether anything clipped in between. Meant for a single reader.
+    pub fn take(&self) -> [ChannelLevel; 2] {
+        self.channels.each_ref().map(|meter| ChannelLevel {
+            peak: f32::from_bits(meter.peak.swap(0, Ordering::Relaxed)),
+            rms: f32::from_bits(meter.rms.load(Ordering::Relaxed)),
+            clipped: meter.clipped.swap(false, Ordering::Relaxed),
+        })
+    }
+}
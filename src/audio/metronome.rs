@@ -0,0 +1,218 @@
+//! A metronome mixed in on the audio thread. Clicks are placed by counting
+//! rendered frames, so they land on the exact sample whatever the buffer
+//! size, and stay clear of the reverb and EQ.
+
+use std::f32::consts::TAU;
+use std::fmt;
+
+pub const MIN_BPM: f64 = 20.0;
+pub const MAX_BPM: f64 = 300.0;
+pub const MAX_BEATS_PER_BAR: u8 = 12;
+/// Note values a time signature's beat can be.
+pub const BEAT_UNITS: [u8; 3] = [2, 4, 8];
+
+/// Seconds a click lasts.
+const CLICK_SECONDS: f32 = 0.03;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Subdivision {
+    #[default]
+    None,
+    Eighths,
+    Triplets,
+    Sixteenths,
+}
+
+impl Subdivision {
+    pub const ALL: [Subdivision; 4] = [
+        Subdivision::None,
+        Subdivision::Eighths,
+        Subdivision::Triplets,
+        Subdivision::Sixteenths,
+    ];
+
+    /// Clicks per beat.
+    pub fn clicks(self) -> u32 {
+        match self {
+            Subdivision::None => 1,
+            Subdivision::Eighths => 2,
+            Subdivision::Triplets => 3,
+            Subdivision::Sixteenths => 4,
+        }
+    }
+}
+
+impl fmt::Display for Subdivision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Subdivision::None => "Beats only",
+            Subdivision::Eighths => "Eighths",
+            Subdivision::Triplets => "Triplets",
+            Subdivision::Sixteenths => "Sixteenths",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MetronomeSettings {
+    pub enabled: bool,
+    /// Beats per minute, a beat being the time signature's note value
+    pub bpm: f64,
+    pub beats_per_bar: u8,
+    pub beat_unit: u8,
+    /// Click the first beat of each bar higher
+    pub accent: bool,
+    pub subdivision: Subdivision,
+    /// 1.0 is full scale
+    pub volume: f32,
+}
+
+impl Default for MetronomeSettings {
+    fn default() -> Self {
+        MetronomeSettings {
+            enabled: false,
+            bpm: 100.0,
+            beats_per_bar: 4,
+            beat_unit: 4,
+            accent: true,
+            subdivision: Subdivision::None,
+            volume: 0.5,
+        }
+    }
+}
+
+/// The three sounds: the bar's first beat, other beats, and the clicks between beats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Click {
+    Accent,
+    Beat,
+    Subdivision,
+}
+
+impl Click {
+    fn frequency(self) -> f32 {
+        match self {
+            Click::Accent => 1760.0,
+            Click::Beat => 1320.0,
+            Click::Subdivision => 880.0,
+        }
+    }
+
+    fn level(self) -> f32 {
+        match self {
+            Click::Accent => 1.0,
+            Click::Beat => 0.7,
+            Click::Subdivision => 0.35,
+        }
+    }
+}
+
+/// Metronome state, owned by the audio thread behind a lock like the effects.
+pub struct Metronome {
+    settings: MetronomeSettings,
+    sample_rate: u32,
+    /// Frames until the next click, fractional so long runs don't drift
+    until_next: f64,
+    /// Clicks since the start of the bar
+    count: u32,
+    /// The click sounding and how many frames of it have played
+    playing: Option<(Click, usize)>,
+    /// One click's sine-with-decay, scaled by each click's level
+    wave: [Vec<f32>; 3],
+}
+
+impl Metronome {
+    pub fn new(sample_rate: u32) -> Self {
+        let wave = [Click::Accent, Click::Beat, Click::Subdivision].map(|click| {
+            let frames = (CLICK_SECONDS * sample_rate as f32) as usize;
+            (0..frames)
+                .map(|i| {
+                    let t = i as f32 / sample_rate as f32;
+                    // Quick fade in so it doesn't pop, then an exponential decay
+                    let envelope = (t / 0.001).min(1.0) * (-t / (CLICK_SECONDS / 5.0)).exp();
+                    (TAU * click.frequency() * t).sin() * envelope * click.level()
+                })
+                .collect()
+        });
+        Metronome {
+            settings: MetronomeSettings::default(),
+            sample_rate,
+            until_next: 0.0,
+            count: 0,
+            playing: None,
+            wave,
+        }
+    }
+
+    /// Turning it on starts a bar right away. Tempo changes take effect from
+    /// the next click, measured from the last one.
+    pub fn set_settings(&mut self, settings: &MetronomeSettings) {
+        let settings = MetronomeSettings {
+            bpm: settings.bpm.clamp(MIN_BPM, MAX_BPM),
+            beats_per_bar: settings.beats_per_bar.clamp(1, MAX_BEATS_PER_BAR),
+            volume: settings.volume.clamp(0.0, 1.0),
+            ..*settings
+        };
+        let starting = settings.enabled && !self.settings.enabled;
+        // How far into the current click's interval we are, and which beat
+        let elapsed = self.interval() - self.until_next;
+        let beat = self.count / self.settings.subdivision.clicks();
+        self.settings = settings;
+        if starting {
+            self.until_next = 0.0;
+            self.count = 0;
+        } else {
+            self.until_next = (self.interval() - elapsed).max(0.0);
+            self.count = beat * settings.subdivision.clicks() % self.clicks_per_bar();
+        }
+    }
+
+    pub fn settings(&self) -> &MetronomeSettings {
+        &self.settings
+    }
+
+    fn clicks_per_bar(&self) -> u32 {
+        self.settings.beats_per_bar as u32 * self.settings.subdivision.clicks()
+    }
+
+    /// Frames between clicks.
+    fn interval(&self) -> f64 {
+        60.0 / self.settings.bpm / self.settings.subdivision.clicks() as f64 * self.sample_rate as f64
+    }
+
+    /// Adds the clicks falling in this block to it.
+    pub fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        if !self.settings.enabled && self.playing.is_none() {
+            return;
+        }
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            if self.settings.enabled {
+                if self.until_next < 1.0 {
+                    self.playing = Some((self.next_click(), 0));
+                    self.until_next += self.interval();
+                }
+                self.until_next -= 1.0;
+            }
+            if let Some((click, frame)) = &mut self.playing {
+                let sample = self.wave[*click as usize][*frame] * self.settings.volume;
+                *l += sample;
+                *r += sample;
+                *frame += 1;
+                if *frame == self.wave[*click as usize].len() {
+                    self.playing = None;
+                }
+            }
+        }
+    }
+
+    fn next_click(&mut self) -> Click {
+        let count = self.count;
+        self.count = (self.count + 1) % self.clicks_per_bar();
+        let clicks = self.settings.subdivision.clicks();
+        match count {
+            0 if self.settings.accent => Click::Accent,
+            _ if count.is_multiple_of(clicks) => Click::Beat,
+            _ => Click::Subdivision,
+        }
+    }
+}
@@ -1,43 +1,89 @@
+pub mod convolution;
+pub mod effects;
+pub mod jingle;
+pub mod master;
+pub mod meter;
+pub mod metronome;
+pub mod null;
+pub mod offline;
+pub mod sequencer;
+pub mod synth_effects;
+pub mod wav;
+
+use crate::midi::file::MidiFile;
+use crate::midi::scala::{KeyboardMapping, ScalaFiles, Scale};
+use crate::midi::tap_tempo::{TapTrigger, TappedTempo};
+use crate::midi::tuning::{Tuning, TuningSettings};
+use crate::midi::velocity::VelocityCurve;
+use crate::midi::zones::ZoneSettings;
+use crate::midi::{handle_midi_message, MidiTarget};
 use anyhow::{Context, Result};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use log::{error, info};
+use log::{error, info, warn};
 use rustysynth::{SoundFont, Synthesizer, SynthesizerSettings};
 use std::fs::File;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
+use convolution::{ConvolutionSettings, ConvolutionStage, Convolver, ImpulseResponse};
+use effects::{EffectChain, MasterEffects};
+use jingle::StartupJingle;
+use master::{MasterStage, MasterVolume};
+use meter::{ChannelLevel, LevelMeter};
+use metronome::{Metronome, MetronomeSettings};
+use null::{NullDriver, NullOutput};
+use sequencer::Sequencer;
+use synth_effects::SynthEffects;
+
+/// Silence before the first note of a MIDI file, so the piano roll can show it coming.
+const PLAYBACK_LEAD_IN: f64 = 2.0;
+
 pub struct AudioEngine {
-    _stream: cpal::Stream,
+    output: Output,
     synthesizer: Arc<Mutex<Synthesizer>>,
+    volume: Arc<MasterVolume>,
+    meter: Arc<LevelMeter>,
+    stages: Stages,
+    midi_target: MidiTarget,
+    sequencer: Arc<Mutex<Sequencer>>,
+}
+
+/// Where the rendered audio goes.
+enum Output {
+    // Only held so the stream keeps playing
+    Device(#[allow(dead_code)] cpal::Stream),
+    Null(NullDriver),
+}
+
+/// Which sound to make and where to send it. `None` picks the defaults.
+#[derive(Debug, Clone, Default)]
+pub struct AudioConfig {
+    pub soundfont: Option<PathBuf>,
+    /// Output device name, as listed by cpal
+    pub device: Option<String>,
 }
 
 impl AudioEngine {
-    pub fn init() -> Result<Self> {
+    pub fn init(audio_config: &AudioConfig) -> Result<Self> {
         info!("Initializing Audio Engine...");
 
         // 1. Load SoundFont
-        let sf2_filename = "SalamanderGrandPiano-V3+20200602.sf2";
-        
-        let sf2_path = {
-             let std_path = std::path::Path::new("assets").join(sf2_filename);
-             if std_path.exists() {
-                 std_path
-             } else if let Ok(exe_path) = std::env::current_exe() {
-                 exe_path.parent().unwrap().join("assets").join(sf2_filename)
-             } else {
-                 std_path // Fallback
-             }
-        };
-
-        info!("Loading SoundFont from: {:?}", sf2_path);
-        let mut sf2_file = File::open(&sf2_path)
-            .with_context(|| format!("Failed to open SoundFont at {:?}", sf2_path))?;
-        let sound_font = Arc::new(SoundFont::new(&mut sf2_file).context("Failed to parse SoundFont")?);
+        let sound_font = load_soundfont(audio_config.soundfont.as_deref())?;
 
         // 2. Setup CPAL
         let host = cpal::default_host();
-        let device = host
-            .default_output_device()
-            .context("No output audio device found")?;
+        let device = match &audio_config.device {
+            Some(name) => host
+                .output_devices()
+                .ok()
+                .and_then(|mut devices| devices.find(|d| d.name().ok().as_deref() == Some(name.as_str())))
+                .or_else(|| {
+                    warn!("Audio device {:?} not found, using the default one.", name);
+                    host.default_output_device()
+                }),
+            None => host.default_output_device(),
+        }
+        .context("No output audio device found")?;
         info!("Using audio device: {}", device.name().unwrap_or_default());
 
         let config = device.default_output_config().context("Failed to get default output config")?;
@@ -47,18 +93,27 @@ impl AudioEngine {
         info!("Audio Config: Sample Rate: {}, Channels: {}", sample_rate, channels);
 
         // 3. Initialize Synthesizer
-        let settings = SynthesizerSettings::new(sample_rate);
-        let synthesizer = Arc::new(Mutex::new(Synthesizer::new(&sound_font, &settings).context("Failed to create Synthesizer")?));
+        let (synthesizer, midi_target, sequencer) = create_synthesizer(&sound_font, sample_rate)?;
 
         // 4. Create Audio Stream
-        let synth_clone = synthesizer.clone();
+        let volume = Arc::new(MasterVolume::default());
+        let meter = Arc::new(LevelMeter::default());
+        let stages = Stages::new(sample_rate as u32);
+        let mut renderer = Renderer::new(
+            midi_target.clone(),
+            sequencer.clone(),
+            volume.clone(),
+            meter.clone(),
+            stages.clone(),
+            sample_rate as u32,
+        );
         let err_fn = |err| error!("an error occurred on stream: {}", err);
 
         let stream = match config.sample_format() {
             cpal::SampleFormat::F32 => device.build_output_stream(
                 &config.into(),
                 move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                    render_audio(data, channels, &synth_clone);
+                    render_audio(data, channels, &mut renderer);
                 },
                 err_fn,
                 None,
@@ -68,59 +123,385 @@ impl AudioEngine {
 
         stream.play().context("Failed to start audio stream")?;
 
-        // Startup Jingle: Playful melodic phrase with dynamics
-        let jingle_synth = synthesizer.clone();
-        std::thread::spawn(move || {
-            // Notes: A little "question-answer" motif
-            // G-A-B-D (up) -> C-B-A-G (resolve down) but only 7 notes total
-            // G4-B4-D5-G5 (leap up) -> F5-E5-D5 (step down to resolve)
-            let notes_and_velocities = [
-                (67, 70),   // G4 - soft start
-                (71, 80),   // B4 - building
-                (74, 90),   // D5 - peak approach
-                (79, 100),  // G5 - peak! (loudest)
-                (77, 85),   // F5 - start descent
-                (76, 75),   // E5 - softer
-                (74, 65),   // D5 - gentle landing
-            ];
-            let note_duration = std::time::Duration::from_millis(100);
-
-            for (note, velocity) in notes_and_velocities {
-                if let Ok(mut synth) = jingle_synth.lock() {
-                    synth.note_on(0, note, velocity);
-                }
-                std::thread::sleep(note_duration);
-                if let Ok(mut synth) = jingle_synth.lock() {
-                    synth.note_off(0, note);
-                }
-            }
-            info!("Startup jingle played!");
-        });
+        Ok(AudioEngine {
+            output: Output::Device(stream),
+            synthesizer,
+            volume,
+            meter,
+            stages,
+            midi_target,
+            sequencer,
+        })
+    }
+
+    /// An engine with no sound card behind it: the same synthesizer and render
+    /// loop, writing to `output.sink`.
+    pub fn init_null(audio_config: &AudioConfig, output: NullOutput) -> Result<Self> {
+        info!("Initializing Audio Engine without an audio device...");
+
+        let sound_font = load_soundfont(audio_config.soundfont.as_deref())?;
+        let (synthesizer, midi_target, sequencer) = create_synthesizer(&sound_font, output.sample_rate as i32)?;
+        let volume = Arc::new(MasterVolume::default());
+        let meter = Arc::new(LevelMeter::default());
+        let stages = Stages::new(output.sample_rate);
+        let renderer = Renderer::new(
+            midi_target.clone(),
+            sequencer.clone(),
+            volume.clone(),
+            meter.clone(),
+            stages.clone(),
+            output.sample_rate,
+        );
+        let driver = NullDriver::start(renderer, output)?;
 
         Ok(AudioEngine {
-            _stream: stream,
+            output: Output::Null(driver),
             synthesizer,
+            volume,
+            meter,
+            stages,
+            midi_target,
+            sequencer,
         })
     }
 
+    /// Renders `frames` frames now and returns them as interleaved stereo.
+    /// Only engines from `init_null` can do this; a sound card keeps its own time.
+    pub fn render(&self, frames: usize) -> Result<Vec<f32>> {
+        match &self.output {
+            Output::Null(driver) => driver.render(frames),
+            Output::Device(_) => anyhow::bail!("The audio device drives rendering, it can't be done on demand"),
+        }
+    }
+
     pub fn get_synthesizer(&self) -> Arc<Mutex<Synthesizer>> {
         self.synthesizer.clone()
     }
+
+    /// Sets the master volume, 1.0 being unity gain. The audio thread glides
+    /// to the new level rather than jumping.
+    pub fn set_volume(&self, volume: f32) {
+        self.volume.set(volume);
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume.get()
+    }
+
+    /// Left and right output levels since the last call, for drawing meters.
+    pub fn levels(&self) -> [ChannelLevel; 2] {
+        self.meter.take()
+    }
+
+    /// Bank, program and name of every preset in the loaded SoundFont, sorted.
+    pub fn presets(&self) -> Vec<(u8, u8, String)> {
+        let synth = self.synthesizer.lock().unwrap();
+        let mut presets: Vec<(u8, u8, String)> = synth
+            .get_sound_font()
+            .get_presets()
+            .iter()
+            .filter(|p| p.get_bank_number() < 128) // drum kits can't be selected on a melodic channel
+            .map(|p| (p.get_bank_number() as u8, p.get_patch_number() as u8, p.get_name().to_string()))
+            .collect();
+        presets.sort();
+        presets
+    }
+
+    /// Switches the sound played on the keyboard, through the normal MIDI path.
+    pub fn select_preset(&self, bank: u8, program: u8) {
+        handle_midi_message(&[0xB0, 0x00, bank & 0x7F], &self.midi_target);
+        handle_midi_message(&[0xC0, program & 0x7F], &self.midi_target);
+    }
+
+    /// Moves everything played by `semitones` (up to ±24), from the next note on.
+    /// Notes already down are released as what they were played as.
+    pub fn set_transpose(&self, semitones: i32) {
+        self.midi_target.transpose.set_semitones(semitones);
+    }
+
+    /// Moves everything played by whole octaves, on top of the transpose.
+    pub fn set_octave_shift(&self, octaves: i32) {
+        self.midi_target.transpose.set_octaves(octaves);
+    }
+
+    /// Sets up split or layer mode: where notes go, and the second zone's sound.
+    pub fn set_zones(&self, zones: &ZoneSettings) {
+        self.midi_target.zones.set(zones);
+        let mut synth = self.synthesizer.lock().unwrap();
+        for (channel, [status, data1, data2]) in zones.messages() {
+            synth.process_midi_message(channel, status as i32, data1 as i32, data2 as i32);
+        }
+    }
+
+    /// Starts, stops or changes the metronome.
+    pub fn set_metronome(&self, settings: &MetronomeSettings) {
+        self.stages.metronome.lock().unwrap().set_settings(settings);
+    }
+
+    /// Sets the reference pitch and temperament, from the next note played.
+    pub fn set_tuning(&self, tuning: &TuningSettings) {
+        self.midi_target.zones.set_tuning(Tuning::new(tuning));
+    }
+
+    /// Retunes to a Scala scale, from the next note played. Without a keyboard
+    /// mapping the scale starts on middle C, at its pitch for an A4 of
+    /// `reference`. The old tuning stays if the files can't be loaded.
+    pub fn load_scala(&self, files: &ScalaFiles, reference: f64) -> Result<Scale> {
+        let scale = Scale::load(&files.scale)?;
+        let mapping = match &files.mapping {
+            Some(path) => KeyboardMapping::load(path)?,
+            None => KeyboardMapping::linear(reference),
+        };
+        let tuning = Tuning::from_scala(&scale, &mapping)
+            .with_context(|| format!("Failed to map {:?} to the keyboard", files.scale))?;
+        info!("Loaded scale {:?}: {} notes", scale.description, scale.len());
+        self.midi_target.zones.set_tuning(tuning);
+        Ok(scale)
+    }
+
+    /// Sets the velocity curve applied to notes from the MIDI input device.
+    pub fn set_input_velocity_curve(&self, curve: &VelocityCurve) {
+        self.midi_target.input_velocity.set(curve);
+    }
+
+    /// Applies reverb and chorus settings to the live channel, through the normal MIDI path.
+    pub fn set_synth_effects(&self, effects: &SynthEffects) {
+        for message in effects.messages() {
+            handle_midi_message(&message, &self.midi_target);
+        }
+    }
+
+    /// Loads a WAV impulse response for the convolution reverb, replacing the
+    /// current one. The old one keeps playing if this fails.
+    pub fn load_impulse_response(&self, path: &Path) -> Result<()> {
+        let sample_rate = self.stages.convolution.lock().unwrap().sample_rate();
+        let impulse = ImpulseResponse::load(path, sample_rate)
+            .with_context(|| format!("Failed to load impulse response {:?}", path))?;
+        info!("Loaded impulse response {:?}: {} frames", path, impulse.len());
+        // Build it here, not on the audio thread, and drop the old one here too
+        let convolver = Box::new(Convolver::new(&impulse));
+        let old = self.stages.convolution.lock().unwrap().set_convolver(Some(convolver));
+        drop(old);
+        Ok(())
+    }
+
+    pub fn clear_impulse_response(&self) {
+        let old = self.stages.convolution.lock().unwrap().set_convolver(None);
+        drop(old);
+    }
+
+    pub fn has_impulse_response(&self) -> bool {
+        self.stages.convolution.lock().unwrap().has_impulse_response()
+    }
+
+    /// Turns the convolution reverb on or off and sets its mix and pre-delay.
+    pub fn set_convolution(&self, settings: ConvolutionSettings) {
+        self.stages.convolution.lock().unwrap().set_settings(settings);
+    }
+
+    /// Applies EQ, compressor and bypass settings to the master effects chain.
+    pub fn set_master_effects(&self, settings: &MasterEffects) {
+        self.stages.effects.lock().unwrap().update(settings);
+    }
+
+    /// Plays a MIDI file through the normal MIDI path, replacing any file already playing.
+    pub fn play_midi_file(&self, file: &MidiFile) {
+        let release = self.sequencer.lock().unwrap().play_song(file, PLAYBACK_LEAD_IN);
+        for message in release {
+            handle_midi_message(&message, &self.midi_target);
+        }
+    }
+
+    /// Plays the startup jingle through the sequencer, unless it's turned off.
+    /// A jingle file that can't be played is an error, and nothing plays.
+    pub fn play_jingle(&self, jingle: &StartupJingle) -> Result<()> {
+        let messages = jingle.messages()?;
+        self.sequencer.lock().unwrap().play_messages(&messages);
+        Ok(())
+    }
+
+    pub fn stop_playback(&self) {
+        let release = self.sequencer.lock().unwrap().stop_song();
+        for message in release {
+            handle_midi_message(&message, &self.midi_target);
+        }
+    }
+
+    /// Plays MIDI files at `tempo` quarter notes per minute, or as written for
+    /// `None`. Tapping a tempo sets this too.
+    pub fn set_song_tempo(&self, tempo: Option<f64>) {
+        self.sequencer.lock().unwrap().set_tempo(tempo);
+    }
+
+    pub fn song_tempo(&self) -> Option<f64> {
+        self.sequencer.lock().unwrap().tempo()
+    }
+
+    /// Counts a tap of the tempo. Two or more set the metronome and song tempo.
+    pub fn tap_tempo(&self) {
+        self.midi_target.tap_tempo.tap();
+    }
+
+    /// The last tempo tapped, on screen or on the input device.
+    pub fn tapped_tempo(&self) -> Option<TappedTempo> {
+        self.midi_target.tap_tempo.latest()
+    }
+
+    /// Sets the note or pedal on the input device that taps the tempo.
+    pub fn set_tap_trigger(&self, trigger: Option<TapTrigger>) {
+        self.midi_target.tap_tempo.set_trigger(trigger);
+    }
+
+    /// Seconds into the MIDI file being played, negative during the lead-in.
+    pub fn playback_position(&self) -> Option<f64> {
+        self.sequencer.lock().unwrap().song_position()
+    }
+
+    /// Where MIDI input should be sent so it both plays and shows up on screen.
+    pub fn midi_target(&self) -> MidiTarget {
+        self.midi_target.clone()
+    }
+}
+
+/// The pieces every engine is made of: the synthesizer, the MIDI path into it, and the sequencer.
+type EngineParts = (Arc<Mutex<Synthesizer>>, MidiTarget, Arc<Mutex<Sequencer>>);
+
+fn create_synthesizer(sound_font: &Arc<SoundFont>, sample_rate: i32) -> Result<EngineParts> {
+    let settings = SynthesizerSettings::new(sample_rate);
+    let synthesizer = Arc::new(Mutex::new(Synthesizer::new(sound_font, &settings).context("Failed to create Synthesizer")?));
+    let midi_target = MidiTarget::new(synthesizer.clone());
+    let sequencer = Arc::new(Mutex::new(Sequencer::new(sample_rate as u32)));
+    Ok((synthesizer, midi_target, sequencer))
+}
+
+/// Loads the SoundFont at `path`, or the bundled Salamander piano from the assets folder.
+pub fn load_soundfont(path: Option<&Path>) -> Result<Arc<SoundFont>> {
+    let sf2_path = match path {
+        Some(path) => path.to_path_buf(),
+        None => {
+            let sf2_filename = "SalamanderGrandPiano-V3+20200602.sf2";
+            // Not found anywhere: let opening it below report the usual place
+            crate::get_asset_path(sf2_filename).unwrap_or_else(|_| Path::new("assets").join(sf2_filename))
+        }
+    };
+
+    info!("Loading SoundFont from: {:?}", sf2_path);
+    let mut sf2_file = File::open(&sf2_path)
+        .with_context(|| format!("Failed to open SoundFont at {:?}", sf2_path))?;
+    Ok(Arc::new(SoundFont::new(&mut sf2_file).context("Failed to parse SoundFont")?))
+}
+
+/// Names of the audio output devices, default device first.
+pub fn list_output_devices() -> Result<Vec<String>> {
+    let host = cpal::default_host();
+    let default_name = host.default_output_device().and_then(|d| d.name().ok());
+    let mut names: Vec<String> = host
+        .output_devices()
+        .context("Failed to list audio devices")?
+        .filter_map(|d| d.name().ok())
+        .collect();
+    if let Some(default_name) = default_name {
+        if let Some(i) = names.iter().position(|n| *n == default_name) {
+            let name = names.remove(i);
+            names.insert(0, name);
+        }
+    }
+    Ok(names)
 }
 
-fn render_audio(output: &mut [f32], channels: usize, synthesizer: &Arc<Mutex<Synthesizer>>) {
-    let mut synth = synthesizer.lock().unwrap();
-    
+/// The processing after the synthesizer that settings can change while it
+/// plays, shared between the engine and the audio callback.
+#[derive(Clone)]
+struct Stages {
+    convolution: Arc<Mutex<ConvolutionStage>>,
+    effects: Arc<Mutex<EffectChain>>,
+    metronome: Arc<Mutex<Metronome>>,
+}
+
+impl Stages {
+    fn new(sample_rate: u32) -> Self {
+        Stages {
+            convolution: Arc::new(Mutex::new(ConvolutionStage::new(sample_rate))),
+            effects: Arc::new(Mutex::new(EffectChain::new(sample_rate, &MasterEffects::default()))),
+            metronome: Arc::new(Mutex::new(Metronome::new(sample_rate))),
+        }
+    }
+}
+
+/// Everything the audio callback owns between calls.
+struct Renderer {
+    target: MidiTarget,
+    sequencer: Arc<Mutex<Sequencer>>,
+    stages: Stages,
+    master: MasterStage,
+    meter: Arc<LevelMeter>,
+    // Scratch buffers, kept around so the callback doesn't allocate every time
+    left: Vec<f32>,
+    right: Vec<f32>,
+}
+
+impl Renderer {
+    fn new(
+        target: MidiTarget,
+        sequencer: Arc<Mutex<Sequencer>>,
+        volume: Arc<MasterVolume>,
+        meter: Arc<LevelMeter>,
+        stages: Stages,
+        sample_rate: u32,
+    ) -> Self {
+        Renderer {
+            target,
+            sequencer,
+            stages,
+            master: MasterStage::new(volume, sample_rate),
+            meter,
+            left: Vec::new(),
+            right: Vec::new(),
+        }
+    }
+}
+
+fn render_audio(output: &mut [f32], channels: usize, renderer: &mut Renderer) {
     // rustysynth renders stereo (left, right)
     // We need to interleave it into the output buffer
     let frame_count = output.len() / channels;
-    
-    // Create a temporary buffer for the synthesizer to render into
-    // rustysynth expects separate left and right buffers
-    let mut left = vec![0.0; frame_count];
-    let mut right = vec![0.0; frame_count];
+    if renderer.left.len() < frame_count {
+        renderer.left.resize(frame_count, 0.0);
+        renderer.right.resize(frame_count, 0.0);
+    }
+    let left = &mut renderer.left[..frame_count];
+    let right = &mut renderer.right[..frame_count];
+
+    // Render in slices that end where the next scheduled message is due,
+    // so sequenced notes land on the right sample rather than the next buffer.
+    let mut sequencer = renderer.sequencer.lock().unwrap();
+    if let Some(bpm) = renderer.target.tap_tempo.take_pending() {
+        let mut metronome = renderer.stages.metronome.lock().unwrap();
+        let settings = MetronomeSettings { bpm, ..*metronome.settings() };
+        metronome.set_settings(&settings);
+        // Taps are the metronome's beats; songs count quarter notes
+        sequencer.set_tempo(Some(bpm * 4.0 / settings.beat_unit as f64));
+    }
+    let mut offset = 0;
+    while offset < frame_count {
+        while let Some(message) = sequencer.pop_due() {
+            handle_midi_message(&message, &renderer.target);
+        }
+        let len = sequencer.frames_until_next(frame_count - offset);
+        let mut synth = renderer.target.synthesizer.lock().unwrap();
+        synth.render(&mut left[offset..offset + len], &mut right[offset..offset + len]);
+        drop(synth);
+        sequencer.advance(len);
+        offset += len;
+    }
+    drop(sequencer);
 
-    synth.render(&mut left, &mut right);
+    renderer.stages.convolution.lock().unwrap().process(left, right);
+    renderer.stages.effects.lock().unwrap().process(left, right);
+    // After the effects so the clicks stay dry, before the limiter so they can't clip
+    renderer.stages.metronome.lock().unwrap().process(left, right);
+    let clipped = renderer.master.process(left, right);
+    renderer.meter.measure(left, right, clipped);
 
     for (i, frame) in output.chunks_mut(channels).enumerate() {
         if channels >= 2 {
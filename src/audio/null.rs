@@ -0,0 +1,140 @@
+//! Running the engine without a sound card, for tests and CI. The same
+//! `render_audio` loop runs, but the samples go to a `Sink` instead of a device.
+
+use super::{render_audio, wav, Renderer};
+use anyhow::Result;
+use log::error;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Where rendered audio goes.
+pub enum Sink {
+    /// Render and throw it away
+    Discard,
+    /// A 16-bit stereo WAV file, finished when the engine is dropped
+    Wav(PathBuf),
+    /// Appended to a shared buffer, interleaved stereo
+    Memory(Arc<Mutex<Vec<f32>>>),
+}
+
+/// What drives rendering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clock {
+    /// A background thread renders in real time, like a sound card would
+    Timer,
+    /// Nothing happens until `AudioEngine::render` is called
+    Manual,
+}
+
+pub struct NullOutput {
+    pub sink: Sink,
+    pub clock: Clock,
+    pub sample_rate: u32,
+}
+
+impl Default for NullOutput {
+    fn default() -> Self {
+        NullOutput {
+            sink: Sink::Discard,
+            clock: Clock::Manual,
+            sample_rate: 44100,
+        }
+    }
+}
+
+/// Frames per render call in timer mode, about the size of a sound card buffer.
+const TIMER_BLOCK_FRAMES: usize = 512;
+
+enum SinkWriter {
+    Discard,
+    Wav(wav::Writer),
+    Memory(Arc<Mutex<Vec<f32>>>),
+}
+
+struct NullState {
+    renderer: Renderer,
+    writer: SinkWriter,
+}
+
+impl NullState {
+    fn render_into(&mut self, output: &mut [f32]) -> Result<()> {
+        render_audio(output, 2, &mut self.renderer);
+        match &mut self.writer {
+            SinkWriter::Discard => {}
+            SinkWriter::Wav(writer) => writer.write(output)?,
+            SinkWriter::Memory(buffer) => buffer.lock().unwrap().extend_from_slice(output),
+        }
+        Ok(())
+    }
+}
+
+/// Stands in for the cpal stream.
+pub(super) struct NullDriver {
+    state: Arc<Mutex<NullState>>,
+    timer: Option<(Arc<AtomicBool>, JoinHandle<()>)>,
+}
+
+impl NullDriver {
+    pub(super) fn start(renderer: Renderer, output: NullOutput) -> Result<Self> {
+        let writer = match output.sink {
+            Sink::Discard => SinkWriter::Discard,
+            Sink::Wav(path) => SinkWriter::Wav(wav::Writer::create(&path, 2, output.sample_rate)?),
+            Sink::Memory(buffer) => SinkWriter::Memory(buffer),
+        };
+        let state = Arc::new(Mutex::new(NullState { renderer, writer }));
+
+        let timer = match output.clock {
+            Clock::Manual => None,
+            Clock::Timer => {
+                let stop = Arc::new(AtomicBool::new(false));
+                let thread = {
+                    let state = state.clone();
+                    let stop = stop.clone();
+                    std::thread::spawn(move || run_timer(&state, &stop, output.sample_rate))
+                };
+                Some((stop, thread))
+            }
+        };
+
+        Ok(NullDriver { state, timer })
+    }
+
+    /// Renders `frames` frames right now and returns them, interleaved stereo.
+    pub(super) fn render(&self, frames: usize) -> Result<Vec<f32>> {
+        let mut output = vec![0.0; frames * 2];
+        self.state.lock().unwrap().render_into(&mut output)?;
+        Ok(output)
+    }
+}
+
+/// Renders a block whenever real time has moved on by one, until told to stop.
+fn run_timer(state: &Mutex<NullState>, stop: &AtomicBool, sample_rate: u32) {
+    let block_time = Duration::from_secs_f64(TIMER_BLOCK_FRAMES as f64 / sample_rate as f64);
+    let mut block = vec![0.0; TIMER_BLOCK_FRAMES * 2];
+    let started = Instant::now();
+    let mut rendered: u64 = 0;
+
+    while !stop.load(Ordering::Relaxed) {
+        let due = (started.elapsed().as_secs_f64() * sample_rate as f64) as u64;
+        while rendered + TIMER_BLOCK_FRAMES as u64 <= due {
+            if let Err(e) = state.lock().unwrap().render_into(&mut block) {
+                error!("Null audio output failed: {:#}", e);
+                return;
+            }
+            rendered += TIMER_BLOCK_FRAMES as u64;
+        }
+        std::thread::sleep(block_time / 2);
+    }
+}
+
+impl Drop for NullDriver {
+    fn drop(&mut self) {
+        if let Some((stop, thread)) = self.timer.take() {
+            stop.store(true, Ordering::Relaxed);
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Rendering MIDI files to audio without a sound card, as fast as the CPU allows.
+
+use super::master::MasterVolume;
+use super::meter::LevelMeter;
+use super::{create_synthesizer, load_soundfont, render_audio, AudioConfig, Renderer, Stages};
+use crate::midi::file::MidiFile;
+use anyhow::Result;
+use std::sync::Arc;
+
+pub const RENDER_SAMPLE_RATE: u32 = 44100;
+
+/// Frames rendered per step, about the size of a sound card buffer.
+const BLOCK_FRAMES: usize = 512;
+
+/// Once the song is over, keep rendering until the reverb and release tails
+/// fall below this level, or for at most `MAX_TAIL_SECONDS`.
+const SILENCE_LEVEL: f32 = 1.0e-4;
+const MAX_TAIL_SECONDS: u32 = 10;
+
+/// Plays `file` through the same synthesizer and sequencer the live engine uses
+/// and returns interleaved stereo samples at `RENDER_SAMPLE_RATE`.
+pub fn render_midi_file(audio_config: &AudioConfig, file: &MidiFile) -> Result<Vec<f32>> {
+    let sound_font = load_soundfont(audio_config.soundfont.as_deref())?;
+    let (_, target, sequencer) = create_synthesizer(&sound_font, RENDER_SAMPLE_RATE as i32)?;
+    sequencer.lock().unwrap().play_song(file, 0.0);
+    let volume = Arc::new(MasterVolume::default());
+    let meter = Arc::new(LevelMeter::default());
+    // Fresh stages: no reverb or effects, and the metronome is never turned on
+    let stages = Stages::new(RENDER_SAMPLE_RATE);
+    let mut renderer = Renderer::new(target, sequencer.clone(), volume, meter, stages, RENDER_SAMPLE_RATE);
+
+    let mut output = Vec::new();
+    let mut block = vec![0.0; BLOCK_FRAMES * 2];
+    let mut tail_frames = 0;
+    loop {
+        render_audio(&mut block, 2, &mut renderer);
+        output.extend_from_slice(&block);
+
+        if sequencer.lock().unwrap().song_position().is_none() {
+            tail_frames += BLOCK_FRAMES;
+            let silent = block.iter().all(|s| s.abs() < SILENCE_LEVEL);
+            if silent || tail_frames >= (MAX_TAIL_SECONDS * RENDER_SAMPLE_RATE) as usize {
+                break;
+            }
+        }
+    }
+
+    Ok(output)
+}
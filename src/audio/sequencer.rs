@@ -0,0 +1,206 @@
+use crate::midi::file::{MidiFile, TimedMessage};
+use std::collections::VecDeque;
+
+/// MIDI messages scheduled against the audio clock. The render loop pops them
+/// as it reaches their frame, so timing follows the sound card, not a sleeping thread.
+pub struct Sequencer {
+    sample_rate: u32,
+    // Frames rendered since the stream started
+    position: u64,
+    // Sorted by frame
+    queue: VecDeque<Scheduled>,
+    // Entries of `queue` that belong to the song
+    song_queued: usize,
+    song: Option<Song>,
+    // Notes the song has started and not yet released, so stopping can release them
+    song_notes: [bool; 128],
+    // Quarter notes per minute songs are played at, `None` for as written
+    tempo: Option<f64>,
+}
+
+struct Scheduled {
+    frame: u64,
+    message: [u8; 3],
+    // Part of the song, as opposed to messages played on their own
+    song: bool,
+}
+
+struct Song {
+    // The song's own tempo at its start
+    tempo: f64,
+    // Seconds of song played per second rendered
+    speed: f64,
+    // Song time at frame `anchor`, which moves on whenever the speed changes
+    anchor: u64,
+    time: f64,
+    // Song time of the last message
+    duration: f64,
+}
+
+impl Song {
+    /// Seconds into the song at `frame`, negative before it starts.
+    fn time_at(&self, frame: u64, sample_rate: u32) -> f64 {
+        self.time + (frame as f64 - self.anchor as f64) / sample_rate as f64 * self.speed
+    }
+}
+
+impl Sequencer {
+    pub fn new(sample_rate: u32) -> Self {
+        Sequencer {
+            sample_rate,
+            position: 0,
+            queue: VecDeque::new(),
+            song_queued: 0,
+            song: None,
+            song_notes: [false; 128],
+            tempo: None,
+        }
+    }
+
+    fn seconds_to_frames(&self, seconds: f64) -> u64 {
+        (seconds.max(0.0) * self.sample_rate as f64).round() as u64
+    }
+
+    /// Schedules every message of `file`, starting `lead_in` seconds from now,
+    /// at the tempo set with `set_tempo`. Replaces whatever song was playing;
+    /// returns the messages needed to release notes the old song left hanging.
+    pub fn play_song(&mut self, file: &MidiFile, lead_in: f64) -> Vec<[u8; 3]> {
+        let release = self.stop_song();
+        let start = self.position + self.seconds_to_frames(lead_in);
+        let speed = self.tempo.map_or(1.0, |tempo| tempo / file.tempo());
+
+        for m in file.messages() {
+            let frame = start + self.seconds_to_frames(m.time / speed);
+            self.schedule_at(frame, m.message, true);
+        }
+
+        self.song = Some(Song {
+            tempo: file.tempo(),
+            speed,
+            anchor: start,
+            time: 0.0,
+            duration: file.duration(),
+        });
+        release
+    }
+
+    /// Plays songs at `tempo` quarter notes per minute, or as written for
+    /// `None`. The song playing speeds up or slows down from where it is.
+    pub fn set_tempo(&mut self, tempo: Option<f64>) {
+        self.tempo = tempo;
+        let Some(song) = &mut self.song else {
+            return;
+        };
+        let speed = tempo.map_or(1.0, |tempo| tempo / song.tempo);
+        // Re-anchor at now (or the start, during the lead-in), then stretch
+        // what's still to come around that point
+        let pivot = self.position.max(song.anchor);
+        song.time = song.time_at(pivot, self.sample_rate);
+        song.anchor = pivot;
+        let stretch = song.speed / speed;
+        for scheduled in self.queue.iter_mut().filter(|s| s.song && s.frame > pivot) {
+            scheduled.frame = pivot + ((scheduled.frame - pivot) as f64 * stretch).round() as u64;
+        }
+        song.speed = speed;
+
+        // The song's messages may have moved past others'. Insertion sort keeps
+        // equal frames in order, and is quick on a queue that's nearly sorted.
+        let queue = self.queue.make_contiguous();
+        for i in 1..queue.len() {
+            let mut j = i;
+            while j > 0 && queue[j - 1].frame > queue[j].frame {
+                queue.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+    }
+
+    pub fn tempo(&self) -> Option<f64> {
+        self.tempo
+    }
+
+    /// Schedules `messages` from now, on their own rather than as a song:
+    /// they don't show as playing, keep their timing whatever the tempo, and
+    /// play on when a song stops.
+    pub fn play_messages(&mut self, messages: &[TimedMessage]) {
+        for m in messages {
+            let frame = self.position + self.seconds_to_frames(m.time);
+            self.schedule_at(frame, m.message, false);
+        }
+    }
+
+    /// Drops what's left of the song and returns note-offs (and a pedal
+    /// release) for notes it left sounding.
+    pub fn stop_song(&mut self) -> Vec<[u8; 3]> {
+        if self.song.take().is_none() {
+            return Vec::new();
+        }
+        self.queue.retain(|s| !s.song);
+        self.song_queued = 0;
+
+        let mut release: Vec<[u8; 3]> = (0..128u8)
+            .filter(|n| std::mem::take(&mut self.song_notes[*n as usize]))
+            .map(|n| [0x80, n, 0])
+            .collect();
+        release.push([0xB0, 64, 0]);
+        release
+    }
+
+    /// Seconds into the current song, or `None` when nothing is playing.
+    pub fn song_position(&self) -> Option<f64> {
+        let song = self.song.as_ref()?;
+        let time = song.time_at(self.position, self.sample_rate);
+        (time <= song.duration).then_some(time)
+    }
+
+    fn schedule_at(&mut self, frame: u64, message: [u8; 3], song: bool) {
+        // After any messages already at this frame, so file order is kept
+        let index = self.queue.partition_point(|s| s.frame <= frame);
+        self.queue.insert(index, Scheduled { frame, message, song });
+        if song {
+            self.song_queued += 1;
+        }
+    }
+
+    /// Next message that is due at the current position, if any.
+    pub fn pop_due(&mut self) -> Option<[u8; 3]> {
+        match self.queue.front() {
+            Some(scheduled) if scheduled.frame <= self.position => {
+                let scheduled = self.queue.pop_front()?;
+                if scheduled.song {
+                    self.song_queued -= 1;
+                    self.track_song_note(scheduled.message);
+                }
+                Some(scheduled.message)
+            }
+            _ => None,
+        }
+    }
+
+    fn track_song_note(&mut self, message: [u8; 3]) {
+        let [status, note, velocity] = message;
+        let note = (note & 0x7F) as usize;
+        match status & 0xF0 {
+            0x90 if velocity > 0 => self.song_notes[note] = true,
+            0x80 | 0x90 => self.song_notes[note] = false,
+            _ => {}
+        }
+    }
+
+    /// How many frames can be rendered before the next message is due, capped at `limit`.
+    pub fn frames_until_next(&self, limit: usize) -> usize {
+        match self.queue.front() {
+            Some(scheduled) => (scheduled.frame.saturating_sub(self.position) as usize).clamp(1, limit.max(1)),
+            None => limit,
+        }
+    }
+
+    pub fn advance(&mut self, frames: usize) {
+        self.position += frames as u64;
+        if let Some(song) = &self.song {
+            if song.time_at(self.position, self.sample_rate) > song.duration && self.song_queued == 0 {
+                self.song = None;
+            }
+        }
+    }
+}
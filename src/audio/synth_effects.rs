@@ -0,0 +1,80 @@
+//! The synthesizer's built-in reverb and chorus. rustysynth runs both all the
+//! time; what we control is how much of each the live channel sends to them,
+//! the MIDI way, with CC91 (reverb) and CC93 (chorus). Its reverb room itself
+//! is fixed, so the room presets are blends of the two sends.
+
+use std::fmt;
+
+const CC_REVERB_SEND: u8 = 91;
+const CC_CHORUS_SEND: u8 = 93;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SynthEffects {
+    pub reverb: bool,
+    /// 0-127, like CC91
+    pub reverb_send: u8,
+    pub chorus: bool,
+    /// 0-127, like CC93
+    pub chorus_send: u8,
+}
+
+impl Default for SynthEffects {
+    /// What rustysynth starts with: the General MIDI default reverb send, no chorus.
+    fn default() -> Self {
+        SynthEffects {
+            reverb: true,
+            reverb_send: 40,
+            chorus: true,
+            chorus_send: 0,
+        }
+    }
+}
+
+impl SynthEffects {
+    /// Control changes for the live channel that put these settings into effect.
+    pub fn messages(&self) -> [[u8; 3]; 2] {
+        let send = |enabled: bool, amount: u8| if enabled { amount.min(127) } else { 0 };
+        [
+            [0xB0, CC_REVERB_SEND, send(self.reverb, self.reverb_send)],
+            [0xB0, CC_CHORUS_SEND, send(self.chorus, self.chorus_send)],
+        ]
+    }
+
+    /// The preset these settings match, if any.
+    pub fn room(&self) -> Option<RoomPreset> {
+        RoomPreset::ALL
+            .into_iter()
+            .find(|room| room.reverb_send == self.reverb_send && room.chorus_send == self.chorus_send)
+    }
+
+    pub fn set_room(&mut self, room: RoomPreset) {
+        self.reverb = true;
+        self.chorus = true;
+        self.reverb_send = room.reverb_send;
+        self.chorus_send = room.chorus_send;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoomPreset {
+    pub name: &'static str,
+    pub reverb_send: u8,
+    pub chorus_send: u8,
+}
+
+impl RoomPreset {
+    pub const ALL: [RoomPreset; 6] = [
+        RoomPreset { name: "Dry", reverb_send: 0, chorus_send: 0 },
+        RoomPreset { name: "Studio", reverb_send: 20, chorus_send: 0 },
+        RoomPreset { name: "Room", reverb_send: 40, chorus_send: 0 },
+        RoomPreset { name: "Hall", reverb_send: 80, chorus_send: 10 },
+        RoomPreset { name: "Cathedral", reverb_send: 127, chorus_send: 20 },
+        RoomPreset { name: "Ensemble", reverb_send: 50, chorus_send: 90 },
+    ];
+}
+
+impl fmt::Display for RoomPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
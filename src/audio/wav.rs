@@ -0,0 +1,179 @@
+//! Minimal RIFF/WAVE support: writing 16-bit PCM, reading PCM or float files.
+
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Encodes interleaved samples in -1.0..=1.0 as a 16-bit PCM WAV file.
+pub fn encode(samples: &[f32], channels: u16, sample_rate: u32) -> Vec<u8> {
+    let data_len = samples.len() as u32 * 2;
+    let mut out = header(channels, sample_rate, data_len);
+    out.reserve(data_len as usize);
+    encode_samples(samples, &mut out);
+    out
+}
+
+fn header(channels: u16, sample_rate: u32, data_len: u32) -> Vec<u8> {
+    let bytes_per_frame = channels as u32 * 2;
+    let mut out = Vec::with_capacity(44);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data_len).to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+
+    out.extend_from_slice(b"fmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    out.extend_from_slice(&channels.to_le_bytes());
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+    out.extend_from_slice(&(sample_rate * bytes_per_frame).to_le_bytes());
+    out.extend_from_slice(&(bytes_per_frame as u16).to_le_bytes());
+    out.extend_from_slice(&16u16.to_le_bytes());
+
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_len.to_le_bytes());
+    out
+}
+
+fn encode_samples(samples: &[f32], out: &mut Vec<u8>) {
+    for sample in samples {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+pub fn write(path: &Path, samples: &[f32], channels: u16, sample_rate: u32) -> Result<()> {
+    let mut file = File::create(path).with_context(|| format!("Failed to create {:?}", path))?;
+    file.write_all(&encode(samples, channels, sample_rate))
+        .with_context(|| format!("Failed to write {:?}", path))?;
+    Ok(())
+}
+
+/// Writes a WAV file a block at a time, for recordings of unknown length.
+/// The header's sizes are filled in by `finish`, or on drop.
+pub struct Writer {
+    file: BufWriter<File>,
+    channels: u16,
+    sample_rate: u32,
+    data_len: u32,
+    finished: bool,
+    // Reused between blocks
+    buffer: Vec<u8>,
+}
+
+impl Writer {
+    pub fn create(path: &Path, channels: u16, sample_rate: u32) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("Failed to create {:?}", path))?;
+        let mut file = BufWriter::new(file);
+        file.write_all(&header(channels, sample_rate, 0))?;
+        Ok(Writer {
+            file,
+            channels,
+            sample_rate,
+            data_len: 0,
+            finished: false,
+            buffer: Vec::new(),
+        })
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> Result<()> {
+        self.buffer.clear();
+        encode_samples(samples, &mut self.buffer);
+        self.file.write_all(&self.buffer).context("Failed to write WAV data")?;
+        self.data_len += self.buffer.len() as u32;
+        Ok(())
+    }
+
+    /// Fills in the header so players know how long the file is.
+    pub fn finish(&mut self) -> Result<()> {
+        self.finished = true;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header(self.channels, self.sample_rate, self.data_len))?;
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.flush().context("Failed to finish WAV file")?;
+        Ok(())
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        if !self.finished {
+            if let Err(e) = self.finish() {
+                log::error!("{:#}", e);
+            }
+        }
+    }
+}
+
+/// A decoded WAV file, one sample vector per channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Audio {
+    pub channels: Vec<Vec<f32>>,
+    pub sample_rate: u32,
+}
+
+pub fn read(path: &Path) -> Result<Audio> {
+    let data = std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+    decode(&data).with_context(|| format!("Failed to decode {:?}", path))
+}
+
+/// Decodes 8/16/24/32-bit integer PCM and 32/64-bit float WAV data.
+pub fn decode(data: &[u8]) -> Result<Audio> {
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        bail!("not a WAV file");
+    }
+
+    let mut format = None;
+    let mut samples = None;
+    let mut rest = &data[12..];
+    while rest.len() >= 8 {
+        let id = &rest[..4];
+        let len = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+        let body = rest.get(8..8 + len).unwrap_or(&rest[8..]); // tolerate a truncated last chunk
+        match id {
+            b"fmt " => format = Some(body),
+            b"data" => samples = Some(body),
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        rest = rest.get(8 + len + (len & 1)..).unwrap_or(&[]);
+    }
+
+    let format = format.context("no fmt chunk")?;
+    let samples = samples.context("no data chunk")?;
+    if format.len() < 16 {
+        bail!("fmt chunk too short");
+    }
+    let u16_at = |i: usize| u16::from_le_bytes([format[i], format[i + 1]]);
+    let mut tag = u16_at(0);
+    let channel_count = u16_at(2) as usize;
+    let sample_rate = u32::from_le_bytes(format[4..8].try_into().unwrap());
+    let bits = u16_at(14);
+    if tag == 0xFFFE && format.len() >= 26 {
+        // WAVE_FORMAT_EXTENSIBLE: the real format is the start of the subformat GUID
+        tag = u16_at(24);
+    }
+    if channel_count == 0 || sample_rate == 0 {
+        bail!("bad channel count or sample rate");
+    }
+
+    let bytes = (bits as usize).div_ceil(8);
+    let decode_sample: fn(&[u8]) -> f32 = match (tag, bits) {
+        (1, 8) => |b| (b[0] as f32 - 128.0) / 128.0,
+        (1, 16) => |b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+        (1, 24) => |b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0,
+        (1, 32) => |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0,
+        (3, 32) => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        (3, 64) => |b| f64::from_le_bytes(b[..8].try_into().unwrap()) as f32,
+        _ => bail!("unsupported sample format {} with {} bits", tag, bits),
+    };
+
+    let mut channels = vec![Vec::new(); channel_count];
+    for frame in samples.chunks_exact(bytes * channel_count) {
+        for (channel, sample) in channels.iter_mut().zip(frame.chunks_exact(bytes)) {
+            channel.push(decode_sample(sample));
+        }
+    }
+
+    Ok(Audio { channels, sample_rate })
+}
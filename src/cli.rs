@@ -0,0 +1,114 @@
+//! Command-line options. Anything given here wins over the settings file for
+//! this run only; it isn't saved.
+
+use anyhow::{bail, Context, Result};
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: toy-piano [OPTIONS]
+
+Options:
+      --soundfont <FILE>         SoundFont (.sf2) to play
+      --midi-port <NAME>         MIDI input to connect to (exact name or part of it)
+      --audio-device <NAME>      Audio output device
+      --list-midi-ports          Print the available MIDI inputs and exit
+      --list-audio-devices       Print the available audio outputs and exit
+      --no-gui                   Run without a window, playing MIDI input until stopped
+      --render <IN.mid> <OUT.wav>
+                                 Render a MIDI file to a WAV file and exit
+  -h, --help                     Print this help
+  -V, --version                  Print the version
+";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Options {
+    pub soundfont: Option<PathBuf>,
+    pub midi_port: Option<String>,
+    pub audio_device: Option<String>,
+    pub list_midi_ports: bool,
+    pub list_audio_devices: bool,
+    pub no_gui: bool,
+    /// Input MIDI file and output WAV file
+    pub render: Option<(PathBuf, PathBuf)>,
+    pub help: bool,
+    pub version: bool,
+}
+
+impl Options {
+    /// Parses the arguments after the program name. Values may follow their
+    /// option as the next argument or after an `=`.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name.to_string(), Some(value.to_string())),
+                _ => (arg.clone(), None),
+            };
+            let mut value = |what: &str| -> Result<String> {
+                match inline_value.clone() {
+                    Some(value) => Ok(value),
+                    None => args.next().with_context(|| format!("{} needs {}", name, what)),
+                }
+            };
+
+            match name.as_str() {
+                "--soundfont" => options.soundfont = Some(value("a file")?.into()),
+                "--midi-port" => options.midi_port = Some(value("a port name")?),
+                "--audio-device" => options.audio_device = Some(value("a device name")?),
+                "--render" => {
+                    let input = value("an input .mid file")?;
+                    let output = args.next().context("--render needs an output .wav file")?;
+                    options.render = Some((input.into(), output.into()));
+                }
+                "--list-midi-ports" => options.list_midi_ports = true,
+                "--list-audio-devices" => options.list_audio_devices = true,
+                "--no-gui" => options.no_gui = true,
+                "-h" | "--help" => options.help = true,
+                "-V" | "--version" => options.version = true,
+                _ => bail!("Unknown option '{}'\n\n{}", arg, USAGE),
+            }
+        }
+
+        if options.no_gui && options.render.is_some() {
+            bail!("--render and --no-gui can't be used together");
+        }
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options> {
+        Options::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn no_arguments_means_defaults() {
+        assert_eq!(parse(&[]).unwrap(), Options::default());
+    }
+
+    #[test]
+    fn values_follow_or_attach_to_options() {
+        let options = parse(&["--soundfont", "piano.sf2", "--midi-port=Keystation", "--no-gui"]).unwrap();
+        assert_eq!(options.soundfont, Some(PathBuf::from("piano.sf2")));
+        assert_eq!(options.midi_port.as_deref(), Some("Keystation"));
+        assert!(options.no_gui);
+    }
+
+    #[test]
+    fn render_takes_two_paths() {
+        let options = parse(&["--render", "in.mid", "out.wav"]).unwrap();
+        assert_eq!(options.render, Some((PathBuf::from("in.mid"), PathBuf::from("out.wav"))));
+        assert!(parse(&["--render", "in.mid"]).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_options_and_missing_values() {
+        assert!(parse(&["--loud"]).is_err());
+        assert!(parse(&["--audio-device"]).is_err());
+    }
+}
@@ -0,0 +1,63 @@
+//! Running without a window, e.g. on kiosk machines with no display. Uses the
+//! same `AudioEngine` and `MidiEngine` as the GUI; only the event loop differs.
+
+use anyhow::{Context, Result};
+use log::{error, info};
+use std::sync::mpsc;
+use std::time::Duration;
+use toy_piano::audio::{AudioConfig, AudioEngine};
+use toy_piano::midi::MidiEngine;
+use toy_piano::settings::Settings;
+
+/// How often to look for MIDI devices that were plugged in or pulled out.
+const PORT_SCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Plays MIDI input until the process gets Ctrl+C, SIGTERM or SIGHUP.
+pub fn run(audio_config: &AudioConfig, settings: &Settings, midi_port: Option<&str>) -> Result<()> {
+    let (stop_sender, stop) = mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = stop_sender.send(());
+    })
+    .context("Failed to install the signal handler")?;
+
+    let audio_engine = AudioEngine::init(audio_config)?;
+    audio_engine.set_volume(settings.volume);
+    audio_engine.select_preset(settings.bank, settings.program);
+    audio_engine.set_transpose(settings.transpose);
+    audio_engine.set_octave_shift(settings.octave_shift);
+    audio_engine.set_zones(&settings.zones);
+    audio_engine.set_tuning(&settings.tuning);
+    if let Some(files) = &settings.scala {
+        if let Err(e) = audio_engine.load_scala(files, settings.tuning.reference) {
+            error!("{:#}", e);
+        }
+    }
+    audio_engine.set_synth_effects(&settings.synth_effects);
+    if let Some(path) = &settings.impulse_response {
+        if let Err(e) = audio_engine.load_impulse_response(path) {
+            error!("{:#}", e);
+        }
+    }
+    audio_engine.set_convolution(settings.convolution);
+    audio_engine.set_master_effects(&settings.master_effects);
+    audio_engine.set_metronome(&settings.metronome);
+    audio_engine.set_tap_trigger(settings.tap_trigger);
+    info!("Audio Engine initialized.");
+    if let Err(e) = audio_engine.play_jingle(&settings.startup_jingle) {
+        error!("{:#}", e);
+    }
+
+    let mut midi_engine = MidiEngine::init(audio_engine.midi_target(), midi_port)?;
+    midi_engine.set_velocity_curves(settings.velocity_curves.clone());
+    info!("Running without a window. Press Ctrl+C to quit.");
+
+    // Anything but a timeout means a signal arrived
+    while let Err(mpsc::RecvTimeoutError::Timeout) = stop.recv_timeout(PORT_SCAN_INTERVAL) {
+        if let Err(e) = midi_engine.reconnect() {
+            error!("{:#}", e);
+        }
+    }
+
+    info!("Shutting down.");
+    Ok(())
+}
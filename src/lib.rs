@@ -0,0 +1,52 @@
+//! Toy Piano's engine: SoundFont synthesis, MIDI input and file playback,
+//! settings, and the iced GUI (behind the `gui` feature, on by default).
+//!
+//! To embed the piano, build an [`AudioEngine`] and feed it MIDI bytes through
+//! [`handle_midi_message`] with its [`MidiTarget`], or let a [`MidiEngine`]
+//! connect to a keyboard:
+//!
+//! ```no_run
+//! use toy_piano::{handle_midi_message, AudioConfig, AudioEngine};
+//!
+//! let engine = AudioEngine::init(&AudioConfig::default())?;
+//! handle_midi_message(&[0x90, 60, 100], &engine.midi_target()); // middle C
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod audio;
+pub mod midi;
+pub mod settings;
+pub mod theory;
+#[cfg(feature = "gui")]
+pub mod ui;
+
+pub use audio::{AudioConfig, AudioEngine};
+pub use midi::{handle_midi_message, KeyState, MidiEngine, MidiTarget};
+pub use settings::Settings;
+
+use anyhow::Result;
+
+/// Helper to find assets whether running via cargo or as a bundle
+pub fn get_asset_path(filename: &str) -> Result<std::path::PathBuf> {
+    let std_path = std::path::Path::new("assets").join(filename);
+    
+    // 1. Check local assets (cargo run)
+    if std_path.exists() {
+        return Ok(std_path);
+    }
+
+    // 2. Check next to executable (compiled / bundle)
+    if let Ok(exe_path) = std::env::current_exe() {
+        if let Some(exe_dir) = exe_path.parent() {
+            let bundle_path = exe_dir.join("assets").join(filename);
+            if bundle_path.exists() {
+                return Ok(bundle_path);
+            }
+            
+            // 3. MacOS Bundle specific: ../Resources/assets (optional, but standard structure sometimes calls for this)
+            // But my script puts them in MacOS/, so step 2 should cover it.
+        }
+    }
+
+    anyhow::bail!("Asset not found: {}", filename)
+}
@@ -1,26 +1,93 @@
-pub mod audio;
-pub mod midi;
-pub mod ui;
-
-use anyhow::{Context, Result};
-use iced::{Application, Settings};
+use anyhow::Result;
+#[cfg(feature = "gui")]
+use anyhow::Context;
 use log::info;
-use std::sync::Arc;
 
-use ui::ToyPianoApp;
+use toy_piano::{audio, midi, settings};
+
+mod cli;
+mod headless;
 
 fn main() -> Result<()> {
-    env_logger::init();
+    let options = cli::Options::parse(std::env::args().skip(1))?;
+
+    // Without a window the log is all there is to see, so show connections by default
+    let default_log_level = if options.no_gui { "info" } else { "error" };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(default_log_level)).init();
+    if options.help {
+        print!("{}", cli::USAGE);
+        return Ok(());
+    }
+    if options.version {
+        println!("toy-piano {}", env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+    if options.list_midi_ports || options.list_audio_devices {
+        if options.list_midi_ports {
+            for name in midi::list_ports()? {
+                println!("{}", name);
+            }
+        }
+        if options.list_audio_devices {
+            for name in audio::list_output_devices()? {
+                println!("{}", name);
+            }
+        }
+        return Ok(());
+    }
+
     info!("Toy Piano starting up...");
 
+    let app_settings = settings::Settings::load();
+
+    // The command line wins over the settings file
+    let audio_config = audio::AudioConfig {
+        soundfont: options.soundfont.clone().or_else(|| app_settings.soundfont.clone()),
+        device: options.audio_device.clone().or_else(|| app_settings.audio_device.clone()),
+    };
+
+    if let Some((input, output)) = &options.render {
+        let file = midi::file::MidiFile::load(input)?;
+        let samples = audio::offline::render_midi_file(&audio_config, &file)?;
+        audio::wav::write(output, &samples, 2, audio::offline::RENDER_SAMPLE_RATE)?;
+        info!("Rendered {:?} to {:?}", input, output);
+        return Ok(());
+    }
+
+    if options.no_gui {
+        let midi_port = options.midi_port.as_deref().or(app_settings.midi_device.as_deref());
+        return headless::run(&audio_config, &app_settings, midi_port);
+    }
+
+    run_gui(options, app_settings, &audio_config)
+}
+
+#[cfg(not(feature = "gui"))]
+fn run_gui(_options: cli::Options, _app_settings: settings::Settings, _audio_config: &audio::AudioConfig) -> Result<()> {
+    anyhow::bail!("This build of Toy Piano has no GUI, run it with --no-gui")
+}
+
+#[cfg(feature = "gui")]
+fn run_gui(options: cli::Options, app_settings: settings::Settings, audio_config: &audio::AudioConfig) -> Result<()> {
+    use iced::{Application, Settings};
+    use std::rc::Rc;
+    use toy_piano::ui::{self, ToyPianoApp};
+
     // Initialize Audio Engine first
-    // We wrap it in Arc to share it with the UI (and keep it alive)
-    let audio_engine = Arc::new(audio::AudioEngine::init()?);
+    // We wrap it in Rc to share it with the UI (and keep it alive)
+    let audio_engine = Rc::new(audio::AudioEngine::init(audio_config)?);
     info!("Audio Engine initialized.");
 
     // Launch GUI
-    let mut settings = Settings::with_flags(audio_engine);
-    settings.window.size = iced::Size::new(800.0, 600.0); // Set a reasonable default size
+    let window_size = iced::Size::new(app_settings.window_width, app_settings.window_height);
+    let mut settings = Settings::with_flags(ui::Startup {
+        audio_engine,
+        settings: app_settings,
+        midi_port: options.midi_port,
+    });
+    settings.window.size = window_size;
+    // The app closes the window itself, after saving the settings
+    settings.window.exit_on_close_request = false;
     
     // Attempt to load icon
     match load_icon() {
@@ -33,8 +100,9 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "gui")]
 fn load_icon() -> Result<iced::window::icon::Icon> {
-    let icon_path = get_asset_path("abstract-soundwave-icon.png")?;
+    let icon_path = toy_piano::get_asset_path("abstract-soundwave-icon.png")?;
     let img = image::open(&icon_path).context(format!("Failed to open icon file at {:?}", icon_path))?.to_rgba8();
     let (width, height) = img.dimensions();
     let rgba = img.into_raw();
@@ -42,28 +110,3 @@ fn load_icon() -> Result<iced::window::icon::Icon> {
     let icon = iced::window::icon::from_rgba(rgba, width, height).context("Failed to process icon")?;
     Ok(icon)
 }
-
-/// Helper to find assets whether running via cargo or as a bundle
-pub fn get_asset_path(filename: &str) -> Result<std::path::PathBuf> {
-    let std_path = std::path::Path::new("assets").join(filename);
-    
-    // 1. Check local assets (cargo run)
-    if std_path.exists() {
-        return Ok(std_path);
-    }
-
-    // 2. Check next to executable (compiled / bundle)
-    if let Ok(exe_path) = std::env::current_exe() {
-        if let Some(exe_dir) = exe_path.parent() {
-            let bundle_path = exe_dir.join("assets").join(filename);
-            if bundle_path.exists() {
-                return Ok(bundle_path);
-            }
-            
-            // 3. MacOS Bundle specific: ../Resources/assets (optional, but standard structure sometimes calls for this)
-            // But my script puts them in MacOS/, so step 2 should cover it.
-        }
-    }
-
-    anyhow::bail!("Asset not found: {}", filename)
-}
@@ -0,0 +1,147 @@
+//! Fitting a velocity curve to how someone actually plays a keyboard: they
+//! play a few notes softly, medium and loud, and the curve maps what the
+//! keyboard sent for each onto the velocities those dynamics should have.
+
+use super::velocity::VelocityCurve;
+use anyhow::{bail, Result};
+
+/// Notes needed at each dynamic before moving on.
+pub const MIN_NOTES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dynamic {
+    Pianissimo,
+    Mezzo,
+    Fortissimo,
+}
+
+impl Dynamic {
+    pub const ALL: [Dynamic; 3] = [Dynamic::Pianissimo, Dynamic::Mezzo, Dynamic::Fortissimo];
+
+    /// The velocity a note played at this dynamic should come out with.
+    pub fn target(self) -> u8 {
+        match self {
+            Dynamic::Pianissimo => 20,
+            Dynamic::Mezzo => 64,
+            Dynamic::Fortissimo => 112,
+        }
+    }
+
+    pub fn instruction(self) -> &'static str {
+        match self {
+            Dynamic::Pianissimo => "Play a few notes pianissimo, as softly as you can and still hear them",
+            Dynamic::Mezzo => "Play a few notes mezzo forte, at a comfortable everyday loudness",
+            Dynamic::Fortissimo => "Play a few notes fortissimo, as loud as you'd play in a piece",
+        }
+    }
+}
+
+/// Velocities recorded so far, one list per dynamic.
+#[derive(Debug, Clone, Default)]
+pub struct Calibration {
+    step: usize,
+    recorded: [Vec<u8>; 3],
+}
+
+impl Calibration {
+    /// The dynamic being recorded, `None` once all three are done.
+    pub fn dynamic(&self) -> Option<Dynamic> {
+        Dynamic::ALL.get(self.step).copied()
+    }
+
+    /// Records a note-on velocity straight from the keyboard.
+    pub fn record(&mut self, velocity: u8) {
+        if let Some(recorded) = self.recorded.get_mut(self.step) {
+            recorded.push(velocity);
+        }
+    }
+
+    /// Velocities recorded for the current dynamic.
+    pub fn recorded(&self) -> &[u8] {
+        self.recorded.get(self.step).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn can_advance(&self) -> bool {
+        self.recorded().len() >= MIN_NOTES
+    }
+
+    pub fn advance(&mut self) {
+        if self.can_advance() {
+            self.step += 1;
+        }
+    }
+
+    /// A curve that sends the median of each dynamic's velocities to its target.
+    pub fn fit(&self) -> Result<VelocityCurve> {
+        if self.dynamic().is_some() {
+            bail!("calibration isn't finished");
+        }
+        let [soft, medium, loud] = self.recorded.each_ref().map(|recorded| median(recorded));
+        if !(soft < medium && medium < loud) {
+            bail!(
+                "the soft, medium and loud notes came in at {}, {} and {}; each needs to be louder than the last",
+                soft,
+                medium,
+                loud
+            );
+        }
+
+        let mut points: Vec<(u8, u8)> = Dynamic::ALL
+            .iter()
+            .zip([soft, medium, loud])
+            .map(|(dynamic, velocity)| (velocity, dynamic.target()))
+            .collect();
+        // Keep playing softer or harder than that possible
+        if soft > 0 {
+            points.insert(0, (0, 1));
+        }
+        if loud < 127 {
+            points.push((127, 127));
+        }
+        Ok(VelocityCurve::custom(points))
+    }
+}
+
+fn median(values: &[u8]) -> u8 {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    sorted[sorted.len() / 2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calibrate(soft: &[u8], medium: &[u8], loud: &[u8]) -> Result<VelocityCurve> {
+        let mut calibration = Calibration::default();
+        for notes in [soft, medium, loud] {
+            notes.iter().for_each(|v| calibration.record(*v));
+            calibration.advance();
+        }
+        calibration.fit()
+    }
+
+    #[test]
+    fn curve_maps_each_dynamic_to_its_target() {
+        let curve = calibrate(&[40, 45, 50], &[80, 90, 85], &[120, 118, 110]).unwrap();
+        assert_eq!(curve.apply(45), Dynamic::Pianissimo.target());
+        assert_eq!(curve.apply(85), Dynamic::Mezzo.target());
+        assert_eq!(curve.apply(118), Dynamic::Fortissimo.target());
+        assert_eq!(curve.apply(127), 127);
+    }
+
+    #[test]
+    fn needs_enough_notes_at_each_dynamic() {
+        let mut calibration = Calibration::default();
+        calibration.record(30);
+        calibration.record(31);
+        calibration.advance();
+        assert_eq!(calibration.dynamic(), Some(Dynamic::Pianissimo));
+        assert!(calibration.fit().is_err());
+    }
+
+    #[test]
+    fn dynamics_out_of_order_are_an_error() {
+        assert!(calibrate(&[60, 60, 60], &[50, 50, 50], &[100, 100, 100]).is_err());
+    }
+}
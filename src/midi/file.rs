@@ -0,0 +1,421 @@
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+/// A channel message from a MIDI file, with its time in seconds from the start.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimedMessage {
+    pub time: f64,
+    pub message: [u8; 3],
+}
+
+/// A note from a MIDI file, with its start and length in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FileNote {
+    pub note: u8,
+    pub velocity: u8,
+    pub start: f64,
+    pub duration: f64,
+}
+
+/// A Standard MIDI File (format 0 or 1), flattened into one time-ordered list
+/// of channel messages with the tempo map already applied.
+pub struct MidiFile {
+    /// What plays, see `playable`
+    messages: Vec<TimedMessage>,
+    notes: Vec<FileNote>,
+    /// Microseconds per quarter note at the start
+    tempo: u32,
+}
+
+// Drums would just come out as random piano notes
+const PERCUSSION_CHANNEL: u8 = 9;
+
+const DEFAULT_TEMPO: u32 = 500_000; // microseconds per quarter note (120 BPM)
+
+struct RawEvent {
+    tick: u64,
+    track: usize,
+    kind: RawKind,
+}
+
+enum RawKind {
+    Message([u8; 3]),
+    Tempo(u32),
+}
+
+impl MidiFile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path).with_context(|| format!("Failed to read MIDI file at {:?}", path))?;
+        Self::parse(&data).with_context(|| format!("Failed to parse MIDI file at {:?}", path))
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data);
+
+        if reader.bytes(4)? != b"MThd" {
+            bail!("Not a Standard MIDI File");
+        }
+        let header_len = reader.u32()? as usize;
+        let header = reader.bytes(header_len)?;
+        if header.len() < 6 {
+            bail!("MIDI header too short");
+        }
+        let format = u16::from_be_bytes([header[0], header[1]]);
+        let track_count = u16::from_be_bytes([header[2], header[3]]) as usize;
+        let division = u16::from_be_bytes([header[4], header[5]]);
+        if format > 1 {
+            bail!("MIDI file format {} is not supported", format);
+        }
+
+        let mut events = Vec::new();
+        for track in 0..track_count {
+            // Skip unknown chunks, as the spec asks
+            loop {
+                let id = reader.bytes(4)?;
+                let len = reader.u32()? as usize;
+                let body = reader.bytes(len)?;
+                if id == b"MTrk" {
+                    parse_track(body, track, &mut events)?;
+                    break;
+                }
+            }
+        }
+
+        // Stable, so events on the same tick keep their track order
+        events.sort_by_key(|e| (e.tick, e.track));
+
+        let seconds_per_tick = |tempo: u32| -> f64 {
+            if division & 0x8000 != 0 {
+                // SMPTE: negative frames per second in the high byte, ticks per frame in the low byte
+                let fps = -((division >> 8) as i8) as f64;
+                let ticks_per_frame = (division & 0xFF) as f64;
+                1.0 / (fps * ticks_per_frame)
+            } else {
+                tempo as f64 / 1_000_000.0 / division.max(1) as f64
+            }
+        };
+
+        let mut messages = Vec::new();
+        let mut tempo = DEFAULT_TEMPO;
+        let mut initial_tempo = DEFAULT_TEMPO;
+        let mut last_tick = 0;
+        let mut time = 0.0;
+        for event in events {
+            time += (event.tick - last_tick) as f64 * seconds_per_tick(tempo);
+            last_tick = event.tick;
+            match event.kind {
+                RawKind::Tempo(t) => {
+                    if event.tick == 0 {
+                        initial_tempo = t;
+                    }
+                    tempo = t;
+                }
+                RawKind::Message(message) => messages.push(TimedMessage { time, message }),
+            }
+        }
+
+        let notes = pair_notes(&messages);
+        Ok(MidiFile { messages: playable(&messages), notes, tempo: initial_tempo })
+    }
+
+    /// The notes and sustain pedal, ready for `handle_midi_message`.
+    pub fn messages(&self) -> &[TimedMessage] {
+        &self.messages
+    }
+
+    pub fn notes(&self) -> &[FileNote] {
+        &self.notes
+    }
+
+    /// Quarter notes per minute at the start, as written.
+    pub fn tempo(&self) -> f64 {
+        60_000_000.0 / self.tempo.max(1) as f64
+    }
+
+    /// Length in seconds, up to the last message.
+    pub fn duration(&self) -> f64 {
+        self.messages.last().map(|m| m.time).unwrap_or(0.0)
+    }
+}
+
+fn parse_track(data: &[u8], track: usize, events: &mut Vec<RawEvent>) -> Result<()> {
+    let mut reader = Reader::new(data);
+    let mut tick = 0u64;
+    let mut running_status = 0u8;
+
+    while !reader.is_empty() {
+        tick += reader.varint()? as u64;
+
+        let mut status = reader.u8()?;
+        let first_data = if status < 0x80 {
+            // Running status: this byte is already the first data byte
+            if running_status == 0 {
+                bail!("Data byte without a status byte");
+            }
+            let data = status;
+            status = running_status;
+            Some(data)
+        } else {
+            None
+        };
+
+        match status {
+            0xFF => {
+                let kind = reader.u8()?;
+                let len = reader.varint()? as usize;
+                let body = reader.bytes(len)?;
+                match kind {
+                    0x2F => break, // End of track
+                    0x51 if body.len() == 3 => {
+                        let tempo = u32::from_be_bytes([0, body[0], body[1], body[2]]);
+                        events.push(RawEvent { tick, track, kind: RawKind::Tempo(tempo) });
+                    }
+                    _ => {}
+                }
+            }
+            0xF0 | 0xF7 => {
+                let len = reader.varint()? as usize;
+                reader.bytes(len)?;
+            }
+            0x80..=0xEF => {
+                running_status = status;
+                let data1 = match first_data {
+                    Some(d) => d,
+                    None => reader.u8()?,
+                };
+                let data2 = match status & 0xF0 {
+                    0xC0 | 0xD0 => 0,
+                    _ => reader.u8()?,
+                };
+                if status & 0x0F != PERCUSSION_CHANNEL {
+                    events.push(RawEvent { tick, track, kind: RawKind::Message([status, data1, data2]) });
+                }
+            }
+            _ => bail!("Unexpected status byte {:#04x}", status),
+        }
+    }
+
+    Ok(())
+}
+
+/// The part of a file that reaches the keyboard path: notes and the sustain
+/// pedal. Program changes, volume, pan, effect sends and pitch bend are
+/// left out, as they'd replace the player's own sound and stay after the
+/// song. That path has one slot per key whatever the channel, so a key held
+/// on several channels is released when the last of them lets go, and the
+/// pedal is down while any channel holds it. Whatever is still down at the
+/// end is let go there, so nothing hangs.
+fn playable(messages: &[TimedMessage]) -> Vec<TimedMessage> {
+    // Channels holding each key, and holding the pedal, one bit each
+    let mut keys = [0u16; 128];
+    let mut pedal = 0u16;
+    let mut playable = Vec::new();
+
+    for m in messages {
+        let [status, data1, data2] = m.message;
+        let bit = 1u16 << (status & 0x0F);
+        let key = (data1 & 0x7F) as usize;
+        match status & 0xF0 {
+            0x90 if data2 > 0 => {
+                keys[key] |= bit;
+                playable.push(*m);
+            }
+            0x80 | 0x90 => {
+                let held = keys[key];
+                keys[key] &= !bit;
+                if held != 0 && keys[key] == 0 {
+                    playable.push(*m);
+                }
+            }
+            0xB0 if data1 == 64 => {
+                let was_down = pedal != 0;
+                if data2 >= 64 {
+                    pedal |= bit;
+                } else {
+                    pedal &= !bit;
+                }
+                if was_down != (pedal != 0) {
+                    playable.push(*m);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let end = messages.last().map_or(0.0, |m| m.time);
+    for key in (0..128u8).filter(|key| keys[*key as usize] != 0) {
+        playable.push(TimedMessage { time: end, message: [0x80, key, 0] });
+    }
+    if pedal != 0 {
+        playable.push(TimedMessage { time: end, message: [0xB0, 64, 0] });
+    }
+    playable
+}
+
+/// Matches note-ons with their note-offs so the notes can be drawn as bars.
+fn pair_notes(messages: &[TimedMessage]) -> Vec<FileNote> {
+    let mut notes: Vec<FileNote> = Vec::new();
+    // Index into `notes` of the note currently sounding on each channel/key
+    let mut open: [[Option<usize>; 128]; 16] = [[None; 128]; 16];
+
+    for m in messages {
+        let [status, key, velocity] = m.message;
+        let channel = (status & 0x0F) as usize;
+        let key_index = (key & 0x7F) as usize;
+        let is_on = status & 0xF0 == 0x90 && velocity > 0;
+        let is_off = status & 0xF0 == 0x80 || (status & 0xF0 == 0x90 && velocity == 0);
+
+        if is_on || is_off {
+            if let Some(i) = open[channel][key_index].take() {
+                notes[i].duration = m.time - notes[i].start;
+            }
+        }
+        if is_on {
+            open[channel][key_index] = Some(notes.len());
+            notes.push(FileNote { note: key, velocity, start: m.time, duration: 0.0 });
+        }
+    }
+
+    notes
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.data.len());
+        let Some(end) = end else {
+            bail!("Unexpected end of MIDI data");
+        };
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn varint(&mut self) -> Result<u32> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("Variable-length quantity too long")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A format 1 file at 480 ticks per quarter note, one chunk per track.
+    fn smf(tracks: &[&[u8]]) -> Vec<u8> {
+        let mut file = b"MThd".to_vec();
+        file.extend_from_slice(&6u32.to_be_bytes());
+        file.extend_from_slice(&[0, 1, 0, tracks.len() as u8, 0x01, 0xE0]);
+        for track in tracks {
+            file.extend_from_slice(b"MTrk");
+            file.extend_from_slice(&(track.len() as u32).to_be_bytes());
+            file.extend_from_slice(track);
+        }
+        file
+    }
+
+    fn messages(file: &MidiFile) -> Vec<(f64, [u8; 3])> {
+        file.messages().iter().map(|m| (m.time, m.message)).collect()
+    }
+
+    const END: [u8; 4] = [0x00, 0xFF, 0x2F, 0x00];
+
+    #[test]
+    fn running_status_reuses_the_last_status() {
+        // Note on, then two more data pairs under the same status, one a note-off by velocity 0
+        let track = [&[0x00, 0x90, 60, 100, 0x00, 64, 100, 0x83, 0x60, 60, 0x00, 0x00, 64, 0x00][..], &END].concat();
+        let file = MidiFile::parse(&smf(&[&track])).unwrap();
+        assert_eq!(
+            messages(&file),
+            vec![(0.0, [0x90, 60, 100]), (0.0, [0x90, 64, 100]), (0.5, [0x90, 60, 0]), (0.5, [0x90, 64, 0])]
+        );
+        assert!(MidiFile::parse(&smf(&[&[0x00, 60, 100]])).is_err());
+    }
+
+    #[test]
+    fn tracks_merge_in_time_order() {
+        let tempo = [&[0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20][..], &END].concat();
+        let melody = [&[0x83, 0x60, 0x90, 72, 90, 0x83, 0x60, 0x80, 72, 0][..], &END].concat();
+        let bass = [&[0x00, 0x91, 48, 90, 0x87, 0x40, 0x81, 48, 0][..], &END].concat();
+        let file = MidiFile::parse(&smf(&[&tempo, &melody, &bass])).unwrap();
+        assert_eq!(
+            messages(&file),
+            vec![(0.0, [0x91, 48, 90]), (0.5, [0x90, 72, 90]), (1.0, [0x80, 72, 0]), (1.0, [0x81, 48, 0])]
+        );
+        assert_eq!(file.notes().len(), 2);
+        assert_eq!(file.duration(), 1.0);
+    }
+
+    #[test]
+    fn tempo_changes_apply_from_their_tick() {
+        // A quarter at 120 BPM, then 60 BPM from tick 480 on
+        let track = [
+            &[0x00, 0x90, 60, 90, 0x83, 0x60, 0x80, 60, 0][..],
+            &[0x00, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40],
+            &[0x00, 0x90, 62, 90, 0x83, 0x60, 0x80, 62, 0],
+            &END,
+        ]
+        .concat();
+        let file = MidiFile::parse(&smf(&[&track])).unwrap();
+        let times: Vec<f64> = file.messages().iter().map(|m| m.time).collect();
+        assert_eq!(times, vec![0.0, 0.5, 0.5, 1.5]);
+        // Only a tempo at the very start counts as the song's tempo
+        assert_eq!(file.tempo(), 120.0);
+    }
+
+    #[test]
+    fn percussion_channel_is_skipped() {
+        let track = [&[0x00, 0x99, 36, 100, 0x00, 0x90, 60, 100, 0x83, 0x60, 0x89, 36, 0, 0x00, 0x80, 60, 0][..], &END].concat();
+        let file = MidiFile::parse(&smf(&[&track])).unwrap();
+        assert_eq!(messages(&file), vec![(0.0, [0x90, 60, 100]), (0.5, [0x80, 60, 0])]);
+    }
+
+    #[test]
+    fn only_notes_and_the_pedal_play() {
+        // Program change, volume, reverb send and pitch bend at the start, as most files have
+        let setup = [0x00, 0xC0, 40, 0x00, 0xB0, 7, 127, 0x00, 0xB0, 91, 100, 0x00, 0xE0, 0, 80];
+        let track = [&setup[..], &[0x00, 0xB0, 64, 127, 0x00, 0x90, 60, 100, 0x83, 0x60, 0xB0, 64, 0], &END].concat();
+        let file = MidiFile::parse(&smf(&[&track])).unwrap();
+        // The note is let go at the end, having no note-off of its own
+        assert_eq!(
+            messages(&file),
+            vec![(0.0, [0xB0, 64, 127]), (0.0, [0x90, 60, 100]), (0.5, [0xB0, 64, 0]), (0.5, [0x80, 60, 0])]
+        );
+    }
+
+    #[test]
+    fn a_key_held_on_two_channels_ends_with_the_last() {
+        let first = [&[0x00, 0x90, 60, 90, 0x83, 0x60, 0x80, 60, 0][..], &END].concat();
+        let second = [&[0x00, 0x91, 60, 90, 0x87, 0x40, 0x81, 60, 0][..], &END].concat();
+        let file = MidiFile::parse(&smf(&[&first, &second])).unwrap();
+        assert_eq!(messages(&file), vec![(0.0, [0x90, 60, 90]), (0.0, [0x91, 60, 90]), (1.0, [0x81, 60, 0])]);
+    }
+}
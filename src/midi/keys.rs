@@ -0,0 +1,158 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// How many finished notes to remember for the piano roll.
+const HISTORY_LEN: usize = 512;
+
+/// A note as it was played, for drawing it after the fact.
+#[derive(Debug, Clone, Copy)]
+pub struct PlayedNote {
+    pub note: u8,
+    pub velocity: u8,
+    pub start: Instant,
+    /// `None` while the key is still down
+    pub end: Option<Instant>,
+}
+
+/// Which keys are currently down, and which are only still sounding because
+/// the sustain pedal is held. Every input (MIDI controller, computer keyboard,
+/// on-screen keys) goes through `handle_midi_message`, which keeps this up to date.
+#[derive(Clone)]
+pub struct KeyState {
+    // Velocity of each held key, 0 when the key is up
+    held: [u8; 128],
+    sustained: [bool; 128],
+    pedal: bool,
+    history: VecDeque<PlayedNote>,
+}
+
+impl Default for KeyState {
+    fn default() -> Self {
+        KeyState {
+            held: [0; 128],
+            sustained: [false; 128],
+            pedal: false,
+            history: VecDeque::with_capacity(HISTORY_LEN),
+        }
+    }
+}
+
+impl KeyState {
+    pub fn note_on(&mut self, note: u8, velocity: u8) {
+        let note = note as usize & 0x7F;
+        self.held[note] = velocity.max(1);
+        self.sustained[note] = false;
+
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(PlayedNote {
+            note: note as u8,
+            velocity: self.held[note],
+            start: Instant::now(),
+            end: None,
+        });
+    }
+
+    pub fn note_off(&mut self, note: u8) {
+        let note = note as usize & 0x7F;
+        if self.held[note] > 0 && self.pedal {
+            self.sustained[note] = true;
+        }
+        self.held[note] = 0;
+
+        let now = Instant::now();
+        if let Some(played) = self.history.iter_mut().rev().find(|p| p.note as usize == note) {
+            played.end.get_or_insert(now);
+        }
+    }
+
+    pub fn set_pedal(&mut self, down: bool) {
+        self.pedal = down;
+        if !down {
+            self.sustained = [false; 128];
+        }
+    }
+
+    pub fn pedal(&self) -> bool {
+        self.pedal
+    }
+
+    /// Velocity the key was struck with, if it is held down.
+    pub fn velocity(&self, note: u8) -> Option<u8> {
+        match self.held[note as usize & 0x7F] {
+            0 => None,
+            v => Some(v),
+        }
+    }
+
+    /// Released, but still ringing on the pedal.
+    pub fn is_sustained(&self, note: u8) -> bool {
+        self.sustained[note as usize & 0x7F]
+    }
+
+    /// Notes that are sounding right now, held or sustained, lowest first.
+    pub fn sounding_notes(&self) -> Vec<u8> {
+        (0..128u8)
+            .filter(|n| self.held[*n as usize] > 0 || self.sustained[*n as usize])
+            .collect()
+    }
+
+    /// Recently played notes, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &PlayedNote> {
+        self.history.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn note_on_and_off() {
+        let mut keys = KeyState::default();
+        keys.note_on(64, 90);
+        keys.note_on(60, 0);
+        assert_eq!(keys.velocity(64), Some(90));
+        // A zero velocity still counts as struck
+        assert_eq!(keys.velocity(60), Some(1));
+        assert_eq!(keys.sounding_notes(), vec![60, 64]);
+
+        keys.note_off(64);
+        assert_eq!(keys.velocity(64), None);
+        assert!(!keys.is_sustained(64));
+        assert_eq!(keys.sounding_notes(), vec![60]);
+    }
+
+    #[test]
+    fn pedal_sustains_released_keys_until_it_lifts() {
+        let mut keys = KeyState::default();
+        keys.note_on(60, 80);
+        keys.set_pedal(true);
+        keys.note_off(60);
+        // Keys that weren't down don't start sounding on a note-off
+        keys.note_off(62);
+        assert!(keys.is_sustained(60));
+        assert!(!keys.is_sustained(62));
+        assert_eq!(keys.sounding_notes(), vec![60]);
+
+        // Striking it again while sustained holds it again
+        keys.note_on(60, 70);
+        assert!(!keys.is_sustained(60));
+        keys.note_off(60);
+
+        keys.set_pedal(false);
+        assert!(!keys.pedal());
+        assert!(keys.sounding_notes().is_empty());
+    }
+
+    #[test]
+    fn history_records_when_notes_end() {
+        let mut keys = KeyState::default();
+        keys.note_on(60, 80);
+        keys.note_on(67, 80);
+        keys.note_off(60);
+        let ended: Vec<(u8, bool)> = keys.history().map(|p| (p.note, p.end.is_some())).collect();
+        assert_eq!(ended, vec![(60, true), (67, false)]);
+    }
+}
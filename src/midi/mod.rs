@@ -1,76 +1,276 @@
+pub mod calibration;
+pub mod file;
+pub mod keys;
+pub mod mts;
+pub mod scala;
+pub mod tap_tempo;
+pub mod transpose;
+pub mod tuning;
+pub mod velocity;
+pub mod zones;
+
 use anyhow::{Context, Result};
 use log::{info, warn};
 use midir::{MidiInput, MidiInputConnection};
 use rustysynth::Synthesizer;
+use std::collections::BTreeMap;
 use std::sync::{Arc, Mutex};
 
+pub use keys::KeyState;
+use tap_tempo::TapTempo;
+use transpose::Transpose;
+use velocity::{VelocityCurve, VelocityMap};
+use zones::{Routed, Zones, LIVE_CHANNELS, ZONE_CHANNELS};
+
+/// Everything a MIDI message ends up touching: the synthesizer that makes the
+/// sound, the key state the UI draws from, and the transpose and zones on the
+/// way to both. Cheap to clone into callbacks.
+#[derive(Clone)]
+pub struct MidiTarget {
+    pub synthesizer: Arc<Mutex<Synthesizer>>,
+    pub keys: Arc<Mutex<KeyState>>,
+    pub transpose: Arc<Transpose>,
+    /// Which channels notes go to and how they're tuned, and what each held key is playing
+    pub zones: Arc<Zones>,
+    /// Velocity curve of the connected input device, see `handle_input_message`
+    pub input_velocity: Arc<VelocityMap>,
+    /// The note or pedal that taps the tempo, and the taps so far
+    pub tap_tempo: Arc<TapTempo>,
+}
+
+impl MidiTarget {
+    pub fn new(synthesizer: Arc<Mutex<Synthesizer>>) -> Self {
+        MidiTarget {
+            synthesizer,
+            keys: Arc::new(Mutex::new(KeyState::default())),
+            transpose: Arc::new(Transpose::default()),
+            zones: Arc::new(Zones::default()),
+            input_velocity: Arc::new(VelocityMap::default()),
+            tap_tempo: Arc::new(TapTempo::default()),
+        }
+    }
+}
+
 pub struct MidiEngine {
-    _connection: Option<MidiInputConnection<()>>,
+    target: MidiTarget,
+    /// Port asked for, `None` for whichever comes first
+    port_name: Option<String>,
+    connection: Option<(String, MidiInputConnection<()>)>,
+    /// Velocity curve for each device that has one, by port name
+    velocity_curves: BTreeMap<String, VelocityCurve>,
 }
 
 impl MidiEngine {
-    pub fn init(synthesizer: Arc<Mutex<Synthesizer>>) -> Result<Self> {
+    /// Connects to the input port named `port_name` (see `find_port`), or the first one there is.
+    pub fn init(target: MidiTarget, port_name: Option<&str>) -> Result<Self> {
         info!("Initializing MIDI Engine...");
 
+        let mut engine = MidiEngine {
+            target,
+            port_name: port_name.map(str::to_string),
+            connection: None,
+            velocity_curves: BTreeMap::new(),
+        };
+        engine.reconnect()?;
+
+        if engine.connection.is_none() {
+            match port_name {
+                Some(wanted) => warn!("MIDI port {:?} not found.", wanted),
+                None => warn!("No available MIDI ports found."),
+            }
+        }
+        Ok(engine)
+    }
+
+    /// Velocity curves by device, applied to whichever one is connected now or later.
+    pub fn set_velocity_curves(&mut self, curves: BTreeMap<String, VelocityCurve>) {
+        self.velocity_curves = curves;
+        if let Some((name, _)) = &self.connection {
+            self.target.input_velocity.set(&self.velocity_curves.get(name).cloned().unwrap_or_default());
+        }
+    }
+
+    /// Name of the port currently connected, if any.
+    pub fn connected_port(&self) -> Option<&str> {
+        self.connection.as_ref().map(|(name, _)| name.as_str())
+    }
+
+    /// Drops the connection if its port has gone away, and connects if not
+    /// connected. Call it now and then to pick up devices plugged in later.
+    pub fn reconnect(&mut self) -> Result<()> {
         let mut midi_in = MidiInput::new("Toy Piano Input").context("Failed to create MIDI input")?;
         midi_in.ignore(midir::Ignore::None);
 
         let ports = midi_in.ports();
-        let connection = if let Some(port) = ports.first() {
-            let port_name = midi_in.port_name(port).unwrap_or_else(|_| "Unknown".to_string());
-            info!("Connecting to MIDI port: {}", port_name);
-
-            let conn = midi_in.connect(
-                port,
-                "toy-piano-input",
-                move |_stamp, message, _| {
-                    handle_midi_message(message, &synthesizer);
-                },
-                (),
-            ).map_err(|e| anyhow::anyhow!("Failed to connect to MIDI port: {}", e))?;
-            
-            Some(conn)
-        } else {
-            warn!("No available MIDI ports found.");
-            None
+        let names: Vec<String> = ports
+            .iter()
+            .map(|p| midi_in.port_name(p).unwrap_or_else(|_| "Unknown".to_string()))
+            .collect();
+
+        if let Some((name, _)) = &self.connection {
+            if names.contains(name) {
+                return Ok(());
+            }
+            warn!("MIDI port {} went away.", name);
+            self.connection = None;
+        }
+
+        let index = match &self.port_name {
+            Some(wanted) => find_port(&names, wanted),
+            None => (!ports.is_empty()).then_some(0),
         };
+        let Some(index) = index else {
+            return Ok(());
+        };
+
+        info!("Connecting to MIDI port: {}", names[index]);
+        let curve = self.velocity_curves.get(&names[index]).cloned().unwrap_or_default();
+        self.target.input_velocity.set(&curve);
+        let target = self.target.clone();
+        let conn = midi_in.connect(
+            &ports[index],
+            "toy-piano-input",
+            move |_stamp, message, _| {
+                handle_input_message(message, &target);
+            },
+            (),
+        ).map_err(|e| anyhow::anyhow!("Failed to connect to MIDI port: {}", e))?;
+
+        self.connection = Some((names[index].clone(), conn));
+        Ok(())
+    }
+}
 
-        Ok(MidiEngine {
-            _connection: connection,
-        })
+/// Names of the MIDI input ports currently available.
+pub fn list_ports() -> Result<Vec<String>> {
+    let midi_in = MidiInput::new("Toy Piano scanner").context("Failed to create MIDI input")?;
+    Ok(midi_in
+        .ports()
+        .iter()
+        .map(|p| midi_in.port_name(p).unwrap_or_else(|_| "Unknown".to_string()))
+        .collect())
+}
+
+/// Index of the port called `wanted`. Falls back to a case-insensitive partial
+/// match, since some systems append client numbers that change between boots.
+pub fn find_port(ports: &[String], wanted: &str) -> Option<usize> {
+    let wanted_lower = wanted.to_lowercase();
+    ports
+        .iter()
+        .position(|name| name == wanted)
+        .or_else(|| ports.iter().position(|name| name.to_lowercase().contains(&wanted_lower)))
+}
+
+/// Handles a message from the input device: takes out tempo taps, applies
+/// its velocity curve to note-ons, then goes on like `handle_midi_message`.
+/// Other sources (files, on-screen keys) have their velocities already and
+/// skip the curve.
+pub fn handle_input_message(message: &[u8], target: &MidiTarget) {
+    if target.tap_tempo.handle_input(message) {
+        return;
+    }
+    match *message {
+        [status, note, velocity] if status & 0xF0 == 0x90 && velocity > 0 => {
+            handle_midi_message(&[status, note, target.input_velocity.map(velocity)], target);
+        }
+        _ => handle_midi_message(message, target),
     }
 }
 
-pub fn handle_midi_message(message: &[u8], synthesizer: &Arc<Mutex<Synthesizer>>) {
-    if message.len() < 3 {
+pub fn handle_midi_message(message: &[u8], target: &MidiTarget) {
+    if message.len() < 2 {
+        return;
+    }
+    if message[0] == 0xF0 {
+        // Of SysEx, only tuning messages mean anything to us
+        if let Some(change) = mts::parse(message) {
+            target.zones.update_tuning(|tuning| change.apply(tuning));
+        }
         return;
     }
 
     let status = message[0] & 0xF0;
-    let note = message[1] as i32;
-    let velocity = message[2] as i32;
+    let data1 = message[1] as i32;
+    let data2 = message.get(2).copied().unwrap_or(0) as i32;
+
+    // Program change and channel pressure are the only two-byte channel messages
+    if message.len() < 3 && status != 0xC0 && status != 0xD0 {
+        return;
+    }
 
     // Use a short scope for the lock to avoid blocking the audio thread too long
     // Ideally, we would use a ring buffer here too, but for a "simple" app with direct locking,
     // rustysynth's mutex is generally fast enough if we don't do I/O.
     match status {
-        0x90 => { // Note On
-            if velocity > 0 {
-                if let Ok(mut synth) = synthesizer.lock() {
-                    synth.note_on(0, note, velocity);
+        0x90 if data2 > 0 => { // Note On
+            let note_on = target.zones.note_on(data1 as u8, target.transpose.offset());
+            notes_off(target, note_on.stale);
+            if let Ok(mut synth) = target.synthesizer.lock() {
+                for (channel, fine_tune) in note_on.retune {
+                    for [status, d1, d2] in tuning::fine_tune_messages(fine_tune) {
+                        synth.process_midi_message(channel, status as i32, d1 as i32, d2 as i32);
+                    }
+                }
+            }
+            for voice in note_on.voices.into_iter().flatten() {
+                if let Ok(mut keys) = target.keys.lock() {
+                    keys.note_on(voice.note, data2 as u8);
                 }
-            } else {
-                // Velocity 0 is effectively Note Off
-                if let Ok(mut synth) = synthesizer.lock() {
-                    synth.note_off(0, note);
+                if let Ok(mut synth) = target.synthesizer.lock() {
+                    synth.note_on(voice.channel, voice.synth_note as i32, data2);
                 }
             }
         }
-        0x80 => { // Note Off
-            if let Ok(mut synth) = synthesizer.lock() {
-                synth.note_off(0, note);
+        0x80 | 0x90 => { // Note Off (Note On with velocity 0 is effectively Note Off)
+            // Release what the key was played as, even if the transpose or zones have changed since
+            notes_off(target, target.zones.note_off(data1 as u8));
+        }
+        // Program Change and Bank Select pick the main sound; the second zone's is set with the zones
+        0xC0 | 0xB0 if status == 0xC0 || data1 == 0 || data1 == 32 => {
+            if let Ok(mut synth) = target.synthesizer.lock() {
+                for channel in ZONE_CHANNELS[0] {
+                    synth.process_midi_message(channel, status as i32, data1, data2);
+                }
+            }
+        }
+        // Data entry and the (N)RPNs it goes to would retune channels behind the zones' backs,
+        // and volume fine would undo the zones' volumes
+        0xB0 if matches!(data1, 6 | 38 | 39 | 43 | 96..=101) => {}
+        0xB0 | 0xE0 => { // Control Change, Pitch Bend
+            // The device's volume and expression scale both zones through expression,
+            // so each zone keeps its own volume
+            let (data1, data2) = match (status, data1) {
+                (0xB0, 7 | 11) => (11, target.zones.set_device_level(data1 as u8, data2 as u8) as i32),
+                _ => (data1, data2),
+            };
+            if status == 0xB0 && data1 == 121 { // Reset All Controllers, which sets expression back to full
+                target.zones.set_device_level(7, 127);
+                target.zones.set_device_level(11, 127);
+            }
+            if status == 0xB0 && data1 == 64 { // Sustain pedal
+                if let Ok(mut keys) = target.keys.lock() {
+                    keys.set_pedal(data2 >= 64);
+                }
+                target.zones.set_pedal(data2 >= 64);
+            }
+            // The pedal, wheels and effect sends apply to both zones
+            if let Ok(mut synth) = target.synthesizer.lock() {
+                for channel in LIVE_CHANNELS {
+                    synth.process_midi_message(channel, status as i32, data1, data2);
+                }
             }
         }
         _ => {}
     }
 }
+
+fn notes_off(target: &MidiTarget, routed: Routed) {
+    for voice in routed.into_iter().flatten() {
+        if let Ok(mut keys) = target.keys.lock() {
+            keys.note_off(voice.note);
+        }
+        if let Ok(mut synth) = target.synthesizer.lock() {
+            synth.note_off(voice.channel, voice.synth_note as i32);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! MIDI Tuning Standard messages, which let microtonal software retune the
+//! piano over MIDI: single note tuning changes, bulk tuning dumps and
+//! scale/octave tunings. Tuning programs and banks aren't kept apart; every
+//! change goes to the one tuning being played, from the next note-on.
+
+use log::warn;
+
+use super::tuning::Tuning;
+
+/// A tuning change from a SysEx message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TuningChange {
+    /// New pitches of some keys, as fractional MIDI note numbers
+    Keys(Vec<(u8, f64)>),
+    /// Cents each pitch class sits from equal temperament at A440, in every octave
+    Octave([f64; 12]),
+}
+
+impl TuningChange {
+    pub fn apply(&self, tuning: &mut Tuning) {
+        match self {
+            TuningChange::Keys(keys) => {
+                for &(key, pitch) in keys {
+                    tuning.set_pitch(key, Some(pitch));
+                }
+            }
+            TuningChange::Octave(cents) => {
+                for key in 0..128u8 {
+                    tuning.set_pitch(key, Some(key as f64 + cents[key as usize % 12] / 100.0));
+                }
+            }
+        }
+    }
+}
+
+/// The tuning change in `message`, if it's an MTS message we handle. Any
+/// device ID is accepted.
+pub fn parse(message: &[u8]) -> Option<TuningChange> {
+    let [0xF0, realtime @ (0x7E | 0x7F), _device, 0x08, format, ref data @ .., 0xF7] = *message else {
+        return None;
+    };
+    let non_realtime = realtime == 0x7E;
+    match format {
+        // Bulk dump: program, 16 byte name, 128 pitches, checksum
+        0x01 if non_realtime => {
+            let pitches = data.get(17..17 + 128 * 3)?;
+            let checksum = *data.get(17 + 128 * 3)?;
+            let expected = message[1..message.len() - 2].iter().fold(0, |sum, b| sum ^ b) & 0x7F;
+            if checksum != expected {
+                warn!("Ignoring an MTS bulk dump with a bad checksum.");
+                return None;
+            }
+            let keys = pitches
+                .chunks(3)
+                .enumerate()
+                .filter_map(|(key, pitch)| Some((key as u8, frequency_data(pitch)?)))
+                .collect();
+            Some(TuningChange::Keys(keys))
+        }
+        // Single note tuning change: program, count, then key and pitch for each
+        0x02 if !non_realtime => single_notes(data.get(1..)?),
+        // The same with a bank before the program
+        0x07 => single_notes(data.get(2..)?),
+        // Scale/octave tuning, 1 byte per pitch class: channel mask, then cents + 64
+        0x08 => {
+            let offsets = data.get(3..15)?;
+            Some(TuningChange::Octave(std::array::from_fn(|i| offsets[i] as f64 - 64.0)))
+        }
+        // Scale/octave tuning, 2 bytes per pitch class: 14 bits over ±100 cents
+        0x09 => {
+            let offsets = data.get(3..27)?;
+            Some(TuningChange::Octave(std::array::from_fn(|i| {
+                let value = (offsets[2 * i] as u16) << 7 | offsets[2 * i + 1] as u16;
+                (value as f64 - 8192.0) / 8192.0 * 100.0
+            })))
+        }
+        _ => None,
+    }
+}
+
+/// Key count, then key and pitch for each.
+fn single_notes(data: &[u8]) -> Option<TuningChange> {
+    let (&count, changes) = data.split_first()?;
+    let changes = changes.get(..count as usize * 4)?;
+    let keys = changes
+        .chunks(4)
+        .filter_map(|change| Some((change[0] & 0x7F, frequency_data(&change[1..])?)))
+        .collect();
+    Some(TuningChange::Keys(keys))
+}
+
+/// A semitone and a 14-bit fraction of one; `None` for 7F 7F 7F, "no change".
+fn frequency_data(bytes: &[u8]) -> Option<f64> {
+    match *bytes {
+        [0x7F, 0x7F, 0x7F] => None,
+        [semitone, msb, lsb] => Some(semitone as f64 + ((msb as u16) << 7 | lsb as u16) as f64 / 16384.0),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_note_tuning_change() {
+        // Key 60 to 60 + 8192/16384, a quarter tone up; key 61 unchanged
+        let message = [0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, 0x02, 60, 60, 0x40, 0x00, 61, 0x7F, 0x7F, 0x7F, 0xF7];
+        assert_eq!(parse(&message), Some(TuningChange::Keys(vec![(60, 60.5)])));
+
+        let mut tuning = Tuning::default();
+        parse(&message).unwrap().apply(&mut tuning);
+        assert_eq!(tuning.pitch(60), Some(60.5));
+        assert_eq!(tuning.pitch(61), Some(61.0));
+    }
+
+    #[test]
+    fn bulk_dump_checks_its_checksum() {
+        let mut message = vec![0xF0, 0x7E, 0x00, 0x08, 0x01, 0x00];
+        message.extend_from_slice(b"Quarter tones   ");
+        for key in 0..128u8 {
+            // Each key a quarter tone above the last, from key 0 at note 0
+            let pitch = key as u32 * 8192;
+            message.extend_from_slice(&[(pitch / 16384) as u8, ((pitch % 16384) >> 7) as u8, (pitch & 0x7F) as u8]);
+        }
+        let checksum = message[1..].iter().fold(0, |sum, b| sum ^ b) & 0x7F;
+        message.extend_from_slice(&[checksum, 0xF7]);
+
+        let Some(TuningChange::Keys(keys)) = parse(&message) else {
+            panic!("bulk dump not recognized");
+        };
+        assert_eq!(keys.len(), 128);
+        assert_eq!(keys[3], (3, 1.5));
+
+        let last = message.len() - 2;
+        message[last] ^= 1;
+        assert_eq!(parse(&message), None);
+    }
+
+    #[test]
+    fn scale_octave_tunings() {
+        let mut one_byte = vec![0xF0, 0x7E, 0x7F, 0x08, 0x08, 0x03, 0x7F, 0x7F];
+        one_byte.extend_from_slice(&[64, 50, 64, 64, 78, 64, 64, 64, 64, 64, 64, 64, 0xF7]);
+        let Some(TuningChange::Octave(cents)) = parse(&one_byte) else {
+            panic!("1-byte scale/octave tuning not recognized");
+        };
+        assert_eq!((cents[0], cents[1], cents[4]), (0.0, -14.0, 14.0));
+
+        let mut two_byte = vec![0xF0, 0x7F, 0x7F, 0x08, 0x09, 0x03, 0x7F, 0x7F];
+        for pitch_class in 0..12 {
+            // 0x2000 is no change, 0x3000 half a semitone up
+            two_byte.extend_from_slice(if pitch_class == 9 { &[0x60, 0x00] } else { &[0x40, 0x00] });
+        }
+        two_byte.push(0xF7);
+        let mut tuning = Tuning::default();
+        parse(&two_byte).unwrap().apply(&mut tuning);
+        assert_eq!(tuning.pitch(69), Some(69.5));
+        assert_eq!(tuning.pitch(60), Some(60.0));
+    }
+
+    #[test]
+    fn other_sysex_is_ignored() {
+        // GM System On
+        assert_eq!(parse(&[0xF0, 0x7E, 0x7F, 0x09, 0x01, 0xF7]), None);
+        // Truncated single note change
+        assert_eq!(parse(&[0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, 0x02, 60, 60, 0x40, 0x00, 0xF7]), None);
+    }
+}
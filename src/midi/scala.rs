@@ -0,0 +1,261 @@
+//! Scala scale (`.scl`) and keyboard mapping (`.kbm`) files, the common
+//! format for microtonal tunings: <https://www.huygens-fokker.org/scala/scl_format.html>.
+//! A scale lists the pitches of one period above its 1/1; a mapping says
+//! which key plays which scale degree and what one key's frequency is.
+
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+/// The .scl file, and the .kbm file if there is one, of a Scala tuning.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScalaFiles {
+    pub scale: PathBuf,
+    pub mapping: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scale {
+    pub description: String,
+    /// Cents above the 1/1 of degrees 1 and up; the last is the period,
+    /// usually 1200.0
+    pub degrees: Vec<f64>,
+}
+
+impl Scale {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+        // Scala files are often Latin-1
+        Scale::parse(&String::from_utf8_lossy(&text)).with_context(|| format!("Failed to parse {:?}", path))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text.lines().filter(|line| !line.starts_with('!'));
+        let description = lines.next().context("missing description")?.trim().to_string();
+        let count_line = lines.next().context("missing note count")?;
+        let count: usize = first_word(count_line)
+            .parse()
+            .with_context(|| format!("bad note count {:?}", count_line.trim()))?;
+        if count == 0 {
+            bail!("a scale needs at least one note");
+        }
+
+        let degrees = lines
+            .take(count)
+            .map(|line| parse_pitch(first_word(line)).with_context(|| format!("bad pitch {:?}", line.trim())))
+            .collect::<Result<Vec<f64>>>()?;
+        if degrees.len() < count {
+            bail!("expected {} notes, found {}", count, degrees.len());
+        }
+        if degrees[count - 1] <= 0.0 {
+            bail!("the period has to be above the 1/1");
+        }
+        Ok(Scale { description, degrees })
+    }
+
+    /// Notes per period, counting the 1/1 but not the period itself.
+    pub fn len(&self) -> usize {
+        self.degrees.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.degrees.is_empty()
+    }
+
+    /// Cents of `degree` above the 1/1, counting on into further periods.
+    pub fn cents(&self, degree: i32) -> f64 {
+        let len = self.len() as i32;
+        let period = self.degrees[self.len() - 1];
+        let step = degree.rem_euclid(len);
+        let within = if step == 0 { 0.0 } else { self.degrees[step as usize - 1] };
+        degree.div_euclid(len) as f64 * period + within
+    }
+}
+
+/// A pitch is in cents if it has a period, otherwise a ratio like `3/2` or `2`.
+fn parse_pitch(word: &str) -> Result<f64> {
+    if word.contains('.') {
+        return Ok(word.parse()?);
+    }
+    let (numerator, denominator) = word.split_once('/').unwrap_or((word, "1"));
+    let numerator: f64 = numerator.parse::<u64>()? as f64;
+    let denominator: f64 = denominator.parse::<u64>()? as f64;
+    if numerator <= 0.0 || denominator <= 0.0 {
+        bail!("ratios have to be positive");
+    }
+    Ok(1200.0 * (numerator / denominator).log2())
+}
+
+fn first_word(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+fn field<'a, T: std::str::FromStr>(words: &mut impl Iterator<Item = &'a str>, what: &str) -> Result<T> {
+    let word = words.next().with_context(|| format!("missing {}", what))?;
+    word.parse().ok().with_context(|| format!("bad {} {:?}", what, word))
+}
+
+fn note<'a>(words: &mut impl Iterator<Item = &'a str>, what: &str) -> Result<u8> {
+    let note: u8 = field(words, what)?;
+    if note > 127 {
+        bail!("{} {} is past the MIDI range", what, note);
+    }
+    Ok(note)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardMapping {
+    /// Keys outside this range don't play
+    pub first_note: u8,
+    pub last_note: u8,
+    /// The key that plays degree 0 of the first mapping repeat
+    pub middle_note: u8,
+    pub reference_note: u8,
+    /// Frequency of `reference_note`, Hz
+    pub reference_frequency: f64,
+    /// Scale degree each repeat of the mapping moves up by
+    pub octave_degree: i32,
+    /// Scale degree of each key in a repeat, `None` for keys that don't
+    /// play. Empty maps keys to consecutive degrees.
+    pub keys: Vec<Option<i32>>,
+}
+
+impl KeyboardMapping {
+    /// What Scala uses without a .kbm: consecutive degrees from the 1/1 on
+    /// middle C, which is at its equal tempered pitch for an A4 of `reference`.
+    pub fn linear(reference: f64) -> Self {
+        KeyboardMapping {
+            first_note: 0,
+            last_note: 127,
+            middle_note: 60,
+            reference_note: 60,
+            reference_frequency: reference * 2f64.powf(-9.0 / 12.0),
+            octave_degree: 0,
+            keys: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+        KeyboardMapping::parse(&String::from_utf8_lossy(&text)).with_context(|| format!("Failed to parse {:?}", path))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut words = text
+            .lines()
+            .filter(|line| !line.starts_with('!') && !line.trim().is_empty())
+            .map(first_word);
+        let size: usize = field(&mut words, "map size")?;
+        let first_note = note(&mut words, "first note")?;
+        let last_note = note(&mut words, "last note")?;
+        let middle_note = note(&mut words, "middle note")?;
+        let reference_note = note(&mut words, "reference note")?;
+        let reference_frequency: f64 = field(&mut words, "reference frequency")?;
+        if reference_frequency <= 0.0 {
+            bail!("the reference frequency has to be positive");
+        }
+        let octave_degree = field(&mut words, "octave degree")?;
+
+        let mut keys = Vec::with_capacity(size);
+        for _ in 0..size {
+            // Files may stop early; the keys left out don't play
+            let key = match words.next() {
+                None | Some("x") | Some("X") => None,
+                Some(word) => Some(word.parse().with_context(|| format!("bad scale degree {:?}", word))?),
+            };
+            keys.push(key);
+        }
+        Ok(KeyboardMapping {
+            first_note,
+            last_note,
+            middle_note,
+            reference_note,
+            reference_frequency,
+            octave_degree,
+            keys,
+        })
+    }
+
+    /// Cents of `key` above the scale's 1/1 on the middle note, `None` if it doesn't play.
+    pub fn cents(&self, scale: &Scale, key: u8) -> Option<f64> {
+        if key < self.first_note || key > self.last_note {
+            return None;
+        }
+        let offset = key as i32 - self.middle_note as i32;
+        if self.keys.is_empty() {
+            return Some(scale.cents(offset));
+        }
+        let size = self.keys.len() as i32;
+        let degree = self.keys[offset.rem_euclid(size) as usize]?;
+        let octave = if self.octave_degree > 0 { scale.cents(self.octave_degree) } else { scale.cents(scale.len() as i32) };
+        Some(offset.div_euclid(size) as f64 * octave + scale.cents(degree))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EQUAL_12: &str = "! 12-TET.scl\n!\n12 tone equal temperament\n 12\n!\n 100.0\n 200.\n 300.0\n 400.0\n 500.0\n 600.0\n 700.0\n 800.0\n 900.0\n 1000.0\n 1100.0\n 2/1\n";
+    const PTOLEMY: &str = "! ptolemy.scl\nPtolemy Intense Diatonic\n7\n9/8\n5/4\n4/3\n3/2\n5/3\n15/8\n2\n";
+    const BOHLEN_PIERCE: &str = "! bohlen-p.scl\nBohlen-Pierce, just\n13\n27/25\n25/21\n9/7\n7/5\n75/49\n5/3\n9/5\n49/25\n15/7\n7/3\n63/25\n25/9\n3/1\n";
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn parses_cents_and_ratios() {
+        let scale = Scale::parse(EQUAL_12).unwrap();
+        assert_eq!(scale.description, "12 tone equal temperament");
+        assert_eq!(scale.len(), 12);
+        assert!(close(scale.cents(7), 700.0));
+        assert!(close(scale.cents(12), 1200.0));
+        assert!(close(scale.cents(-1), -100.0));
+
+        let ptolemy = Scale::parse(PTOLEMY).unwrap();
+        assert!(close(ptolemy.cents(2), 386.314));
+        assert!(close(ptolemy.cents(4), 701.955));
+        assert!(close(ptolemy.cents(9), 1200.0 + 386.314));
+    }
+
+    #[test]
+    fn non_octave_period() {
+        let scale = Scale::parse(BOHLEN_PIERCE).unwrap();
+        assert_eq!(scale.len(), 13);
+        // The tritave, 3/1
+        assert!(close(scale.cents(13), 1901.955));
+        assert!(close(scale.cents(1), 133.238));
+        assert!(close(scale.cents(26), 2.0 * 1901.955));
+    }
+
+    #[test]
+    fn bad_scales_are_errors() {
+        assert!(Scale::parse("").is_err());
+        assert!(Scale::parse("short\n3\n100.0\n200.0\n").is_err());
+        assert!(Scale::parse("nonsense\n1\nabc\n").is_err());
+        assert!(Scale::parse("negative\n1\n-3/2\n").is_err());
+    }
+
+    #[test]
+    fn linear_mapping_puts_the_1_1_on_middle_c() {
+        let scale = Scale::parse(PTOLEMY).unwrap();
+        let mapping = KeyboardMapping::linear(440.0);
+        assert!(close(mapping.cents(&scale, 60).unwrap(), 0.0));
+        // Seven keys up is the next octave
+        assert!(close(mapping.cents(&scale, 67).unwrap(), 1200.0));
+        assert!(close(mapping.reference_frequency, 261.6256));
+    }
+
+    #[test]
+    fn mapping_skips_unmapped_keys() {
+        // White keys only, A4 = 440
+        let kbm = "! white.kbm\n12\n0\n127\n60\n69\n440.0\n7\n0\nx\n1\nx\n2\n3\nx\n4\nx\n5\nx\n6\n";
+        let mapping = KeyboardMapping::parse(kbm).unwrap();
+        let scale = Scale::parse(PTOLEMY).unwrap();
+        assert_eq!(mapping.reference_note, 69);
+        assert_eq!(mapping.cents(&scale, 61), None);
+        assert!(close(mapping.cents(&scale, 64).unwrap(), 386.314));
+        assert!(close(mapping.cents(&scale, 72).unwrap(), 1200.0));
+        assert!(close(mapping.cents(&scale, 48).unwrap(), -1200.0));
+    }
+}
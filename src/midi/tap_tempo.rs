@@ -0,0 +1,244 @@
+//! Tap tempo: the time between taps, on the on-screen button or a note or
+//! pedal of the input device, sets the metronome and the tempo songs play at.
+//! The audio thread picks new tempos up with `take_pending`, so they land
+//! between blocks like everything else it plays.
+
+use log::info;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::audio::metronome::{MAX_BPM, MIN_BPM};
+
+/// Taps the tempo is averaged over, so one early or late tap only nudges it.
+const MAX_TAPS: usize = 6;
+/// A pause longer than a beat at the slowest tempo starts counting afresh.
+const MAX_GAP: Duration = Duration::from_secs(3);
+
+/// What on the input device taps the tempo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapTrigger {
+    Note(u8),
+    /// A controller number, e.g. a footswitch's
+    Control(u8),
+}
+
+impl fmt::Display for TapTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TapTrigger::Note(note) => write!(f, "note {}", note),
+            TapTrigger::Control(control) => write!(f, "CC {}", control),
+        }
+    }
+}
+
+/// Recent tap times and the tempo they make.
+#[derive(Debug, Default)]
+pub struct Taps {
+    times: VecDeque<Instant>,
+}
+
+impl Taps {
+    /// Records a tap at `now`. Returns the tempo in BPM once there are two
+    /// taps to measure between.
+    pub fn tap(&mut self, now: Instant) -> Option<f64> {
+        if self.times.back().is_some_and(|last| now.saturating_duration_since(*last) > MAX_GAP) {
+            self.times.clear();
+        }
+        self.times.push_back(now);
+        if self.times.len() > MAX_TAPS {
+            self.times.pop_front();
+        }
+        let (first, last) = (self.times.front()?, self.times.back()?);
+        if self.times.len() < 2 {
+            return None;
+        }
+        let beat = last.saturating_duration_since(*first).as_secs_f64() / (self.times.len() - 1) as f64;
+        (beat > 0.0).then(|| (60.0 / beat).clamp(MIN_BPM, MAX_BPM))
+    }
+}
+
+/// A tempo that was tapped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TappedTempo {
+    pub bpm: f64,
+    /// Counts up with each tempo tapped, to tell a new one from the last
+    pub serial: u64,
+}
+
+/// Tap tempo state shared by the MIDI input, the UI and the audio thread.
+#[derive(Debug, Default)]
+pub struct TapTempo {
+    state: Mutex<TapState>,
+}
+
+#[derive(Debug, Default)]
+struct TapState {
+    trigger: Option<TapTrigger>,
+    /// Whether the next note or pedal press becomes the trigger
+    learning: bool,
+    /// Whether the trigger pedal is down, so only presses tap
+    pedal_down: bool,
+    taps: Taps,
+    /// Tapped and not yet picked up by the audio thread
+    pending: Option<f64>,
+    latest: Option<TappedTempo>,
+}
+
+impl TapState {
+    fn tap(&mut self, now: Instant) {
+        if let Some(bpm) = self.taps.tap(now) {
+            self.pending = Some(bpm);
+            let serial = self.latest.map_or(0, |latest| latest.serial + 1);
+            self.latest = Some(TappedTempo { bpm, serial });
+        }
+    }
+}
+
+impl TapTempo {
+    pub fn tap(&self) {
+        self.tap_at(Instant::now());
+    }
+
+    /// Taps at a given time, e.g. when the tap was timestamped elsewhere.
+    pub fn tap_at(&self, now: Instant) {
+        self.state.lock().unwrap().tap(now);
+    }
+
+    pub fn set_trigger(&self, trigger: Option<TapTrigger>) {
+        let mut state = self.state.lock().unwrap();
+        state.trigger = trigger;
+        state.learning = false;
+        state.pedal_down = false;
+    }
+
+    pub fn trigger(&self) -> Option<TapTrigger> {
+        self.state.lock().unwrap().trigger
+    }
+
+    /// Makes the next note or pedal press on the input device the trigger.
+    pub fn learn(&self) {
+        self.state.lock().unwrap().learning = true;
+    }
+
+    pub fn is_learning(&self) -> bool {
+        self.state.lock().unwrap().learning
+    }
+
+    /// Taps if `message` from the input device is the trigger, or learns it as
+    /// the trigger. Returns whether the message was used up here; the trigger
+    /// doesn't play, so the pedal or key can't also sustain or sound.
+    pub fn handle_input(&self, message: &[u8]) -> bool {
+        self.handle_input_at(message, Instant::now())
+    }
+
+    /// `handle_input` for a message that arrived at `now`.
+    pub fn handle_input_at(&self, message: &[u8], now: Instant) -> bool {
+        let [status, data1, data2] = *message else {
+            return false;
+        };
+        let mut state = self.state.lock().unwrap();
+        if state.learning {
+            let trigger = match status & 0xF0 {
+                0x90 if data2 > 0 => TapTrigger::Note(data1),
+                0xB0 if data2 >= 64 => TapTrigger::Control(data1),
+                _ => return false,
+            };
+            info!("Tapping the tempo with {}", trigger);
+            state.trigger = Some(trigger);
+            state.learning = false;
+            state.pedal_down = true;
+            return true;
+        }
+        match (state.trigger, status & 0xF0) {
+            (Some(TapTrigger::Note(note)), 0x80 | 0x90) if data1 == note => {
+                if status & 0xF0 == 0x90 && data2 > 0 {
+                    state.tap(now);
+                }
+                true
+            }
+            (Some(TapTrigger::Control(control)), 0xB0) if data1 == control => {
+                let down = data2 >= 64;
+                if down && !state.pedal_down {
+                    state.tap(now);
+                }
+                state.pedal_down = down;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The tempo tapped since the last call, if there was one.
+    pub fn take_pending(&self) -> Option<f64> {
+        self.state.lock().unwrap().pending.take()
+    }
+
+    /// The last tempo tapped.
+    pub fn latest(&self) -> Option<TappedTempo> {
+        self.state.lock().unwrap().latest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tap_every(taps: &mut Taps, start: Instant, millis: &[u64]) -> Option<f64> {
+        let mut time = start;
+        let mut bpm = None;
+        for &gap in millis {
+            time += Duration::from_millis(gap);
+            bpm = taps.tap(time);
+        }
+        bpm
+    }
+
+    #[test]
+    fn averages_the_last_taps() {
+        let mut taps = Taps::default();
+        let start = Instant::now();
+        assert_eq!(taps.tap(start), None);
+        // 500 ms is 120 BPM; one tap 60 ms late only pulls it a little
+        let bpm = tap_every(&mut taps, start, &[500, 500, 560, 440, 500]).unwrap();
+        assert!((bpm - 120.0).abs() < 0.01, "{}", bpm);
+        let nudged = tap_every(&mut taps, start + Duration::from_millis(2500), &[560]).unwrap();
+        assert!(nudged < 120.0 && nudged > 115.0, "{}", nudged);
+    }
+
+    #[test]
+    fn a_long_pause_starts_again() {
+        let mut taps = Taps::default();
+        let start = Instant::now();
+        tap_every(&mut taps, start, &[0, 500, 500]);
+        // A new tempo after a pause isn't averaged with the old one
+        let bpm = tap_every(&mut taps, start + Duration::from_secs(10), &[0, 1000]).unwrap();
+        assert!((bpm - 60.0).abs() < 0.01, "{}", bpm);
+    }
+
+    #[test]
+    fn pedal_taps_on_press_only() {
+        let tap_tempo = TapTempo::default();
+        tap_tempo.set_trigger(Some(TapTrigger::Control(67)));
+        assert!(tap_tempo.handle_input(&[0xB0, 67, 127]));
+        assert!(tap_tempo.handle_input(&[0xB0, 67, 127]));
+        assert!(tap_tempo.handle_input(&[0xB0, 67, 0]));
+        // Only one press so far, so no tempo yet
+        assert_eq!(tap_tempo.latest(), None);
+        assert!(!tap_tempo.handle_input(&[0xB0, 64, 127]));
+        assert!(!tap_tempo.handle_input(&[0x90, 60, 100]));
+    }
+
+    #[test]
+    fn learns_the_next_press() {
+        let tap_tempo = TapTempo::default();
+        tap_tempo.learn();
+        assert!(!tap_tempo.handle_input(&[0x80, 36, 0]));
+        assert!(tap_tempo.handle_input(&[0x90, 36, 90]));
+        assert_eq!(tap_tempo.trigger(), Some(TapTrigger::Note(36)));
+        assert!(!tap_tempo.is_learning());
+        // Its release doesn't reach the synthesizer either
+        assert!(tap_tempo.handle_input(&[0x80, 36, 0]));
+    }
+}
@@ -0,0 +1,39 @@
+//! Moving everything played up or down before it reaches the synthesizer.
+//! Which note each key ended up as is tracked in `zones`, so changing the
+//! transpose while keys are held can't leave notes stuck.
+
+use std::sync::atomic::{AtomicI32, Ordering};
+
+pub const MAX_TRANSPOSE: i32 = 24;
+pub const MAX_OCTAVE_SHIFT: i32 = 3;
+
+#[derive(Debug, Default)]
+pub struct Transpose {
+    semitones: AtomicI32,
+    octaves: AtomicI32,
+}
+
+impl Transpose {
+    /// Semitones, clamped to ±`MAX_TRANSPOSE`. Applies from the next note-on.
+    pub fn set_semitones(&self, semitones: i32) {
+        self.semitones.store(semitones.clamp(-MAX_TRANSPOSE, MAX_TRANSPOSE), Ordering::Relaxed);
+    }
+
+    pub fn semitones(&self) -> i32 {
+        self.semitones.load(Ordering::Relaxed)
+    }
+
+    /// Octaves, clamped to ±`MAX_OCTAVE_SHIFT`, on top of the transpose.
+    pub fn set_octaves(&self, octaves: i32) {
+        self.octaves.store(octaves.clamp(-MAX_OCTAVE_SHIFT, MAX_OCTAVE_SHIFT), Ordering::Relaxed);
+    }
+
+    pub fn octaves(&self) -> i32 {
+        self.octaves.load(Ordering::Relaxed)
+    }
+
+    /// Semitones to add to every note.
+    pub fn offset(&self) -> i32 {
+        self.semitones() + 12 * self.octaves()
+    }
+}
@@ -0,0 +1,226 @@
+//! Tuning the keyboard away from equal temperament at A4 = 440 Hz: a
+//! different reference pitch, historical temperaments in a chosen key, or a
+//! Scala scale (see `scala`). A tuning gives every key a pitch as a
+//! fractional MIDI note number. The synthesizer can only tune whole
+//! channels, so `zones` spreads notes that need different fine tunings
+//! across several channels.
+
+use anyhow::{bail, Result};
+use std::fmt;
+
+use super::scala::{KeyboardMapping, Scale};
+
+pub const DEFAULT_REFERENCE: f64 = 440.0;
+/// Range the A4 reference can be set in, Hz
+pub const MIN_REFERENCE: f64 = 380.0;
+pub const MAX_REFERENCE: f64 = 480.0;
+/// Reference pitches people ask for by name: French baroque, baroque,
+/// classical, "verdi", modern and the orchestral ones a little above it.
+pub const COMMON_REFERENCES: [f64; 7] = [392.0, 415.0, 430.0, 432.0, 440.0, 442.0, 443.0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Temperament {
+    #[default]
+    Equal,
+    Pythagorean,
+    Just,
+    Meantone,
+    WerckmeisterIII,
+    Vallotti,
+}
+
+impl Temperament {
+    pub const ALL: [Temperament; 6] = [
+        Temperament::Equal,
+        Temperament::Pythagorean,
+        Temperament::Just,
+        Temperament::Meantone,
+        Temperament::WerckmeisterIII,
+        Temperament::Vallotti,
+    ];
+
+    /// Cents above the tonic of each of the twelve notes from it upward.
+    fn cents(self) -> [f64; 12] {
+        match self {
+            Temperament::Equal => std::array::from_fn(|i| i as f64 * 100.0),
+            // Pure fifths from Eb up to G#, with the wolf between them
+            Temperament::Pythagorean => [
+                0.0, 113.685, 203.910, 294.135, 407.820, 498.045, 611.730, 701.955, 815.640, 905.865, 996.090, 1109.775,
+            ],
+            // 5-limit: 16/15, 9/8, 6/5, 5/4, 4/3, 45/32, 3/2, 8/5, 5/3, 9/5, 15/8
+            Temperament::Just => [
+                0.0, 111.731, 203.910, 315.641, 386.314, 498.045, 590.224, 701.955, 813.686, 884.359, 1017.596, 1088.269,
+            ],
+            // Quarter-comma: pure major thirds, fifths from Eb up to G#
+            Temperament::Meantone => [
+                0.0, 76.049, 193.157, 310.265, 386.314, 503.422, 579.471, 696.578, 772.627, 889.735, 1006.843, 1082.892,
+            ],
+            // C-G-D-A and B-F# narrowed by a quarter of the Pythagorean comma
+            Temperament::WerckmeisterIII => [
+                0.0, 90.225, 192.180, 294.135, 390.225, 498.045, 588.270, 696.090, 792.180, 888.270, 996.090, 1092.180,
+            ],
+            // F-C-G-D-A-E-B narrowed by a sixth of the Pythagorean comma, the rest pure
+            Temperament::Vallotti => [
+                0.0, 94.135, 196.090, 298.045, 392.180, 501.955, 592.180, 698.045, 796.090, 894.135, 1000.000, 1090.225,
+            ],
+        }
+    }
+}
+
+impl fmt::Display for Temperament {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Temperament::Equal => "Equal",
+            Temperament::Pythagorean => "Pythagorean",
+            Temperament::Just => "Just Intonation",
+            Temperament::Meantone => "Meantone (1/4 comma)",
+            Temperament::WerckmeisterIII => "Werckmeister III",
+            Temperament::Vallotti => "Vallotti",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TuningSettings {
+    /// Frequency of A4, Hz
+    pub reference: f64,
+    pub temperament: Temperament,
+    /// Pitch class the temperament is laid out from, 0 = C
+    pub tonic: u8,
+}
+
+impl Default for TuningSettings {
+    fn default() -> Self {
+        TuningSettings {
+            reference: DEFAULT_REFERENCE,
+            temperament: Temperament::Equal,
+            tonic: 0,
+        }
+    }
+}
+
+/// The pitch each key plays, as a MIDI note number with cents as the
+/// fraction: 69.0 is 440 Hz, 69.5 a quarter tone above. `None` for keys
+/// that don't play.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tuning {
+    pitches: [Option<f64>; 128],
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning::new(&TuningSettings::default())
+    }
+}
+
+impl Tuning {
+    /// The temperament laid out from the tonic, then moved as a whole so A4
+    /// sounds at the reference pitch.
+    pub fn new(settings: &TuningSettings) -> Self {
+        let cents = settings.temperament.cents();
+        let tonic = settings.tonic as usize % 12;
+        // Cents each pitch class sits away from equal temperament
+        let deviation = |key: usize| {
+            let degree = (key + 12 - tonic) % 12;
+            cents[degree] - degree as f64 * 100.0
+        };
+        let reference = settings.reference.clamp(MIN_REFERENCE, MAX_REFERENCE);
+        let shift = 12.0 * (reference / DEFAULT_REFERENCE).log2() - deviation(69) / 100.0;
+        Tuning {
+            pitches: std::array::from_fn(|key| Some(key as f64 + deviation(key) / 100.0 + shift)),
+        }
+    }
+
+    /// A Scala scale laid out on the keys by `mapping`.
+    pub fn from_scala(scale: &Scale, mapping: &KeyboardMapping) -> Result<Self> {
+        let Some(reference) = mapping.cents(scale, mapping.reference_note) else {
+            bail!("the reference note {} isn't mapped to the scale", mapping.reference_note);
+        };
+        let reference_pitch = 69.0 + 12.0 * (mapping.reference_frequency / DEFAULT_REFERENCE).log2();
+        Ok(Tuning {
+            pitches: std::array::from_fn(|key| {
+                let cents = mapping.cents(scale, key as u8)?;
+                Some(reference_pitch + (cents - reference) / 100.0)
+            }),
+        })
+    }
+
+    pub fn pitch(&self, key: u8) -> Option<f64> {
+        self.pitches[key as usize & 0x7F]
+    }
+
+    pub fn set_pitch(&mut self, key: u8, pitch: Option<f64>) {
+        self.pitches[key as usize & 0x7F] = pitch;
+    }
+
+    pub fn frequency(&self, key: u8) -> Option<f64> {
+        Some(DEFAULT_REFERENCE * 2f64.powf((self.pitch(key)? - 69.0) / 12.0))
+    }
+}
+
+/// Control changes that set a channel's fine tuning to `semitones` (within
+/// ±1) through RPN 1, then deselect it so later data entry can't change it.
+pub fn fine_tune_messages(semitones: f64) -> [[u8; 3]; 6] {
+    let value = (8192.0 + semitones.clamp(-1.0, 1.0) * 8192.0).round().clamp(0.0, 16383.0) as u16;
+    [
+        [0xB0, 101, 0],
+        [0xB0, 100, 1],
+        [0xB0, 6, (value >> 7) as u8],
+        [0xB0, 38, (value & 0x7F) as u8],
+        [0xB0, 101, 127],
+        [0xB0, 100, 127],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cents_between(tuning: &Tuning, low: u8, high: u8) -> f64 {
+        (tuning.pitch(high).unwrap() - tuning.pitch(low).unwrap()) * 100.0
+    }
+
+    #[test]
+    fn reference_moves_a4() {
+        let baroque = Tuning::new(&TuningSettings { reference: 415.0, ..TuningSettings::default() });
+        assert!((baroque.frequency(69).unwrap() - 415.0).abs() < 1e-9);
+        assert!((baroque.frequency(81).unwrap() - 830.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn a4_stays_at_the_reference_in_any_temperament() {
+        for temperament in Temperament::ALL {
+            let tuning = Tuning::new(&TuningSettings { temperament, tonic: 2, ..TuningSettings::default() });
+            assert!((tuning.frequency(69).unwrap() - 440.0).abs() < 1e-9, "{}", temperament);
+        }
+    }
+
+    #[test]
+    fn just_intonation_in_d_has_a_pure_third_on_d() {
+        let tuning = Tuning::new(&TuningSettings { temperament: Temperament::Just, tonic: 2, ..TuningSettings::default() });
+        // D4 to F#4 is 5/4, D4 to A4 is 3/2
+        assert!((cents_between(&tuning, 62, 66) - 386.314).abs() < 0.01);
+        assert!((cents_between(&tuning, 62, 69) - 701.955).abs() < 0.01);
+        assert!((cents_between(&tuning, 62, 74) - 1200.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn scala_scale_follows_its_mapping() {
+        let degrees: String = (1..=19).map(|step| format!("{:.4}\n", step as f64 * 1200.0 / 19.0)).collect();
+        let scale = Scale::parse(&format!("19-EDO\n19\n{}", degrees)).unwrap();
+
+        let tuning = Tuning::from_scala(&scale, &KeyboardMapping::linear(440.0)).unwrap();
+        assert!((tuning.frequency(60).unwrap() - 261.6256).abs() < 1e-3);
+        assert!((tuning.frequency(79).unwrap() - 2.0 * 261.6256).abs() < 1e-3);
+        assert!((cents_between(&tuning, 60, 61) - 1200.0 / 19.0).abs() < 1e-3);
+
+        let unmapped = KeyboardMapping { reference_note: 61, keys: vec![Some(0), None], ..KeyboardMapping::linear(440.0) };
+        assert!(Tuning::from_scala(&scale, &unmapped).is_err());
+    }
+
+    #[test]
+    fn fine_tune_is_centred_on_8192() {
+        assert_eq!(fine_tune_messages(0.0)[2..4], [[0xB0, 6, 64], [0xB0, 38, 0]]);
+        assert_eq!(fine_tune_messages(-1.0)[2..4], [[0xB0, 6, 0], [0xB0, 38, 0]]);
+    }
+}
@@ -0,0 +1,212 @@
+//! Velocity curves: how hard a controller's keys have to be hit for a given
+//! loudness. Keyboards differ a lot here, so each input device gets its own.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Exponents of the built-in curves, applied to velocity scaled to 0..1.
+const SOFT_EXPONENT: f32 = 0.6;
+const HARD_EXPONENT: f32 = 1.7;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum VelocityCurve {
+    /// Velocity as the keyboard sends it
+    #[default]
+    Linear,
+    /// Loud notes come easier, for stiff keyboards
+    Soft,
+    /// Loud notes take more force, for light keyboards
+    Hard,
+    /// Every note at the same velocity, like an organ
+    Fixed(u8),
+    /// Straight lines between (in, out) points, sorted by input, from 0 to 127
+    Custom(Vec<(u8, u8)>),
+}
+
+impl VelocityCurve {
+    /// A custom curve through `points`, sorted, one point per input, and
+    /// stretched to cover inputs from 0 to 127.
+    pub fn custom(mut points: Vec<(u8, u8)>) -> VelocityCurve {
+        points.iter_mut().for_each(|(x, y)| (*x, *y) = ((*x).min(127), (*y).clamp(1, 127)));
+        points.sort_by_key(|&(x, _)| x);
+        points.dedup_by_key(|&mut (x, _)| x);
+        match (points.first().copied(), points.last().copied()) {
+            (Some((first_x, first_y)), Some((last_x, last_y))) => {
+                if first_x > 0 {
+                    points.insert(0, (0, first_y));
+                }
+                if last_x < 127 {
+                    points.push((127, last_y));
+                }
+            }
+            _ => points = vec![(0, 1), (127, 127)],
+        }
+        VelocityCurve::Custom(points)
+    }
+
+    /// The velocity to play for `velocity` from the keyboard. Never 0, since
+    /// a note-on with velocity 0 would be a note-off.
+    pub fn apply(&self, velocity: u8) -> u8 {
+        let velocity = velocity.min(127);
+        let power = |exponent: f32| (127.0 * (velocity as f32 / 127.0).powf(exponent)).round() as u8;
+        let out = match self {
+            VelocityCurve::Linear => velocity,
+            VelocityCurve::Soft => power(SOFT_EXPONENT),
+            VelocityCurve::Hard => power(HARD_EXPONENT),
+            VelocityCurve::Fixed(fixed) => *fixed,
+            VelocityCurve::Custom(points) => interpolate(points, velocity),
+        };
+        out.clamp(1, 127)
+    }
+
+    /// The kind of curve, without its parameters, for choosing in a list.
+    pub fn kind(&self) -> CurveKind {
+        match self {
+            VelocityCurve::Linear => CurveKind::Linear,
+            VelocityCurve::Soft => CurveKind::Soft,
+            VelocityCurve::Hard => CurveKind::Hard,
+            VelocityCurve::Fixed(_) => CurveKind::Fixed,
+            VelocityCurve::Custom(_) => CurveKind::Custom,
+        }
+    }
+
+    /// A curve of `kind` that starts out close to this one, so switching to
+    /// Custom gives points to drag rather than a blank slate.
+    pub fn convert(&self, kind: CurveKind) -> VelocityCurve {
+        match kind {
+            CurveKind::Linear => VelocityCurve::Linear,
+            CurveKind::Soft => VelocityCurve::Soft,
+            CurveKind::Hard => VelocityCurve::Hard,
+            CurveKind::Fixed => match self {
+                VelocityCurve::Fixed(_) => self.clone(),
+                _ => VelocityCurve::Fixed(self.apply(100)),
+            },
+            CurveKind::Custom => match self {
+                VelocityCurve::Custom(_) => self.clone(),
+                _ => VelocityCurve::Custom([0, 32, 64, 96, 127].map(|x| (x, self.apply(x))).to_vec()),
+            },
+        }
+    }
+}
+
+/// Piecewise linear lookup. Points should be sorted by input; inputs outside
+/// them take the nearest end's output.
+fn interpolate(points: &[(u8, u8)], velocity: u8) -> u8 {
+    let Some(&(first_in, first_out)) = points.first() else {
+        return velocity;
+    };
+    if velocity <= first_in {
+        return first_out;
+    }
+    for pair in points.windows(2) {
+        let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+        if velocity <= x1 {
+            if x1 == x0 {
+                return y1;
+            }
+            let t = (velocity - x0) as f32 / (x1 - x0) as f32;
+            return (y0 as f32 + (y1 as f32 - y0 as f32) * t).round() as u8;
+        }
+    }
+    points[points.len() - 1].1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurveKind {
+    Linear,
+    Soft,
+    Hard,
+    Fixed,
+    Custom,
+}
+
+impl CurveKind {
+    pub const ALL: [CurveKind; 5] = [
+        CurveKind::Linear,
+        CurveKind::Soft,
+        CurveKind::Hard,
+        CurveKind::Fixed,
+        CurveKind::Custom,
+    ];
+}
+
+impl fmt::Display for CurveKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CurveKind::Linear => "Linear",
+            CurveKind::Soft => "Soft",
+            CurveKind::Hard => "Hard",
+            CurveKind::Fixed => "Fixed",
+            CurveKind::Custom => "Custom",
+        })
+    }
+}
+
+/// The current input device's curve as a lookup table, shared with the MIDI
+/// callback without a lock.
+#[derive(Debug)]
+pub struct VelocityMap {
+    table: [AtomicU8; 128],
+}
+
+impl Default for VelocityMap {
+    fn default() -> Self {
+        VelocityMap {
+            table: std::array::from_fn(|velocity| AtomicU8::new(velocity as u8)),
+        }
+    }
+}
+
+impl VelocityMap {
+    pub fn set(&self, curve: &VelocityCurve) {
+        for (velocity, entry) in self.table.iter().enumerate() {
+            entry.store(curve.apply(velocity as u8), Ordering::Relaxed);
+        }
+    }
+
+    pub fn map(&self, velocity: u8) -> u8 {
+        self.table[velocity as usize & 0x7F].load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_curves_keep_the_ends_and_bend_the_middle() {
+        for curve in [VelocityCurve::Linear, VelocityCurve::Soft, VelocityCurve::Hard] {
+            assert_eq!(curve.apply(127), 127);
+            assert_eq!(curve.apply(0), 1);
+        }
+        assert_eq!(VelocityCurve::Linear.apply(64), 64);
+        assert!(VelocityCurve::Soft.apply(64) > 64);
+        assert!(VelocityCurve::Hard.apply(64) < 64);
+        assert_eq!(VelocityCurve::Fixed(90).apply(10), 90);
+    }
+
+    #[test]
+    fn custom_curve_interpolates_between_points() {
+        let curve = VelocityCurve::custom(vec![(100, 127), (20, 40)]);
+        assert_eq!(curve, VelocityCurve::Custom(vec![(0, 40), (20, 40), (100, 127), (127, 127)]));
+        assert_eq!(curve.apply(10), 40);
+        assert_eq!(curve.apply(60), 84);
+        assert_eq!(curve.apply(110), 127);
+    }
+
+    #[test]
+    fn curves_never_turn_a_note_on_into_a_note_off() {
+        let silent = VelocityCurve::custom(vec![(0, 0), (127, 0)]);
+        assert_eq!(silent.apply(100), 1);
+        assert_eq!(VelocityCurve::Hard.apply(5), 1);
+    }
+
+    #[test]
+    fn switching_to_custom_starts_from_the_current_curve() {
+        let VelocityCurve::Custom(points) = VelocityCurve::Soft.convert(CurveKind::Custom) else {
+            panic!("expected a custom curve");
+        };
+        assert_eq!(points.len(), 5);
+        assert!(points.iter().all(|&(x, y)| y == VelocityCurve::Soft.apply(x)));
+    }
+}
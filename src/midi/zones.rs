@@ -0,0 +1,368 @@
+//! Split and layer modes. The keyboard's notes go to one or two zones, each
+//! with its own sound, transpose and volume: split sends the keys below the
+//! split point to the second zone, layer sends every key to both.
+//!
+//! Each zone has a few synthesizer channels, all set to its sound. The
+//! synthesizer tunes whole channels, so a note whose tuning needs a different
+//! fine tune than what's playing goes to another of the zone's channels,
+//! retuned for it. A channel is only retuned while nothing rings on it, if
+//! it can be helped: keys down, and keys the sustain pedal holds, keep it.
+
+use std::fmt;
+use std::sync::Mutex;
+
+use super::tuning::Tuning;
+
+/// Channels of the main zone, which plays everything in single mode, and the
+/// second zone. Channel 9 is left out: the synthesizer keeps it for drums.
+pub const ZONE_CHANNELS: [[i32; 7]; 2] = [[0, 2, 3, 4, 5, 6, 7], [1, 8, 10, 11, 12, 13, 14]];
+/// The channel each zone uses first, and the only one in equal temperament at A440.
+pub const MAIN_CHANNEL: i32 = ZONE_CHANNELS[0][0];
+pub const SECOND_CHANNEL: i32 = ZONE_CHANNELS[1][0];
+/// Channels live input can reach. Controllers and the pedal go to all of them.
+pub const LIVE_CHANNELS: [i32; 14] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 10, 11, 12, 13, 14];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyboardMode {
+    #[default]
+    Single,
+    Split,
+    Layer,
+}
+
+impl KeyboardMode {
+    pub const ALL: [KeyboardMode; 3] = [KeyboardMode::Single, KeyboardMode::Split, KeyboardMode::Layer];
+}
+
+impl fmt::Display for KeyboardMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            KeyboardMode::Single => "Single",
+            KeyboardMode::Split => "Split",
+            KeyboardMode::Layer => "Layer",
+        })
+    }
+}
+
+/// The second zone's sound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Zone {
+    pub bank: u8,
+    pub program: u8,
+    /// Semitones, on top of the global transpose
+    pub transpose: i32,
+    /// Channel volume, 0-127 like CC7
+    pub volume: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZoneSettings {
+    pub mode: KeyboardMode,
+    /// Lowest key of the upper (main) zone in split mode
+    pub split_point: u8,
+    /// The main zone plays the selected sound; these adjust it per zone
+    pub main_transpose: i32,
+    pub main_volume: u8,
+    /// Lower zone in split mode, the added sound in layer mode
+    pub second: Zone,
+}
+
+impl Default for ZoneSettings {
+    /// Split at middle C with an acoustic bass an octave down, when turned on.
+    fn default() -> Self {
+        ZoneSettings {
+            mode: KeyboardMode::Single,
+            split_point: 60,
+            main_transpose: 0,
+            main_volume: 100,
+            second: Zone {
+                bank: 0,
+                program: 32,
+                transpose: -12,
+                volume: 100,
+            },
+        }
+    }
+}
+
+impl ZoneSettings {
+    /// Control changes and program change that set the channels up. The main
+    /// zone's sound is the selected preset, so only its volume is here.
+    pub fn messages(&self) -> Vec<(i32, [u8; 3])> {
+        let main = ZONE_CHANNELS[0].map(|channel| (channel, [0xB0, 7, self.main_volume.min(127)]));
+        let second = ZONE_CHANNELS[1].map(|channel| {
+            [
+                (channel, [0xB0, 7, self.second.volume.min(127)]),
+                (channel, [0xB0, 0, self.second.bank & 0x7F]),
+                (channel, [0xC0, self.second.program & 0x7F, 0]),
+            ]
+        });
+        main.into_iter().chain(second.into_iter().flatten()).collect()
+    }
+}
+
+/// A note playing on the synthesizer for one key in one zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Voice {
+    pub channel: i32,
+    /// The key after transposing, which is what the screen shows
+    pub note: u8,
+    /// The note the synthesizer plays, the nearest to the tuned pitch
+    pub synth_note: u8,
+}
+
+/// A key's voice in each zone, `None` where it isn't playing.
+pub type Routed = [Option<Voice>; 2];
+
+/// What a note-on turned into.
+#[derive(Debug, Default)]
+pub struct NoteOn {
+    pub voices: Routed,
+    /// What the key was still playing from before, to release first
+    pub stale: Routed,
+    /// Channels to retune before playing, and their fine tune in semitones
+    pub retune: Vec<(i32, f64)>,
+}
+
+/// The zone layout, the tuning and, for each key that's down, what it's playing where.
+#[derive(Debug)]
+pub struct Zones {
+    state: Mutex<ZoneState>,
+}
+
+#[derive(Debug)]
+struct ZoneState {
+    mode: KeyboardMode,
+    split_point: u8,
+    transposes: [i32; 2],
+    tuning: Tuning,
+    sounding: [Routed; 128],
+    pedal: bool,
+    /// Notes on each channel let go of while the pedal is down, so still ringing
+    sustained: [u16; 16],
+    /// Fine tune each channel is set to, semitones
+    fine_tunes: [f64; 16],
+    /// When each channel last started or let go of a note, to retune the one
+    /// quiet the longest first, its release tail most likely over
+    last_used: [u64; 16],
+    notes_played: u64,
+    /// Volume (CC7) and expression (CC11) last sent by the input device
+    device_levels: [u8; 2],
+}
+
+impl Default for Zones {
+    fn default() -> Self {
+        Zones {
+            state: Mutex::new(ZoneState {
+                mode: KeyboardMode::Single,
+                split_point: 60,
+                transposes: [0; 2],
+                tuning: Tuning::default(),
+                sounding: [[None; 2]; 128],
+                pedal: false,
+                sustained: [0; 16],
+                fine_tunes: [0.0; 16],
+                last_used: [0; 16],
+                notes_played: 0,
+                device_levels: [127; 2],
+            }),
+        }
+    }
+}
+
+impl ZoneState {
+    /// One of `zone`'s channels set to `fine_tune`: one already tuned so, or
+    /// else the longest unused that isn't holding notes, retuned. If every
+    /// channel is holding notes, the longest unused one is retuned anyway.
+    fn channel_for(&mut self, zone: usize, fine_tune: f64, retune: &mut Vec<(i32, f64)>) -> i32 {
+        let channels = ZONE_CHANNELS[zone];
+        let tuned = channels
+            .into_iter()
+            .find(|&channel| (self.fine_tunes[channel as usize] - fine_tune).abs() < 1e-6);
+        let channel = tuned.unwrap_or_else(|| {
+            let holding = |channel: i32| {
+                self.sustained[channel as usize] > 0
+                    || self.sounding.iter().flatten().flatten().any(|voice| voice.channel == channel)
+            };
+            let free = channels.into_iter().filter(|&channel| !holding(channel));
+            let channel = free
+                .min_by_key(|&channel| self.last_used[channel as usize])
+                .or_else(|| channels.into_iter().min_by_key(|&channel| self.last_used[channel as usize]))
+                .unwrap_or(channels[0]);
+            self.fine_tunes[channel as usize] = fine_tune;
+            retune.push((channel, fine_tune));
+            channel
+        });
+        self.notes_played += 1;
+        self.last_used[channel as usize] = self.notes_played;
+        channel
+    }
+
+    /// Notes being let go of: they ring on while the pedal is down.
+    fn release(&mut self, routed: &Routed) {
+        for voice in routed.iter().flatten() {
+            let channel = voice.channel as usize;
+            if self.pedal {
+                self.sustained[channel] += 1;
+            } else {
+                self.notes_played += 1;
+                self.last_used[channel] = self.notes_played;
+            }
+        }
+    }
+
+    fn set_pedal(&mut self, down: bool) {
+        self.pedal = down;
+        if !down {
+            self.notes_played += 1;
+            for (sustained, last_used) in self.sustained.iter_mut().zip(&mut self.last_used) {
+                if *sustained > 0 {
+                    *sustained = 0;
+                    *last_used = self.notes_played;
+                }
+            }
+        }
+    }
+}
+
+impl Zones {
+    /// Applies from the next note-on; keys already down finish where they started.
+    pub fn set(&self, settings: &ZoneSettings) {
+        let mut state = self.state.lock().unwrap();
+        state.mode = settings.mode;
+        state.split_point = settings.split_point;
+        state.transposes = [settings.main_transpose, settings.second.transpose];
+    }
+
+    /// Applies from the next note-on, like `set`.
+    pub fn set_tuning(&self, tuning: Tuning) {
+        self.state.lock().unwrap().tuning = tuning;
+    }
+
+    /// Changes part of the tuning, e.g. for MTS messages. Applies from the next note-on.
+    pub fn update_tuning(&self, change: impl FnOnce(&mut Tuning)) {
+        change(&mut self.state.lock().unwrap().tuning);
+    }
+
+    /// Where `key` plays, transposed by `offset` plus each zone's own transpose,
+    /// then tuned. Notes pushed off the MIDI range, and keys the tuning leaves
+    /// out, don't play.
+    pub fn note_on(&self, key: u8, offset: i32) -> NoteOn {
+        let mut state = self.state.lock().unwrap();
+        let zones = match state.mode {
+            KeyboardMode::Single => [true, false],
+            KeyboardMode::Split if key < state.split_point => [false, true],
+            KeyboardMode::Split => [true, false],
+            KeyboardMode::Layer => [true, true],
+        };
+
+        // Released first, so the channels it held are free to retune, unless the pedal holds it
+        let stale = std::mem::take(&mut state.sounding[key as usize & 0x7F]);
+        state.release(&stale);
+        let mut note_on = NoteOn { stale, ..NoteOn::default() };
+        for (zone, _) in zones.into_iter().enumerate().filter(|(_, plays)| *plays) {
+            let shifted = key as i32 + offset + state.transposes[zone];
+            let Some(note) = u8::try_from(shifted).ok().filter(|n| *n < 128) else {
+                continue;
+            };
+            let Some(pitch) = state.tuning.pitch(note) else {
+                continue;
+            };
+            let Some(synth_note) = u8::try_from(pitch.round() as i32).ok().filter(|n| *n < 128) else {
+                continue;
+            };
+            let fine_tune = pitch - synth_note as f64;
+            let channel = state.channel_for(zone, fine_tune, &mut note_on.retune);
+            note_on.voices[zone] = Some(Voice { channel, note, synth_note });
+        }
+
+        state.sounding[key as usize & 0x7F] = note_on.voices;
+        // A voice that's the same as before is just struck again
+        for (old, new) in note_on.stale.iter_mut().zip(note_on.voices) {
+            if *old == new {
+                *old = None;
+            }
+        }
+        note_on
+    }
+
+    /// What `key` has been playing, however the zones or transpose have changed since.
+    pub fn note_off(&self, key: u8) -> Routed {
+        let mut state = self.state.lock().unwrap();
+        let routed = std::mem::take(&mut state.sounding[key as usize & 0x7F]);
+        state.release(&routed);
+        routed
+    }
+
+    /// Records the input device's volume (`control` 7) or expression (11) and
+    /// returns the expression that applies both, for every zone's channels.
+    pub fn set_device_level(&self, control: u8, value: u8) -> u8 {
+        let mut state = self.state.lock().unwrap();
+        state.device_levels[usize::from(control != 7)] = value.min(127);
+        let [volume, expression] = state.device_levels.map(u32::from);
+        ((volume * expression + 63) / 127) as u8
+    }
+
+    /// The sustain pedal. The notes it holds keep their channels' tuning until it lifts.
+    pub fn set_pedal(&self, down: bool) {
+        self.state.lock().unwrap().set_pedal(down);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keys from 60 up, each with its own fine tune, so each needs a channel of its own.
+    fn zones() -> Zones {
+        let mut tuning = Tuning::default();
+        for (i, key) in (60..80u8).enumerate() {
+            tuning.set_pitch(key, Some(key as f64 + 0.01 * (i + 1) as f64));
+        }
+        let zones = Zones::default();
+        zones.set_tuning(tuning);
+        zones
+    }
+
+    fn channel(note_on: &NoteOn) -> i32 {
+        note_on.voices[0].unwrap().channel
+    }
+
+    #[test]
+    fn held_keys_keep_their_channels() {
+        let zones = zones();
+        let channels: Vec<i32> = (60..67).map(|key| channel(&zones.note_on(key, 0))).collect();
+        assert_eq!(channels, ZONE_CHANNELS[0]);
+        // Every channel is busy: the longest unused is retuned anyway
+        let note_on = zones.note_on(67, 0);
+        assert_eq!(channel(&note_on), MAIN_CHANNEL);
+        assert!(matches!(note_on.retune[..], [(MAIN_CHANNEL, fine_tune)] if (fine_tune - 0.08).abs() < 1e-9));
+    }
+
+    #[test]
+    fn pedal_held_notes_keep_their_channels() {
+        let zones = zones();
+        let channels: Vec<i32> = (60..67).map(|key| channel(&zones.note_on(key, 0))).collect();
+        zones.note_off(61);
+        zones.set_pedal(true);
+        zones.note_off(60);
+        // The first channel is the longest unused, but the pedal holds its note
+        assert_eq!(channel(&zones.note_on(67, 0)), channels[1]);
+        zones.set_pedal(false);
+        assert_eq!(channel(&zones.note_on(68, 0)), channels[0]);
+    }
+
+    #[test]
+    fn the_channel_quiet_longest_is_retuned() {
+        let zones = zones();
+        let channels: Vec<i32> = (60..67).map(|key| channel(&zones.note_on(key, 0))).collect();
+        // Let go of the newest note first, the oldest last
+        for key in (60..67).rev() {
+            zones.note_off(key);
+        }
+        assert_eq!(channel(&zones.note_on(67, 0)), channels[6]);
+        // Striking a key again keeps its channel and tuning
+        let again = zones.note_on(62, 0);
+        assert_eq!((channel(&again), again.retune.len()), (channels[2], 0));
+    }
+}
@@ -0,0 +1,622 @@
+pub mod toml;
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::audio::convolution::{ConvolutionSettings, MAX_PRE_DELAY_MS};
+use crate::audio::effects::compressor::{self, CompressorSettings};
+use crate::audio::effects::eq::{self, BandKind, EqBand};
+use crate::audio::effects::MasterEffects;
+use crate::audio::jingle::StartupJingle;
+use crate::audio::metronome::{self, MetronomeSettings, Subdivision};
+use crate::audio::synth_effects::SynthEffects;
+use crate::midi::transpose::{MAX_OCTAVE_SHIFT, MAX_TRANSPOSE};
+use crate::midi::scala::ScalaFiles;
+use crate::midi::tap_tempo::TapTrigger;
+use crate::midi::tuning::{self, Temperament, TuningSettings};
+use crate::midi::velocity::VelocityCurve;
+use crate::midi::zones::{KeyboardMode, Zone, ZoneSettings};
+use toml::Document;
+
+/// Bumped whenever a key is renamed or changes meaning; `migrate` brings older files up to date.
+pub const SETTINGS_VERSION: i64 = 1;
+
+const DEFAULT_THEME: &str = "deep-purple";
+
+/// Everything the app remembers between launches.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub midi_device: Option<String>,
+    /// Velocity curve of each MIDI input device that isn't linear, by port name
+    pub velocity_curves: BTreeMap<String, VelocityCurve>,
+    pub audio_device: Option<String>,
+    pub soundfont: Option<PathBuf>,
+    /// SoundFont bank and program played on the keyboard
+    pub bank: u8,
+    pub program: u8,
+    /// Master volume, 1.0 is unity gain
+    pub volume: f32,
+    /// Semitones
+    pub transpose: i32,
+    /// Octaves, on top of the transpose
+    pub octave_shift: i32,
+    /// Split or layer mode, and the second zone's sound
+    pub zones: ZoneSettings,
+    /// Reference pitch and temperament
+    pub tuning: TuningSettings,
+    /// Scala scale played instead of the temperament
+    pub scala: Option<ScalaFiles>,
+    pub synth_effects: SynthEffects,
+    /// WAV file for the convolution reverb
+    pub impulse_response: Option<PathBuf>,
+    pub convolution: ConvolutionSettings,
+    /// EQ and compressor
+    pub master_effects: MasterEffects,
+    /// Tempo, time signature and sound of the metronome. It always starts off.
+    pub metronome: MetronomeSettings,
+    /// Note or pedal on the input device that taps the tempo
+    pub tap_trigger: Option<TapTrigger>,
+    pub startup_jingle: StartupJingle,
+    /// Name of the color theme. Only "deep-purple" exists so far.
+    pub theme: String,
+    pub window_width: f32,
+    pub window_height: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            midi_device: None,
+            velocity_curves: BTreeMap::new(),
+            audio_device: None,
+            soundfont: None,
+            bank: 0,
+            program: 0,
+            volume: 1.0,
+            transpose: 0,
+            octave_shift: 0,
+            zones: ZoneSettings::default(),
+            tuning: TuningSettings::default(),
+            scala: None,
+            synth_effects: SynthEffects::default(),
+            impulse_response: None,
+            convolution: ConvolutionSettings::default(),
+            master_effects: MasterEffects::default(),
+            metronome: MetronomeSettings::default(),
+            tap_trigger: None,
+            startup_jingle: StartupJingle::default(),
+            theme: DEFAULT_THEME.to_string(),
+            window_width: 1040.0,
+            window_height: 860.0,
+        }
+    }
+}
+
+/// Platform config directory: `$XDG_CONFIG_HOME` (or `~/.config`) on Linux,
+/// `~/Library/Application Support` on macOS, `%APPDATA%` on Windows.
+pub fn config_dir() -> Option<PathBuf> {
+    let env_dir = |name: &str| std::env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+
+    if cfg!(target_os = "windows") {
+        env_dir("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env_dir("XDG_CONFIG_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".config")))
+    }
+    .map(|dir| dir.join("toy-piano"))
+}
+
+pub fn settings_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("settings.toml"))
+}
+
+impl Settings {
+    /// Loads the settings file, falling back to defaults if there is none.
+    /// A file that can't be read is left where it is, with a copy in
+    /// `settings.toml.bak` that the next save doesn't overwrite.
+    pub fn load() -> Self {
+        let Some(path) = settings_path() else {
+            warn!("No config directory found, settings won't be remembered.");
+            return Settings::default();
+        };
+        if !path.exists() {
+            return Settings::default();
+        }
+
+        match Self::load_from(&path) {
+            Ok(settings) => {
+                info!("Loaded settings from {:?}", path);
+                settings
+            }
+            Err(e) => {
+                warn!("Ignoring unreadable settings file: {:#}", e);
+                let backup = path.with_extension("toml.bak");
+                if let Err(e) = std::fs::copy(&path, &backup) {
+                    warn!("Failed to copy it to {:?}: {}", backup, e);
+                }
+                Settings::default()
+            }
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+        let mut doc = Document::parse(&text).with_context(|| format!("Failed to parse {:?}", path))?;
+        migrate(&mut doc)?;
+        Ok(Self::from_document(&doc))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = settings_path().context("No config directory to save settings in")?;
+        self.save_to(&path)
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+        }
+        // Write then rename, so a crash mid-save never leaves a half-written file
+        let temp = path.with_extension("toml.tmp");
+        std::fs::write(&temp, self.to_document().to_toml()).with_context(|| format!("Failed to write {:?}", temp))?;
+        std::fs::rename(&temp, path).with_context(|| format!("Failed to replace {:?}", path))?;
+        Ok(())
+    }
+
+    fn from_document(doc: &Document) -> Self {
+        let defaults = Settings::default();
+        let string = |table: &str, key: &str| doc.get(&[table], key).and_then(|v| v.as_str()).map(str::to_string);
+        let integer = |table: &str, key: &str| doc.get(&[table], key).and_then(|v| v.as_integer());
+        let float = |table: &str, key: &str| doc.get(&[table], key).and_then(|v| v.as_float());
+        let boolean = |table: &str, key: &str| doc.get(&[table], key).and_then(|v| v.as_bool());
+        let effects = defaults.synth_effects;
+        let convolution = defaults.convolution;
+
+        Settings {
+            midi_device: string("midi", "device"),
+            velocity_curves: velocity_curves_from_document(doc),
+            audio_device: string("audio", "device"),
+            soundfont: string("audio", "soundfont").map(PathBuf::from),
+            bank: integer("sound", "bank").map_or(defaults.bank, |v| v.clamp(0, 127) as u8),
+            program: integer("sound", "program").map_or(defaults.program, |v| v.clamp(0, 127) as u8),
+            volume: float("audio", "volume").map_or(defaults.volume, |v| v.clamp(0.0, 2.0) as f32),
+            transpose: integer("sound", "transpose")
+                .map_or(defaults.transpose, |v| v.clamp(-MAX_TRANSPOSE as i64, MAX_TRANSPOSE as i64) as i32),
+            octave_shift: integer("sound", "octave_shift")
+                .map_or(defaults.octave_shift, |v| v.clamp(-MAX_OCTAVE_SHIFT as i64, MAX_OCTAVE_SHIFT as i64) as i32),
+            zones: zones_from_document(doc, &defaults.zones),
+            tuning: tuning_from_document(doc, &defaults.tuning),
+            scala: string("tuning", "scale").map(|scale| ScalaFiles {
+                scale: PathBuf::from(scale),
+                mapping: string("tuning", "keyboard_mapping").map(PathBuf::from),
+            }),
+            synth_effects: SynthEffects {
+                reverb: boolean("effects", "reverb").unwrap_or(effects.reverb),
+                reverb_send: integer("effects", "reverb_send").map_or(effects.reverb_send, |v| v.clamp(0, 127) as u8),
+                chorus: boolean("effects", "chorus").unwrap_or(effects.chorus),
+                chorus_send: integer("effects", "chorus_send").map_or(effects.chorus_send, |v| v.clamp(0, 127) as u8),
+            },
+            impulse_response: string("convolution", "impulse_response").map(PathBuf::from),
+            convolution: ConvolutionSettings {
+                enabled: boolean("convolution", "enabled").unwrap_or(convolution.enabled),
+                mix: float("convolution", "mix").map_or(convolution.mix, |v| v.clamp(0.0, 1.0) as f32),
+                pre_delay_ms: float("convolution", "pre_delay_ms")
+                    .map_or(convolution.pre_delay_ms, |v| v.clamp(0.0, MAX_PRE_DELAY_MS as f64) as f32),
+            },
+            master_effects: master_effects_from_document(doc, &defaults.master_effects),
+            metronome: metronome_from_document(doc, &defaults.metronome),
+            tap_trigger: match (integer("tap_tempo", "note"), integer("tap_tempo", "control")) {
+                (Some(note), _) => Some(TapTrigger::Note(note.clamp(0, 127) as u8)),
+                (None, Some(control)) => Some(TapTrigger::Control(control.clamp(0, 127) as u8)),
+                (None, None) => None,
+            },
+            startup_jingle: StartupJingle {
+                enabled: boolean("startup", "jingle").unwrap_or(defaults.startup_jingle.enabled),
+                file: string("startup", "jingle_file").map(PathBuf::from),
+            },
+            theme: string("window", "theme").unwrap_or(defaults.theme),
+            window_width: float("window", "width").map_or(defaults.window_width, |v| v.max(200.0) as f32),
+            window_height: float("window", "height").map_or(defaults.window_height, |v| v.max(200.0) as f32),
+        }
+    }
+
+    fn to_document(&self) -> Document {
+        let mut doc = Document::default();
+        doc.set(&[], "version", SETTINGS_VERSION);
+
+        if let Some(device) = &self.midi_device {
+            doc.set(&["midi"], "device", device.as_str());
+        }
+        if let Some(device) = &self.audio_device {
+            doc.set(&["audio"], "device", device.as_str());
+        }
+        if let Some(soundfont) = &self.soundfont {
+            doc.set(&["audio"], "soundfont", soundfont.to_string_lossy().as_ref());
+        }
+        velocity_curves_to_document(&self.velocity_curves, &mut doc);
+        doc.set(&["audio"], "volume", self.volume as f64);
+        doc.set(&["sound"], "bank", self.bank as i64);
+        doc.set(&["sound"], "program", self.program as i64);
+        doc.set(&["sound"], "transpose", self.transpose as i64);
+        doc.set(&["sound"], "octave_shift", self.octave_shift as i64);
+        zones_to_document(&self.zones, &mut doc);
+        tuning_to_document(&self.tuning, &mut doc);
+        if let Some(files) = &self.scala {
+            doc.set(&["tuning"], "scale", files.scale.to_string_lossy().as_ref());
+            if let Some(mapping) = &files.mapping {
+                doc.set(&["tuning"], "keyboard_mapping", mapping.to_string_lossy().as_ref());
+            }
+        }
+        doc.set(&["effects"], "reverb", self.synth_effects.reverb);
+        doc.set(&["effects"], "reverb_send", self.synth_effects.reverb_send as i64);
+        doc.set(&["effects"], "chorus", self.synth_effects.chorus);
+        doc.set(&["effects"], "chorus_send", self.synth_effects.chorus_send as i64);
+        doc.set(&["convolution"], "enabled", self.convolution.enabled);
+        if let Some(impulse_response) = &self.impulse_response {
+            doc.set(&["convolution"], "impulse_response", impulse_response.to_string_lossy().as_ref());
+        }
+        doc.set(&["convolution"], "mix", self.convolution.mix as f64);
+        doc.set(&["convolution"], "pre_delay_ms", self.convolution.pre_delay_ms as f64);
+        master_effects_to_document(&self.master_effects, &mut doc);
+        metronome_to_document(&self.metronome, &mut doc);
+        match self.tap_trigger {
+            Some(TapTrigger::Note(note)) => doc.set(&["tap_tempo"], "note", note as i64),
+            Some(TapTrigger::Control(control)) => doc.set(&["tap_tempo"], "control", control as i64),
+            None => {}
+        }
+        doc.set(&["startup"], "jingle", self.startup_jingle.enabled);
+        if let Some(file) = &self.startup_jingle.file {
+            doc.set(&["startup"], "jingle_file", file.to_string_lossy().as_ref());
+        }
+        doc.set(&["window"], "theme", self.theme.as_str());
+        doc.set(&["window"], "width", self.window_width.round() as i64);
+        doc.set(&["window"], "height", self.window_height.round() as i64);
+        doc
+    }
+}
+
+fn keyboard_mode_name(mode: KeyboardMode) -> &'static str {
+    match mode {
+        KeyboardMode::Single => "single",
+        KeyboardMode::Split => "split",
+        KeyboardMode::Layer => "layer",
+    }
+}
+
+fn zones_from_document(doc: &Document, defaults: &ZoneSettings) -> ZoneSettings {
+    let integer = |table: &[&str], key: &str| doc.get(table, key).and_then(|v| v.as_integer());
+    let midi = |v: i64| v.clamp(0, 127) as u8;
+    let transpose = |v: i64| v.clamp(-MAX_TRANSPOSE as i64, MAX_TRANSPOSE as i64) as i32;
+    let mode = doc.get(&["zones"], "mode").and_then(|v| v.as_str());
+    let second = ["zones", "second"];
+
+    ZoneSettings {
+        mode: KeyboardMode::ALL
+            .into_iter()
+            .find(|m| Some(keyboard_mode_name(*m)) == mode)
+            .unwrap_or(defaults.mode),
+        split_point: integer(&["zones"], "split_point").map_or(defaults.split_point, midi),
+        main_transpose: integer(&["zones"], "main_transpose").map_or(defaults.main_transpose, transpose),
+        main_volume: integer(&["zones"], "main_volume").map_or(defaults.main_volume, midi),
+        second: Zone {
+            bank: integer(&second, "bank").map_or(defaults.second.bank, midi),
+            program: integer(&second, "program").map_or(defaults.second.program, midi),
+            transpose: integer(&second, "transpose").map_or(defaults.second.transpose, transpose),
+            volume: integer(&second, "volume").map_or(defaults.second.volume, midi),
+        },
+    }
+}
+
+fn zones_to_document(zones: &ZoneSettings, doc: &mut Document) {
+    doc.set(&["zones"], "mode", keyboard_mode_name(zones.mode));
+    doc.set(&["zones"], "split_point", zones.split_point as i64);
+    doc.set(&["zones"], "main_transpose", zones.main_transpose as i64);
+    doc.set(&["zones"], "main_volume", zones.main_volume as i64);
+    let second = ["zones", "second"];
+    doc.set(&second, "bank", zones.second.bank as i64);
+    doc.set(&second, "program", zones.second.program as i64);
+    doc.set(&second, "transpose", zones.second.transpose as i64);
+    doc.set(&second, "volume", zones.second.volume as i64);
+}
+
+/// Each device's curve is a `[velocity."Port Name"]` table: `curve` names the
+/// kind, `fixed` is the velocity of a fixed curve, and `points` lists a custom
+/// curve's input and output velocities in turn.
+fn velocity_curves_from_document(doc: &Document) -> BTreeMap<String, VelocityCurve> {
+    let mut curves = BTreeMap::new();
+    for device in doc.subtables(&["velocity"]) {
+        let table = ["velocity", device.as_str()];
+        let velocity = |v: i64| v.clamp(1, 127) as u8;
+        let curve = match doc.get(&table, "curve").and_then(|v| v.as_str()) {
+            Some("linear") => VelocityCurve::Linear,
+            Some("soft") => VelocityCurve::Soft,
+            Some("hard") => VelocityCurve::Hard,
+            Some("fixed") => VelocityCurve::Fixed(
+                doc.get(&table, "fixed").and_then(|v| v.as_integer()).map_or(100, velocity),
+            ),
+            Some("custom") => {
+                let values: Vec<i64> = doc
+                    .get(&table, "points")
+                    .and_then(|v| v.as_array())
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|v| v.as_integer())
+                    .collect();
+                let points = values.chunks_exact(2).map(|p| (p[0].clamp(0, 127) as u8, velocity(p[1]))).collect();
+                VelocityCurve::custom(points)
+            }
+            other => {
+                warn!("Unknown velocity curve {:?} for {}, using linear.", other, device);
+                VelocityCurve::Linear
+            }
+        };
+        curves.insert(device, curve);
+    }
+    curves
+}
+
+fn velocity_curves_to_document(curves: &BTreeMap<String, VelocityCurve>, doc: &mut Document) {
+    for (device, curve) in curves {
+        let table = ["velocity", device.as_str()];
+        let name = match curve {
+            VelocityCurve::Linear => "linear",
+            VelocityCurve::Soft => "soft",
+            VelocityCurve::Hard => "hard",
+            VelocityCurve::Fixed(fixed) => {
+                doc.set(&table, "fixed", *fixed as i64);
+                "fixed"
+            }
+            VelocityCurve::Custom(points) => {
+                let values = points.iter().flat_map(|&(x, y)| [toml::Value::Integer(x as i64), toml::Value::Integer(y as i64)]);
+                doc.set(&table, "points", toml::Value::Array(values.collect()));
+                "custom"
+            }
+        };
+        doc.set(&table, "curve", name);
+    }
+}
+
+fn temperament_name(temperament: Temperament) -> &'static str {
+    match temperament {
+        Temperament::Equal => "equal",
+        Temperament::Pythagorean => "pythagorean",
+        Temperament::Just => "just",
+        Temperament::Meantone => "meantone",
+        Temperament::WerckmeisterIII => "werckmeister-iii",
+        Temperament::Vallotti => "vallotti",
+    }
+}
+
+fn tuning_from_document(doc: &Document, defaults: &TuningSettings) -> TuningSettings {
+    let temperament = doc.get(&["tuning"], "temperament").and_then(|v| v.as_str());
+    TuningSettings {
+        reference: doc
+            .get(&["tuning"], "reference")
+            .and_then(|v| v.as_float())
+            .map_or(defaults.reference, |v| v.clamp(tuning::MIN_REFERENCE, tuning::MAX_REFERENCE)),
+        temperament: Temperament::ALL
+            .into_iter()
+            .find(|t| Some(temperament_name(*t)) == temperament)
+            .unwrap_or(defaults.temperament),
+        tonic: doc
+            .get(&["tuning"], "tonic")
+            .and_then(|v| v.as_integer())
+            .map_or(defaults.tonic, |v| v.rem_euclid(12) as u8),
+    }
+}
+
+fn tuning_to_document(tuning: &TuningSettings, doc: &mut Document) {
+    doc.set(&["tuning"], "reference", tuning.reference);
+    doc.set(&["tuning"], "temperament", temperament_name(tuning.temperament));
+    doc.set(&["tuning"], "tonic", tuning.tonic as i64);
+}
+
+/// Table names of the EQ bands, `[eq.band1]` and so on.
+const EQ_BANDS: [&str; 4] = ["band1", "band2", "band3", "band4"];
+
+fn band_kind_name(kind: BandKind) -> &'static str {
+    match kind {
+        BandKind::LowShelf => "low-shelf",
+        BandKind::Peak => "peak",
+        BandKind::HighShelf => "high-shelf",
+    }
+}
+
+fn master_effects_from_document(doc: &Document, defaults: &MasterEffects) -> MasterEffects {
+    let float = |table: &[&str], key: &str| doc.get(table, key).and_then(|v| v.as_float()).map(|v| v as f32);
+    let boolean = |table: &[&str], key: &str| doc.get(table, key).and_then(|v| v.as_bool());
+
+    let mut bands = defaults.eq.bands;
+    for (band, name) in bands.iter_mut().zip(EQ_BANDS) {
+        let table = ["eq", name];
+        let kind = doc.get(&table, "kind").and_then(|v| v.as_str());
+        *band = EqBand {
+            kind: [BandKind::LowShelf, BandKind::Peak, BandKind::HighShelf]
+                .into_iter()
+                .find(|k| Some(band_kind_name(*k)) == kind)
+                .unwrap_or(band.kind),
+            frequency: float(&table, "frequency").map_or(band.frequency, |v| v.clamp(eq::MIN_FREQUENCY, eq::MAX_FREQUENCY)),
+            gain_db: float(&table, "gain_db").map_or(band.gain_db, |v| v.clamp(-eq::MAX_GAIN_DB, eq::MAX_GAIN_DB)),
+            q: float(&table, "q").map_or(band.q, |v| v.clamp(eq::MIN_Q, eq::MAX_Q)),
+        };
+    }
+
+    let c = defaults.compressor;
+    let table = ["compressor"];
+    MasterEffects {
+        eq: eq::EqSettings {
+            bypass: boolean(&["eq"], "bypass").unwrap_or(defaults.eq.bypass),
+            bands,
+        },
+        compressor: CompressorSettings {
+            bypass: boolean(&table, "bypass").unwrap_or(c.bypass),
+            threshold_db: float(&table, "threshold_db").map_or(c.threshold_db, |v| v.clamp(compressor::MIN_THRESHOLD_DB, 0.0)),
+            ratio: float(&table, "ratio").map_or(c.ratio, |v| v.clamp(1.0, compressor::MAX_RATIO)),
+            attack_ms: float(&table, "attack_ms").map_or(c.attack_ms, |v| v.clamp(0.1, compressor::MAX_ATTACK_MS)),
+            release_ms: float(&table, "release_ms").map_or(c.release_ms, |v| v.clamp(1.0, compressor::MAX_RELEASE_MS)),
+            makeup_db: float(&table, "makeup_db").map_or(c.makeup_db, |v| v.clamp(0.0, compressor::MAX_MAKEUP_DB)),
+        },
+    }
+}
+
+fn master_effects_to_document(effects: &MasterEffects, doc: &mut Document) {
+    doc.set(&["eq"], "bypass", effects.eq.bypass);
+    for (band, name) in effects.eq.bands.iter().zip(EQ_BANDS) {
+        let table = ["eq", name];
+        doc.set(&table, "kind", band_kind_name(band.kind));
+        doc.set(&table, "frequency", band.frequency as f64);
+        doc.set(&table, "gain_db", band.gain_db as f64);
+        doc.set(&table, "q", band.q as f64);
+    }
+    let c = &effects.compressor;
+    let table = ["compressor"];
+    doc.set(&table, "bypass", c.bypass);
+    doc.set(&table, "threshold_db", c.threshold_db as f64);
+    doc.set(&table, "ratio", c.ratio as f64);
+    doc.set(&table, "attack_ms", c.attack_ms as f64);
+    doc.set(&table, "release_ms", c.release_ms as f64);
+    doc.set(&table, "makeup_db", c.makeup_db as f64);
+}
+
+fn subdivision_name(subdivision: Subdivision) -> &'static str {
+    match subdivision {
+        Subdivision::None => "none",
+        Subdivision::Eighths => "eighths",
+        Subdivision::Triplets => "triplets",
+        Subdivision::Sixteenths => "sixteenths",
+    }
+}
+
+fn metronome_from_document(doc: &Document, defaults: &MetronomeSettings) -> MetronomeSettings {
+    let table = ["metronome"];
+    let integer = |key: &str| doc.get(&table, key).and_then(|v| v.as_integer());
+    let subdivision = doc.get(&table, "subdivision").and_then(|v| v.as_str());
+    MetronomeSettings {
+        enabled: false,
+        bpm: doc
+            .get(&table, "bpm")
+            .and_then(|v| v.as_float())
+            .map_or(defaults.bpm, |v| v.clamp(metronome::MIN_BPM, metronome::MAX_BPM)),
+        beats_per_bar: integer("beats_per_bar")
+            .map_or(defaults.beats_per_bar, |v| v.clamp(1, metronome::MAX_BEATS_PER_BAR as i64) as u8),
+        beat_unit: integer("beat_unit")
+            .and_then(|v| metronome::BEAT_UNITS.into_iter().find(|unit| *unit as i64 == v))
+            .unwrap_or(defaults.beat_unit),
+        accent: doc.get(&table, "accent").and_then(|v| v.as_bool()).unwrap_or(defaults.accent),
+        subdivision: Subdivision::ALL
+            .into_iter()
+            .find(|s| Some(subdivision_name(*s)) == subdivision)
+            .unwrap_or(defaults.subdivision),
+        volume: doc
+            .get(&table, "volume")
+            .and_then(|v| v.as_float())
+            .map_or(defaults.volume, |v| v.clamp(0.0, 1.0) as f32),
+    }
+}
+
+fn metronome_to_document(metronome: &MetronomeSettings, doc: &mut Document) {
+    let table = ["metronome"];
+    doc.set(&table, "bpm", metronome.bpm);
+    doc.set(&table, "beats_per_bar", metronome.beats_per_bar as i64);
+    doc.set(&table, "beat_unit", metronome.beat_unit as i64);
+    doc.set(&table, "accent", metronome.accent);
+    doc.set(&table, "subdivision", subdivision_name(metronome.subdivision));
+    doc.set(&table, "volume", metronome.volume as f64);
+}
+
+/// Upgrade steps, one per version bump: entry `i` turns a version `i + 1` document into version `i + 2`.
+const MIGRATIONS: [fn(&mut Document); (SETTINGS_VERSION - 1) as usize] = [];
+
+/// Upgrades an older settings document, one version at a time, to `SETTINGS_VERSION`.
+fn migrate(doc: &mut Document) -> Result<()> {
+    // Files written before versioning have no version key; their layout is version 1's.
+    let version = doc.get(&[], "version").and_then(|v| v.as_integer()).unwrap_or(1);
+
+    if version < 1 {
+        anyhow::bail!("Invalid settings version {}", version);
+    }
+    if version > SETTINGS_VERSION {
+        warn!(
+            "Settings file is from a newer Toy Piano (version {}), reading what we understand.",
+            version
+        );
+        return Ok(());
+    }
+
+    for step in &MIGRATIONS[(version - 1) as usize..] {
+        step(doc);
+    }
+    if version < SETTINGS_VERSION {
+        info!("Migrated settings from version {} to {}", version, SETTINGS_VERSION);
+    }
+
+    doc.set(&[], "version", SETTINGS_VERSION);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changed() -> Settings {
+        let defaults = Settings::default();
+        Settings {
+            midi_device: Some("USB MIDI: Port 1".into()),
+            velocity_curves: BTreeMap::from([
+                ("Stage Piano".to_string(), VelocityCurve::Fixed(90)),
+                ("USB MIDI: Port 1".to_string(), VelocityCurve::custom(vec![(0, 1), (64, 100), (127, 127)])),
+            ]),
+            soundfont: Some(PathBuf::from("/sounds/grand piano.sf2")),
+            bank: 8,
+            program: 4,
+            volume: 0.5,
+            transpose: -3,
+            zones: ZoneSettings { mode: KeyboardMode::Split, split_point: 55, ..defaults.zones },
+            tuning: TuningSettings { reference: 432.0, temperament: Temperament::Pythagorean, tonic: 2 },
+            metronome: MetronomeSettings { bpm: 96.0, beats_per_bar: 3, subdivision: Subdivision::Eighths, ..defaults.metronome },
+            tap_trigger: Some(TapTrigger::Control(67)),
+            startup_jingle: StartupJingle { enabled: false, file: Some(PathBuf::from("hello.mid")) },
+            window_width: 1200.0,
+            ..defaults
+        }
+    }
+
+    #[test]
+    fn saved_settings_load_back() {
+        let path = std::env::temp_dir().join(format!("toy-piano-settings-{}.toml", std::process::id()));
+        let settings = changed();
+        settings.save_to(&path).unwrap();
+        let loaded = Settings::load_from(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), settings);
+    }
+
+    #[test]
+    fn unknown_and_out_of_range_values_fall_back() {
+        let doc = Document::parse("[sound]\nbank = 300\nprogram = \"piano\"\n\n[audio]\nvolume = 9\n\n[other]\nkey = 1").unwrap();
+        let settings = Settings::from_document(&doc);
+        assert_eq!(settings.bank, 127);
+        assert_eq!(settings.program, 0);
+        assert_eq!(settings.volume, 2.0);
+    }
+
+    #[test]
+    fn migrate_stamps_the_version() {
+        // Files from before versioning read as version 1
+        let mut doc = Document::parse("[sound]\nbank = 1").unwrap();
+        migrate(&mut doc).unwrap();
+        assert_eq!(doc.get(&[], "version").and_then(|v| v.as_integer()), Some(SETTINGS_VERSION));
+        assert_eq!(Settings::from_document(&doc).bank, 1);
+
+        let mut doc = Document::parse("version = 0").unwrap();
+        assert!(migrate(&mut doc).is_err());
+
+        // A newer file is read as far as it's understood, and keeps its version
+        let mut doc = Document::parse("version = 99\n[sound]\nbank = 2").unwrap();
+        migrate(&mut doc).unwrap();
+        assert_eq!(doc.get(&[], "version").and_then(|v| v.as_integer()), Some(99));
+        assert_eq!(Settings::from_document(&doc).bank, 2);
+    }
+}
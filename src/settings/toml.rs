@@ -0,0 +1,283 @@
+//! The settings file as tables of keys holding strings, integers, floats,
+//! booleans or flat arrays of those. Reading goes through the `toml` crate, so
+//! a file edited by hand loads as long as it's valid TOML; writing sticks to
+//! the plain subset the settings need.
+
+use anyhow::Result;
+use log::warn;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Integers are accepted too, so `volume = 1` works as well as `volume = 1.0`.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Float(f) => Some(*f),
+            Value::Integer(i) => Some(*i as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    /// None for tables, and arrays holding them.
+    fn from_toml(value: &::toml::Value) -> Option<Self> {
+        match value {
+            ::toml::Value::String(s) => Some(Value::String(s.clone())),
+            ::toml::Value::Integer(i) => Some(Value::Integer(*i)),
+            ::toml::Value::Float(f) => Some(Value::Float(*f)),
+            ::toml::Value::Boolean(b) => Some(Value::Boolean(*b)),
+            ::toml::Value::Datetime(d) => Some(Value::String(d.to_string())),
+            ::toml::Value::Array(items) => items.iter().map(Value::from_toml).collect::<Option<_>>().map(Value::Array),
+            ::toml::Value::Table(_) => None,
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Integer(i)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(f: f64) -> Self {
+        Value::Float(f)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Boolean(b)
+    }
+}
+
+/// Tables by their dotted path (the root table is the empty path), each holding its keys.
+#[derive(Debug, Default)]
+pub struct Document {
+    tables: BTreeMap<Vec<String>, BTreeMap<String, Value>>,
+}
+
+impl Document {
+    pub fn get(&self, table: &[&str], key: &str) -> Option<&Value> {
+        let path: Vec<String> = table.iter().map(|s| s.to_string()).collect();
+        self.tables.get(&path)?.get(key)
+    }
+
+    pub fn set(&mut self, table: &[&str], key: &str, value: impl Into<Value>) {
+        let path = table.iter().map(|s| s.to_string()).collect();
+        self.tables.entry(path).or_default().insert(key.to_string(), value.into());
+    }
+
+    pub fn remove(&mut self, table: &[&str], key: &str) -> Option<Value> {
+        let path: Vec<String> = table.iter().map(|s| s.to_string()).collect();
+        self.tables.get_mut(&path)?.remove(key)
+    }
+
+    /// Names of the sub-tables directly under `table`, e.g. one per device.
+    pub fn subtables(&self, table: &[&str]) -> Vec<String> {
+        self.tables
+            .keys()
+            .filter(|path| path.len() == table.len() + 1 && path.iter().zip(table).all(|(a, b)| a == b))
+            .map(|path| path[table.len()].clone())
+            .collect()
+    }
+
+    /// Reads any valid TOML. Arrays of tables have no place in the settings
+    /// and are left out; inline tables read like any other table.
+    pub fn parse(input: &str) -> Result<Self> {
+        let table: ::toml::Table = input.parse()?;
+        let mut doc = Document::default();
+        doc.insert_table(Vec::new(), &table);
+        Ok(doc)
+    }
+
+    fn insert_table(&mut self, path: Vec<String>, table: &::toml::Table) {
+        let mut keys = BTreeMap::new();
+        for (key, value) in table {
+            if let ::toml::Value::Table(sub) = value {
+                let mut sub_path = path.clone();
+                sub_path.push(key.clone());
+                self.insert_table(sub_path, sub);
+            } else if let Some(value) = Value::from_toml(value) {
+                keys.insert(key.clone(), value);
+            } else {
+                warn!("Ignoring setting {:?} in [{}], it holds tables", key, path.join("."));
+            }
+        }
+        self.tables.entry(path).or_default().extend(keys);
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut out = String::new();
+        for (path, keys) in &self.tables {
+            if keys.is_empty() && !path.is_empty() {
+                continue;
+            }
+            if !path.is_empty() {
+                if !out.is_empty() {
+                    out.push('\n');
+                }
+                let header: Vec<String> = path.iter().map(|s| format_key(s)).collect();
+                let _ = writeln!(out, "[{}]", header.join("."));
+            }
+            for (key, value) in keys {
+                let _ = writeln!(out, "{} = {}", format_key(key), format_value(value));
+            }
+        }
+        out
+    }
+}
+
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn format_key(key: &str) -> String {
+    if is_bare_key(key) {
+        key.to_string()
+    } else {
+        format_string(key)
+    }
+}
+
+fn format_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04X}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => format_string(s),
+        Value::Integer(i) => i.to_string(),
+        // Debug keeps the ".0", so floats read back as floats
+        Value::Float(f) => format!("{:?}", f),
+        Value::Boolean(b) => b.to_string(),
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(format_value).collect();
+            format!("[{}]", items.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_files_edited_by_hand() {
+        let doc = Document::parse(
+            r#"
+            version = 1 # comments anywhere
+
+            [audio]
+            soundfont = 'C:\Sounds\piano.sf2'
+            volume = 0.8
+
+            [velocity."My Keyboard"]
+            curve = "custom"
+            points = [
+                0, 0,
+                64, 90, # trailing comma
+            ]
+
+            [zones]
+            second = { bank = 0, program = 32 }
+            eq.band1.gain = -3
+            "#,
+        )
+        .unwrap();
+        assert_eq!(doc.get(&[], "version"), Some(&Value::Integer(1)));
+        assert_eq!(doc.get(&["audio"], "soundfont").and_then(Value::as_str), Some(r"C:\Sounds\piano.sf2"));
+        assert_eq!(doc.get(&["audio"], "volume").and_then(Value::as_float), Some(0.8));
+        let points = doc.get(&["velocity", "My Keyboard"], "points").and_then(Value::as_array).unwrap();
+        assert_eq!(points.len(), 4);
+        assert_eq!(doc.subtables(&["velocity"]), vec!["My Keyboard"]);
+        assert_eq!(doc.get(&["zones", "second"], "program"), Some(&Value::Integer(32)));
+        assert_eq!(doc.get(&["zones", "eq", "band1"], "gain"), Some(&Value::Integer(-3)));
+    }
+
+    #[test]
+    fn invalid_toml_is_an_error() {
+        assert!(Document::parse("volume = ").is_err());
+        assert!(Document::parse("[audio\nvolume = 1").is_err());
+        assert!(Document::parse("a = 1\na = 2").is_err());
+    }
+
+    #[test]
+    fn arrays_of_tables_are_left_out() {
+        let doc = Document::parse("[[presets]]\nname = \"a\"\n\n[sound]\nbank = 1").unwrap();
+        assert_eq!(doc.get(&[], "presets"), None);
+        assert_eq!(doc.get(&["sound"], "bank"), Some(&Value::Integer(1)));
+    }
+
+    #[test]
+    fn writes_what_it_reads() {
+        let mut doc = Document::default();
+        doc.set(&[], "version", 1);
+        doc.set(&["audio"], "device", "Speakers \"Front\"\tleft\\right");
+        doc.set(&["audio"], "volume", 1.0);
+        doc.set(&["effects"], "reverb", true);
+        doc.set(&["velocity", "USB MIDI: Port 1"], "points", Value::Array(vec![0.into(), 127.into()]));
+        let text = doc.to_toml();
+        let read = Document::parse(&text).unwrap();
+        assert_eq!(read.to_toml(), text);
+        // Floats stay floats
+        assert_eq!(read.get(&["audio"], "volume"), Some(&Value::Float(1.0)));
+        assert_eq!(read.subtables(&["velocity"]), vec!["USB MIDI: Port 1"]);
+    }
+}
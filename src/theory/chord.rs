@@ -0,0 +1,316 @@
+use super::spelling::{spell, KeySignature};
+
+/// A chord type, as intervals in semitones above the root.
+struct Template {
+    suffix: &'static str,
+    intervals: &'static [u8],
+    // Pianists often leave the fifth out of sevenths and bigger chords
+    fifth_optional: bool,
+    // Lower is simpler. Used to pick between readings of the same notes.
+    complexity: u8,
+}
+
+const fn t(suffix: &'static str, intervals: &'static [u8], fifth_optional: bool, complexity: u8) -> Template {
+    Template {
+        suffix,
+        intervals,
+        fifth_optional,
+        complexity,
+    }
+}
+
+const TEMPLATES: [Template; 30] = [
+    // Triads
+    t("", &[0, 4, 7], false, 0),
+    t("m", &[0, 3, 7], false, 0),
+    t("dim", &[0, 3, 6], false, 1),
+    t("aug", &[0, 4, 8], false, 1),
+    t("sus2", &[0, 2, 7], false, 1),
+    t("sus4", &[0, 5, 7], false, 1),
+    t("5", &[0, 7], false, 1),
+    // Sixths and sevenths
+    t("6", &[0, 4, 7, 9], false, 1),
+    t("m6", &[0, 3, 7, 9], false, 1),
+    t("7", &[0, 4, 7, 10], true, 1),
+    t("maj7", &[0, 4, 7, 11], true, 1),
+    t("m7", &[0, 3, 7, 10], true, 1),
+    t("m(maj7)", &[0, 3, 7, 11], true, 2),
+    t("m7b5", &[0, 3, 6, 10], false, 1),
+    t("dim7", &[0, 3, 6, 9], false, 1),
+    t("7#5", &[0, 4, 8, 10], false, 2),
+    t("maj7#5", &[0, 4, 8, 11], false, 2),
+    t("7sus4", &[0, 5, 7, 10], true, 2),
+    // Added tones and extensions
+    t("add9", &[0, 2, 4, 7], false, 2),
+    t("madd9", &[0, 2, 3, 7], false, 2),
+    t("6/9", &[0, 2, 4, 7, 9], true, 2),
+    t("9", &[0, 2, 4, 7, 10], true, 2),
+    t("maj9", &[0, 2, 4, 7, 11], true, 2),
+    t("m9", &[0, 2, 3, 7, 10], true, 2),
+    t("9sus4", &[0, 2, 5, 7, 10], true, 3),
+    t("7b9", &[0, 1, 4, 7, 10], true, 3),
+    t("7#9", &[0, 3, 4, 7, 10], true, 3),
+    t("11", &[0, 2, 4, 5, 7, 10], true, 3),
+    t("m11", &[0, 2, 3, 5, 7, 10], true, 3),
+    t("13", &[0, 2, 4, 7, 9, 10], true, 3),
+];
+
+// Extra cost of each reading that isn't the plain root-position chord
+const INVERSION_COST: u8 = 2;
+const FOREIGN_BASS_COST: u8 = 3;
+const MISSING_FIFTH_COST: u8 = 1;
+
+/// A recognized chord. Pitch classes are 0 = C ... 11 = B.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    pub root: u8,
+    /// Lowest note; differs from `root` for inversions and slash chords
+    pub bass: u8,
+    quality: usize,
+}
+
+impl Chord {
+    /// Suffix after the root, e.g. "m7b5" ("" for a major triad).
+    pub fn quality(&self) -> &'static str {
+        TEMPLATES[self.quality].suffix
+    }
+
+    /// Full name with roots spelled for `key`, e.g. "Cmaj7/E" or "F#m7b5".
+    pub fn name(&self, key: KeySignature) -> String {
+        let root = spell(self.root, key).name();
+        if self.bass == self.root {
+            format!("{}{}", root, self.quality())
+        } else {
+            format!("{}{}/{}", root, self.quality(), spell(self.bass, key).name())
+        }
+    }
+}
+
+fn pitch_set(notes: impl Iterator<Item = u8>) -> u16 {
+    notes.fold(0, |set, n| set | 1 << (n % 12))
+}
+
+/// Rotates a pitch-class set so `root` becomes 0.
+fn relative_to(set: u16, root: u8) -> u16 {
+    let set = set as u32;
+    let root = root as u32;
+    (((set >> root) | (set << (12 - root))) & 0xFFF) as u16
+}
+
+/// Best template for a pitch-class set built on `root`, with its complexity.
+fn match_template(set: u16, root: u8) -> Option<(usize, u8)> {
+    let relative = relative_to(set, root);
+    TEMPLATES.iter().enumerate().find_map(|(i, template)| {
+        let full = pitch_set(template.intervals.iter().copied());
+        if relative == full {
+            Some((i, template.complexity))
+        } else if template.fifth_optional && relative == full & !(1 << 7) {
+            Some((i, template.complexity + MISSING_FIFTH_COST))
+        } else {
+            None
+        }
+    })
+}
+
+/// Names the chord formed by `notes` (MIDI note numbers, in any order, doublings allowed).
+///
+/// Every note is tried as a root. Root position beats inversions, which beat
+/// a chord over a foreign bass note, and simpler chord types win; among equals
+/// the lower root wins. So C-E-G-A is "C6" but A-C-E-G is "Am7".
+pub fn identify(notes: &[u8]) -> Option<Chord> {
+    let mut sorted = notes.to_vec();
+    sorted.sort_unstable();
+    let bass = *sorted.first()? % 12;
+    let set = pitch_set(notes.iter().copied());
+
+    // Candidate roots from the bottom up, so lower notes win ties
+    let mut roots: Vec<u8> = Vec::with_capacity(12);
+    for pitch_class in sorted.iter().map(|n| n % 12) {
+        if !roots.contains(&pitch_class) {
+            roots.push(pitch_class);
+        }
+    }
+
+    let mut best: Option<(u8, Chord)> = None;
+    let mut consider = |score: u8, chord: Chord| {
+        if best.is_none_or(|(s, _)| score < s) {
+            best = Some((score, chord));
+        }
+    };
+
+    for &root in &roots {
+        if let Some((quality, complexity)) = match_template(set, root) {
+            let cost = if root == bass { 0 } else { INVERSION_COST };
+            consider(complexity + cost, Chord { root, bass, quality });
+        }
+    }
+
+    // A chord sitting on a bass note that isn't part of it, like C/D
+    let upper = set & !(1 << bass);
+    if upper.count_ones() >= 3 {
+        for &root in &roots[1..] {
+            if let Some((quality, complexity)) = match_template(upper, root) {
+                consider(complexity + FOREIGN_BASS_COST, Chord { root, bass, quality });
+            }
+        }
+    }
+
+    best.map(|(_, chord)| chord)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const C_MAJOR: KeySignature = KeySignature::ALL[7];
+
+    fn name(notes: &[u8]) -> Option<String> {
+        identify(notes).map(|c| c.name(C_MAJOR))
+    }
+
+    /// The template's notes on `root`, starting from `base` and stacked upward.
+    fn voicing(root: u8, intervals: &[u8], base: u8) -> Vec<u8> {
+        intervals.iter().map(|i| base + root + i).collect()
+    }
+
+    #[test]
+    fn templates_are_unique() {
+        let mut seen = Vec::new();
+        for template in TEMPLATES.iter() {
+            let full = pitch_set(template.intervals.iter().copied());
+            assert!(!seen.contains(&full), "duplicate template {}", template.suffix);
+            seen.push(full);
+            if template.fifth_optional {
+                let without_fifth = full & !(1 << 7);
+                assert!(!seen.contains(&without_fifth), "duplicate no-fifth {}", template.suffix);
+                seen.push(without_fifth);
+            }
+        }
+    }
+
+    #[test]
+    fn every_chord_in_root_position_on_every_root() {
+        for root in 0..12u8 {
+            for (i, template) in TEMPLATES.iter().enumerate() {
+                let notes = voicing(root, template.intervals, 48);
+                let chord = identify(&notes).unwrap_or_else(|| panic!("{:?} not recognized", notes));
+                // Symmetrical chords have no single root; with the bass first, root position wins anyway
+                assert_eq!(chord.root, root, "root of {}{:?}", template.suffix, notes);
+                assert_eq!(chord.bass, root);
+                assert_eq!(chord.quality, i, "{:?} read as {}", notes, chord.quality());
+            }
+        }
+    }
+
+    #[test]
+    fn every_chord_without_its_fifth() {
+        for root in 0..12u8 {
+            // Without its fifth, 9sus4 is the upper triad over the root (C9sus4 = Bb/C), checked in `names`
+            let optional = TEMPLATES.iter().enumerate().filter(|(_, t)| t.fifth_optional && t.suffix != "9sus4");
+            for (i, template) in optional {
+                let intervals: Vec<u8> = template.intervals.iter().copied().filter(|i| *i != 7).collect();
+                let notes = voicing(root, &intervals, 48);
+                let chord = identify(&notes).unwrap_or_else(|| panic!("{:?} not recognized", notes));
+                assert_eq!((chord.root, chord.quality), (root, i), "{}{:?}", template.suffix, notes);
+            }
+        }
+    }
+
+    #[test]
+    fn doubling_and_spreading_notes_changes_nothing() {
+        for root in 0..12u8 {
+            for template in TEMPLATES.iter() {
+                let close = voicing(root, template.intervals, 48);
+                let mut open = close.clone();
+                open.extend(close.iter().skip(1).map(|n| n + 12));
+                open.push(close[0] + 24);
+                open.reverse(); // order doesn't matter either
+                assert_eq!(identify(&close), identify(&open));
+            }
+        }
+    }
+
+    #[test]
+    fn inversions_of_triads_and_sevenths() {
+        for suffix in ["", "m", "dim", "7", "maj7"] {
+            let template = TEMPLATES.iter().find(|t| t.suffix == suffix).unwrap();
+            for root in 0..12u8 {
+                for inversion in 1..template.intervals.len() {
+                    let mut notes = voicing(root, template.intervals, 48);
+                    // Move the bottom notes up an octave
+                    for note in notes.iter_mut().take(inversion) {
+                        *note += 12;
+                    }
+                    let chord = identify(&notes).unwrap();
+                    assert_eq!(chord.root, root, "{}{:?}", suffix, notes);
+                    assert_eq!(chord.quality(), suffix);
+                    assert_eq!(chord.bass, (root + template.intervals[inversion]) % 12);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn minor_sevenths_with_fifth_or_seventh_in_the_bass() {
+        for root in 0..12u8 {
+            for (bass, _) in [(7u8, "fifth"), (10, "seventh")] {
+                let mut notes = vec![48 + root + bass];
+                notes.extend(voicing(root, &[0, 3, 7, 10], 60));
+                let chord = identify(&notes).unwrap();
+                assert_eq!((chord.root, chord.quality()), (root, "m7"));
+                assert_eq!(chord.bass, (root + bass) % 12);
+            }
+        }
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(name(&[60, 64, 67]), Some("C".into()));
+        assert_eq!(name(&[64, 67, 72]), Some("C/E".into()));
+        assert_eq!(name(&[67, 72, 76]), Some("C/G".into()));
+        assert_eq!(name(&[52, 60, 67, 71]), Some("Cmaj7/E".into()));
+        assert_eq!(name(&[54, 57, 60, 64]), Some("F#m7b5".into()));
+        assert_eq!(name(&[57, 60, 64, 67]), Some("Am7".into()));
+        assert_eq!(name(&[60, 64, 67, 69]), Some("C6".into()));
+        assert_eq!(name(&[62, 64, 67, 72]), Some("C/D".into()));
+        assert_eq!(name(&[60, 67]), Some("C5".into()));
+        assert_eq!(name(&[59, 62, 65, 68]), Some("Bdim7".into()));
+        assert_eq!(name(&[60, 63, 67, 71]), Some("Cm(maj7)".into()));
+        assert_eq!(name(&[48, 64, 70, 75]), Some("C7#9".into()));
+        assert_eq!(name(&[48, 62, 65, 70]), Some("Bb/C".into()));
+        assert_eq!(name(&[48, 53, 55, 58]), Some("C7sus4".into()));
+    }
+
+    #[test]
+    fn spelling_follows_the_key() {
+        let b_flat_major = KeySignature::new(-2);
+        let e_major = KeySignature::new(4);
+        let chord = identify(&[58, 62, 65]).unwrap();
+        assert_eq!(chord.name(b_flat_major), "Bb");
+        assert_eq!(chord.name(KeySignature::new(3)), "A#");
+        let chord = identify(&[56, 59, 63, 66]).unwrap();
+        assert_eq!(chord.name(e_major), "G#m7");
+        assert_eq!(chord.name(b_flat_major), "Abm7");
+    }
+
+    #[test]
+    fn ambiguous_sixths_follow_the_bass() {
+        // Same notes, the bass decides: C6 over C, Am7 over A
+        assert_eq!(name(&[48, 64, 67, 69]), Some("C6".into()));
+        assert_eq!(name(&[45, 60, 64, 67]), Some("Am7".into()));
+        // Cm7 over its third reads as the simpler Eb6
+        assert_eq!(name(&[51, 60, 67, 70]), Some("Eb6".into()));
+        // Same inversion, but the lower of the two possible roots wins: Am7/E, not C6/E
+        assert_eq!(name(&[52, 57, 60, 64, 67]), Some("Am7/E".into()));
+    }
+
+    #[test]
+    fn too_few_notes_or_no_match() {
+        assert_eq!(name(&[]), None);
+        assert_eq!(name(&[60]), None);
+        assert_eq!(name(&[60, 72]), None);
+        assert_eq!(name(&[60, 64]), None);
+        assert_eq!(name(&[60, 61, 62]), None);
+        assert_eq!(name(&[60, 61, 62, 63, 64, 65, 66]), None);
+    }
+}
@@ -0,0 +1,7 @@
+//! Music theory helpers with no UI or audio dependencies.
+
+pub mod chord;
+pub mod spelling;
+
+pub use chord::{identify, Chord};
+pub use spelling::{spell, KeySignature, SpelledNote};
@@ -0,0 +1,232 @@
+use std::fmt;
+
+/// Letter names, indexed by diatonic step within the octave (C = 0 ... B = 6).
+pub const LETTERS: [char; 7] = ['C', 'D', 'E', 'F', 'G', 'A', 'B'];
+
+/// Pitch class of each natural letter.
+const NATURAL_PITCH: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+/// Order sharps are added to a key signature: F C G D A E B.
+pub const SHARP_ORDER: [usize; 7] = [3, 0, 4, 1, 5, 2, 6];
+/// Order flats are added to a key signature: B E A D G C F.
+pub const FLAT_ORDER: [usize; 7] = [6, 2, 5, 1, 4, 0, 3];
+
+const MAJOR_KEYS: [&str; 15] = [
+    "Cb", "Gb", "Db", "Ab", "Eb", "Bb", "F", "C", "G", "D", "A", "E", "B", "F#", "C#",
+];
+const MINOR_KEYS: [&str; 15] = [
+    "Ab", "Eb", "Bb", "F", "C", "G", "D", "A", "E", "B", "F#", "C#", "G#", "D#", "A#",
+];
+
+/// A key signature, counted in fifths: positive is that many sharps, negative that many flats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KeySignature {
+    fifths: i8,
+}
+
+impl KeySignature {
+    pub const ALL: [KeySignature; 15] = {
+        let mut all = [KeySignature { fifths: 0 }; 15];
+        let mut i = 0;
+        while i < 15 {
+            all[i].fifths = i as i8 - 7;
+            i += 1;
+        }
+        all
+    };
+
+    pub fn new(fifths: i8) -> Self {
+        KeySignature {
+            fifths: fifths.clamp(-7, 7),
+        }
+    }
+
+    pub fn fifths(self) -> i8 {
+        self.fifths
+    }
+
+    /// Sharp (+1), flat (-1) or natural (0) that the signature puts on a letter.
+    pub fn alteration(self, letter: usize) -> i8 {
+        let count = self.fifths.unsigned_abs() as usize;
+        if self.fifths > 0 && SHARP_ORDER[..count].contains(&letter) {
+            1
+        } else if self.fifths < 0 && FLAT_ORDER[..count].contains(&letter) {
+            -1
+        } else {
+            0
+        }
+    }
+
+    /// Letters carrying an accidental in the signature, in the order they are written.
+    pub fn accidentals(self) -> &'static [usize] {
+        let count = self.fifths.unsigned_abs() as usize;
+        if self.fifths >= 0 {
+            &SHARP_ORDER[..count]
+        } else {
+            &FLAT_ORDER[..count]
+        }
+    }
+}
+
+impl fmt::Display for KeySignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let i = (self.fifths + 7) as usize;
+        write!(f, "{} major / {} minor", MAJOR_KEYS[i], MINOR_KEYS[i])
+    }
+}
+
+/// A MIDI note written out as letter, accidental and octave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpelledNote {
+    /// Index into `LETTERS`
+    pub letter: usize,
+    /// -1 flat, 0 natural, +1 sharp
+    pub alteration: i8,
+    /// Scientific pitch notation octave (middle C is C4)
+    pub octave: i32,
+    /// The key signature doesn't already imply this alteration, so it must be written
+    pub show_accidental: bool,
+}
+
+impl SpelledNote {
+    /// Diatonic steps above C-1, used to place the note on a staff.
+    pub fn step(&self) -> i32 {
+        self.octave * 7 + self.letter as i32
+    }
+
+    /// Name without octave, e.g. "F#" or "Bb".
+    pub fn name(&self) -> String {
+        pitch_name(self.letter, self.alteration)
+    }
+}
+
+pub fn pitch_name(letter: usize, alteration: i8) -> String {
+    let accidental = match alteration {
+        1 => "#",
+        -1 => "b",
+        _ => "",
+    };
+    format!("{}{}", LETTERS[letter], accidental)
+}
+
+/// Spells a MIDI note in a key. Notes in the key use the signature's spelling;
+/// anything else prefers a natural, then sharps in sharp keys and flats in flat keys.
+/// C major uses the most common name for each black key.
+pub fn spell(note: u8, key: KeySignature) -> SpelledNote {
+    let pitch_class = note as i32 % 12;
+    let spells_as = |letter: usize, alteration: i8| {
+        (NATURAL_PITCH[letter] + alteration as i32).rem_euclid(12) == pitch_class
+    };
+
+    let diatonic = (0..7).find(|l| spells_as(*l, key.alteration(*l)));
+    let (letter, alteration, show_accidental) = match diatonic {
+        Some(letter) => (letter, key.alteration(letter), false),
+        None => {
+            let direction = match key.fifths() {
+                1.. => 1,
+                ..=-1 => -1,
+                // C major has no lean either way: use the usual C#, Eb, F#, Ab, Bb
+                0 if matches!(pitch_class, 1 | 6) => 1,
+                0 => -1,
+            };
+            [0, direction, -direction]
+                .iter()
+                .find_map(|alt| (0..7).find(|l| spells_as(*l, *alt)).map(|l| (l, *alt, true)))
+                .unwrap_or((0, 0, true)) // every pitch class has a natural or a single accidental
+        }
+    };
+
+    // B# and Cb cross the octave boundary
+    let octave = (note as i32 - alteration as i32 - NATURAL_PITCH[letter]) / 12 - 1;
+
+    SpelledNote {
+        letter,
+        alteration,
+        octave,
+        show_accidental,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Note name with octave, and whether an accidental is written.
+    fn written(note: u8, fifths: i8) -> (String, bool) {
+        let spelled = spell(note, KeySignature::new(fifths));
+        (format!("{}{}", spelled.name(), spelled.octave), spelled.show_accidental)
+    }
+
+    fn scale(fifths: i8, notes: &[u8]) -> Vec<String> {
+        notes.iter().map(|note| written(*note, fifths).0).collect()
+    }
+
+    #[test]
+    fn key_signatures() {
+        assert_eq!(KeySignature::new(0).to_string(), "C major / A minor");
+        assert_eq!(KeySignature::new(3).to_string(), "A major / F# minor");
+        assert_eq!(KeySignature::new(-2).to_string(), "Bb major / G minor");
+        assert_eq!(KeySignature::new(9), KeySignature::ALL[14]);
+        assert_eq!(KeySignature::new(-9).fifths(), -7);
+        // F C G for three sharps, B E A D for four flats
+        assert_eq!(KeySignature::new(3).accidentals(), &[3, 0, 4]);
+        assert_eq!(KeySignature::new(-4).accidentals(), &[6, 2, 5, 1]);
+        assert_eq!(KeySignature::new(3).alteration(4), 1);
+        assert_eq!(KeySignature::new(3).alteration(1), 0);
+        assert_eq!(KeySignature::new(-4).alteration(1), -1);
+    }
+
+    #[test]
+    fn sharp_keys_use_their_sharps() {
+        assert_eq!(
+            scale(2, &[62, 64, 66, 67, 69, 71, 73]),
+            ["D4", "E4", "F#4", "G4", "A4", "B4", "C#5"]
+        );
+        assert_eq!(
+            scale(4, &[64, 66, 68, 69, 71, 73, 75]),
+            ["E4", "F#4", "G#4", "A4", "B4", "C#5", "D#5"]
+        );
+        // Written by the signature, not as accidentals
+        assert_eq!(written(66, 2), ("F#4".into(), false));
+    }
+
+    #[test]
+    fn flat_keys_use_their_flats() {
+        assert_eq!(
+            scale(-3, &[63, 65, 67, 68, 70, 72, 74]),
+            ["Eb4", "F4", "G4", "Ab4", "Bb4", "C5", "D5"]
+        );
+        assert_eq!(written(70, -1), ("Bb4".into(), false));
+    }
+
+    #[test]
+    fn enharmonics_across_the_octave() {
+        // F# major has E#, C# major has B# too
+        assert_eq!(written(65, 6), ("E#4".into(), false));
+        assert_eq!(written(60, 7), ("B#3".into(), false));
+        // Gb major has Cb, which belongs to the octave above the B it sounds as
+        assert_eq!(written(71, -6), ("Cb5".into(), false));
+        assert_eq!(written(64, -7), ("Fb4".into(), false));
+        // Middle C is still C4 where it isn't respelled
+        assert_eq!(written(60, 6), ("C4".into(), true));
+    }
+
+    #[test]
+    fn accidentals_outside_the_key() {
+        // A natural in a sharp key, then chromatic notes leaning with the key
+        assert_eq!(written(65, 2), ("F4".into(), true));
+        assert_eq!(written(63, 2), ("D#4".into(), true));
+        assert_eq!(written(66, -2), ("Gb4".into(), true));
+        assert_eq!(written(71, -2), ("B4".into(), true));
+        // C major picks the usual name for each black key
+        assert_eq!(scale(0, &[61, 63, 66, 68, 70]), ["C#4", "Eb4", "F#4", "Ab4", "Bb4"]);
+        assert!(!written(60, 0).1);
+    }
+
+    #[test]
+    fn steps_place_notes_on_the_staff() {
+        // B#3 sits a step below C4 on the staff, even though they sound the same
+        assert_eq!(spell(60, KeySignature::new(7)).step() + 1, spell(60, KeySignature::new(0)).step());
+        assert_eq!(spell(72, KeySignature::new(0)).step() - spell(60, KeySignature::new(0)).step(), 7);
+    }
+}
@@ -0,0 +1,169 @@
+//! The master effects settings: EQ bands and compressor, each with a bypass switch.
+
+use iced::widget::{checkbox, column, row, slider, text, Column, Row};
+use iced::{Color, Element, Length};
+use std::ops::RangeInclusive;
+
+use super::{ForestGreenCheckbox, ForestGreenSlider, Message};
+use crate::audio::effects::compressor::{self, CompressorSettings};
+use crate::audio::effects::eq::{self, EqBand};
+use crate::audio::effects::MasterEffects;
+
+const LABEL_COLOR: Color = Color { r: 0.8, g: 1.0, b: 0.8, a: 1.0 };
+
+/// Frequency sliders run over 0..1 and map onto a log scale, so each octave gets the same room.
+fn frequency_to_position(frequency: f32) -> f32 {
+    (frequency / eq::MIN_FREQUENCY).ln() / (eq::MAX_FREQUENCY / eq::MIN_FREQUENCY).ln()
+}
+
+fn position_to_frequency(position: f32) -> f32 {
+    eq::MIN_FREQUENCY * (eq::MAX_FREQUENCY / eq::MIN_FREQUENCY).powf(position)
+}
+
+/// A caption, a slider and the value it's set to.
+fn control<'a>(
+    caption: &'a str,
+    range: RangeInclusive<f32>,
+    value: f32,
+    step: f32,
+    shown: String,
+    on_change: impl Fn(f32) -> Message + 'a,
+) -> Row<'a, Message> {
+    row![
+        text(caption).size(16).style(LABEL_COLOR),
+        slider(range, value, on_change)
+            .step(step)
+            .width(Length::Fixed(110.0))
+            .style(iced::theme::Slider::Custom(Box::new(ForestGreenSlider))),
+        text(shown).size(16).width(Length::Fixed(70.0)).style(LABEL_COLOR),
+    ]
+    .spacing(8)
+    .align_items(iced::Alignment::Center)
+}
+
+fn bypass_toggle<'a>(label: &'a str, bypass: bool, on_toggle: impl Fn(bool) -> Message + 'a) -> Element<'a, Message> {
+    checkbox(label, !bypass)
+        .on_toggle(move |enabled| on_toggle(!enabled))
+        .text_size(18)
+        .width(Length::Fixed(130.0))
+        .style(iced::theme::Checkbox::Custom(Box::new(ForestGreenCheckbox)))
+        .into()
+}
+
+pub fn view(effects: MasterEffects) -> Element<'static, Message> {
+    let with_band = move |i: usize, change: fn(&mut EqBand, f32)| {
+        move |value: f32| {
+            let mut effects = effects;
+            change(&mut effects.eq.bands[i], value);
+            Message::MasterEffectsChanged(effects)
+        }
+    };
+    let with_compressor = move |change: fn(&mut CompressorSettings, f32)| {
+        move |value: f32| {
+            let mut effects = effects;
+            change(&mut effects.compressor, value);
+            Message::MasterEffectsChanged(effects)
+        }
+    };
+
+    let eq_toggle = bypass_toggle("EQ", effects.eq.bypass, move |bypass| {
+        let mut effects = effects;
+        effects.eq.bypass = bypass;
+        Message::MasterEffectsChanged(effects)
+    });
+    let bands = effects.eq.bands.iter().enumerate().map(|(i, band)| {
+        row![
+            text(band.kind.to_string()).size(16).width(Length::Fixed(80.0)).style(LABEL_COLOR),
+            control(
+                "Freq",
+                0.0..=1.0,
+                frequency_to_position(band.frequency),
+                0.001,
+                format!("{:.0} Hz", band.frequency),
+                with_band(i, |band, position| band.frequency = position_to_frequency(position).round()),
+            ),
+            control(
+                "Gain",
+                -eq::MAX_GAIN_DB..=eq::MAX_GAIN_DB,
+                band.gain_db,
+                0.5,
+                format!("{:+.1} dB", band.gain_db),
+                with_band(i, |band, gain_db| band.gain_db = gain_db),
+            ),
+            control(
+                "Q",
+                eq::MIN_Q..=eq::MAX_Q,
+                band.q,
+                0.1,
+                format!("{:.1}", band.q),
+                with_band(i, |band, q| band.q = q),
+            ),
+        ]
+        .spacing(20)
+        .align_items(iced::Alignment::Center)
+        .into()
+    });
+
+    let c = effects.compressor;
+    let compressor_toggle = bypass_toggle("Compressor", c.bypass, move |bypass| {
+        let mut effects = effects;
+        effects.compressor.bypass = bypass;
+        Message::MasterEffectsChanged(effects)
+    });
+    let compressor_controls = column![
+        row![
+            control(
+                "Threshold",
+                compressor::MIN_THRESHOLD_DB..=0.0,
+                c.threshold_db,
+                1.0,
+                format!("{:.0} dB", c.threshold_db),
+                with_compressor(|c, v| c.threshold_db = v),
+            ),
+            control(
+                "Ratio",
+                1.0..=compressor::MAX_RATIO,
+                c.ratio,
+                0.1,
+                format!("{:.1}:1", c.ratio),
+                with_compressor(|c, v| c.ratio = v),
+            ),
+            control(
+                "Makeup",
+                0.0..=compressor::MAX_MAKEUP_DB,
+                c.makeup_db,
+                0.5,
+                format!("{:+.1} dB", c.makeup_db),
+                with_compressor(|c, v| c.makeup_db = v),
+            ),
+        ]
+        .spacing(20),
+        row![
+            control(
+                "Attack",
+                0.1..=compressor::MAX_ATTACK_MS,
+                c.attack_ms,
+                0.1,
+                format!("{:.1} ms", c.attack_ms),
+                with_compressor(|c, v| c.attack_ms = v),
+            ),
+            control(
+                "Release",
+                1.0..=compressor::MAX_RELEASE_MS,
+                c.release_ms,
+                1.0,
+                format!("{:.0} ms", c.release_ms),
+                with_compressor(|c, v| c.release_ms = v),
+            ),
+        ]
+        .spacing(20),
+    ]
+    .spacing(6);
+
+    column![
+        row![eq_toggle, Column::with_children(bands).spacing(6)].spacing(20),
+        row![compressor_toggle, compressor_controls].spacing(20),
+    ]
+    .spacing(12)
+    .into()
+}
@@ -0,0 +1,212 @@
+use iced::mouse;
+use iced::touch;
+use iced::widget::canvas::{self, event, Event, Frame, Geometry, Path, Stroke};
+use iced::{Color, Point, Rectangle, Renderer, Size, Theme};
+use std::collections::HashMap;
+
+use super::Message;
+use crate::midi::KeyState;
+
+/// A0, the lowest key on an 88-key piano.
+pub const LOWEST_NOTE: u8 = 21;
+/// C8, the highest key on an 88-key piano.
+pub const HIGHEST_NOTE: u8 = 108;
+
+const WHITE_KEY_COUNT: usize = 52;
+const BLACK_KEY_WIDTH: f32 = 0.6; // relative to a white key
+const BLACK_KEY_HEIGHT: f32 = 0.62; // relative to the keyboard
+
+// Touching near the front edge of a key plays louder, like a real keybed.
+const MIN_CLICK_VELOCITY: f32 = 30.0;
+const MAX_CLICK_VELOCITY: f32 = 127.0;
+
+pub fn is_black(note: u8) -> bool {
+    matches!(note % 12, 1 | 3 | 6 | 8 | 10)
+}
+
+/// Number of white keys between A0 and `note` (exclusive).
+fn white_index(note: u8) -> usize {
+    (LOWEST_NOTE..note).filter(|n| !is_black(*n)).count()
+}
+
+/// Horizontal position and size of every key, for a keyboard of the given width.
+pub(super) struct KeyLayout {
+    white_width: f32,
+    height: f32,
+}
+
+impl KeyLayout {
+    pub(super) fn new(size: Size) -> Self {
+        KeyLayout {
+            white_width: size.width / WHITE_KEY_COUNT as f32,
+            height: size.height,
+        }
+    }
+
+    pub(super) fn key_rect(&self, note: u8) -> Rectangle {
+        let x = white_index(note) as f32 * self.white_width;
+        if is_black(note) {
+            let width = self.white_width * BLACK_KEY_WIDTH;
+            Rectangle::new(
+                Point::new(x - width / 2.0, 0.0),
+                Size::new(width, self.height * BLACK_KEY_HEIGHT),
+            )
+        } else {
+            Rectangle::new(Point::new(x, 0.0), Size::new(self.white_width, self.height))
+        }
+    }
+
+    /// Key under `position`. Black keys sit on top, so they win.
+    fn note_at(&self, position: Point) -> Option<u8> {
+        let keys = LOWEST_NOTE..=HIGHEST_NOTE;
+        keys.clone()
+            .filter(|n| is_black(*n))
+            .chain(keys.filter(|n| !is_black(*n)))
+            .find(|n| self.key_rect(*n).contains(position))
+    }
+
+    fn velocity_at(&self, note: u8, position: Point) -> u8 {
+        let rect = self.key_rect(note);
+        let depth = ((position.y - rect.y) / rect.height).clamp(0.0, 1.0);
+        (MIN_CLICK_VELOCITY + depth * (MAX_CLICK_VELOCITY - MIN_CLICK_VELOCITY)).round() as u8
+    }
+}
+
+/// Who is holding an on-screen key down: the mouse, or a finger on a touch screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Pointer {
+    Mouse,
+    Finger(u64),
+}
+
+#[derive(Default)]
+pub struct KeyboardState {
+    pressed: HashMap<Pointer, u8>,
+}
+
+/// The on-screen 88-key keyboard. Draws a snapshot of the shared key state and
+/// turns clicks and touches into notes.
+pub struct PianoKeyboard {
+    keys: KeyState,
+}
+
+impl PianoKeyboard {
+    pub fn new(keys: KeyState) -> Self {
+        PianoKeyboard { keys }
+    }
+
+    fn key_color(&self, note: u8) -> Color {
+        if let Some(velocity) = self.keys.velocity(note) {
+            // Forest green, brighter the harder the key was struck
+            let strength = 0.5 + 0.5 * velocity as f32 / 127.0;
+            Color::from_rgb(0.13 * strength, 0.55 * strength + 0.2, 0.13 * strength)
+        } else if self.keys.is_sustained(note) {
+            Color::from_rgb8(150, 110, 200) // Soft purple: ringing on the pedal
+        } else if is_black(note) {
+            Color::from_rgb8(25, 5, 40)
+        } else {
+            Color::from_rgb8(240, 250, 240)
+        }
+    }
+}
+
+impl canvas::Program<Message> for PianoKeyboard {
+    type State = KeyboardState;
+
+    fn update(
+        &self,
+        state: &mut KeyboardState,
+        event: Event,
+        bounds: Rectangle,
+        cursor: mouse::Cursor,
+    ) -> (event::Status, Option<Message>) {
+        let layout = KeyLayout::new(bounds.size());
+
+        let (pointer, position, down) = match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                match cursor.position_in(bounds) {
+                    Some(position) => (Pointer::Mouse, position, true),
+                    None => return (event::Status::Ignored, None),
+                }
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                (Pointer::Mouse, Point::ORIGIN, false)
+            }
+            Event::Touch(touch::Event::FingerPressed { id, position }) => {
+                if !bounds.contains(position) {
+                    return (event::Status::Ignored, None);
+                }
+                let position = Point::new(position.x - bounds.x, position.y - bounds.y);
+                (Pointer::Finger(id.0), position, true)
+            }
+            Event::Touch(
+                touch::Event::FingerLifted { id, .. } | touch::Event::FingerLost { id, .. },
+            ) => (Pointer::Finger(id.0), Point::ORIGIN, false),
+            _ => return (event::Status::Ignored, None),
+        };
+
+        if down {
+            match layout.note_at(position) {
+                Some(note) => {
+                    state.pressed.insert(pointer, note);
+                    let velocity = layout.velocity_at(note, position);
+                    (event::Status::Captured, Some(Message::ScreenKeyPressed(note, velocity)))
+                }
+                None => (event::Status::Ignored, None),
+            }
+        } else {
+            match state.pressed.remove(&pointer) {
+                Some(note) => (event::Status::Captured, Some(Message::ScreenKeyReleased(note))),
+                None => (event::Status::Ignored, None),
+            }
+        }
+    }
+
+    fn draw(
+        &self,
+        _state: &KeyboardState,
+        renderer: &Renderer,
+        _theme: &Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(renderer, bounds.size());
+        let layout = KeyLayout::new(bounds.size());
+        let outline = Stroke::default()
+            .with_color(Color::from_rgb8(60, 30, 80))
+            .with_width(1.0);
+
+        // White keys first so the black keys are drawn on top of them
+        let keys = LOWEST_NOTE..=HIGHEST_NOTE;
+        for note in keys.clone().filter(|n| !is_black(*n)).chain(keys.filter(|n| is_black(*n))) {
+            let rect = layout.key_rect(note);
+            let path = Path::rectangle(rect.position(), rect.size());
+            frame.fill(&path, self.key_color(note));
+            frame.stroke(&path, outline.clone());
+        }
+
+        // A thin bar across the top while the sustain pedal is down
+        if self.keys.pedal() {
+            frame.fill_rectangle(
+                Point::ORIGIN,
+                Size::new(bounds.width, 3.0),
+                Color::from_rgb8(150, 110, 200),
+            );
+        }
+
+        vec![frame.into_geometry()]
+    }
+
+    fn mouse_interaction(
+        &self,
+        _state: &KeyboardState,
+        bounds: Rectangle,
+        cursor: mouse::Cursor,
+    ) -> mouse::Interaction {
+        if cursor.is_over(bounds) {
+            mouse::Interaction::Pointer
+        } else {
+            mouse::Interaction::default()
+        }
+    }
+}
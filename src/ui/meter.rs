@@ -0,0 +1,174 @@
+use iced::mouse;
+use iced::widget::canvas::{self, Frame, Geometry, Path};
+use iced::{Color, Point, Rectangle, Renderer, Size, Theme};
+use std::time::{Duration, Instant};
+
+use super::Message;
+use crate::audio::meter::ChannelLevel;
+
+// Range shown on the meter
+const FLOOR_DB: f32 = -60.0;
+const CEILING_DB: f32 = 0.0;
+
+// Colors change at these levels
+const WARN_DB: f32 = -12.0;
+const HOT_DB: f32 = -3.0;
+
+/// How fast the peak bar falls back, in dB per second, like a PPM.
+const PEAK_FALL: f32 = 20.0;
+const PEAK_HOLD: Duration = Duration::from_millis(1500);
+const CLIP_HOLD: Duration = Duration::from_secs(2);
+
+const CLIP_WIDTH: f32 = 10.0;
+const BAR_GAP: f32 = 3.0;
+
+fn to_db(level: f32) -> f32 {
+    if level <= 0.0 {
+        FLOOR_DB
+    } else {
+        (20.0 * level.log10()).clamp(FLOOR_DB, CEILING_DB)
+    }
+}
+
+/// What one channel's meter shows, with the ballistics that make it readable.
+#[derive(Debug, Clone, Copy)]
+struct ChannelDisplay {
+    rms_db: f32,
+    peak_db: f32,
+    hold_db: f32,
+    hold_until: Instant,
+    clip_until: Option<Instant>,
+}
+
+/// Meter state kept by the app between frames.
+#[derive(Debug, Clone, Copy)]
+pub struct LevelDisplay {
+    channels: [ChannelDisplay; 2],
+    updated: Instant,
+}
+
+impl Default for LevelDisplay {
+    fn default() -> Self {
+        let now = Instant::now();
+        let channel = ChannelDisplay {
+            rms_db: FLOOR_DB,
+            peak_db: FLOOR_DB,
+            hold_db: FLOOR_DB,
+            hold_until: now,
+            clip_until: None,
+        };
+        LevelDisplay {
+            channels: [channel; 2],
+            updated: now,
+        }
+    }
+}
+
+impl LevelDisplay {
+    /// Folds in the levels measured since the last update.
+    pub fn update(&mut self, levels: [ChannelLevel; 2]) {
+        let now = Instant::now();
+        let fall = PEAK_FALL * now.duration_since(self.updated).as_secs_f32();
+        self.updated = now;
+
+        for (display, level) in self.channels.iter_mut().zip(levels) {
+            let peak_db = to_db(level.peak);
+            display.rms_db = to_db(level.rms);
+            display.peak_db = peak_db.max(display.peak_db - fall);
+            if peak_db >= display.hold_db || now >= display.hold_until {
+                display.hold_db = peak_db;
+                display.hold_until = now + PEAK_HOLD;
+            }
+            if level.clipped {
+                display.clip_until = Some(now + CLIP_HOLD);
+            }
+        }
+    }
+}
+
+/// Stereo output meter: RMS as the solid bar, peak as the paler one over it,
+/// a tick at the held peak, and a clip light at the end.
+pub struct LevelMeters {
+    display: LevelDisplay,
+}
+
+impl LevelMeters {
+    pub fn new(display: LevelDisplay) -> Self {
+        LevelMeters { display }
+    }
+}
+
+fn level_color(db: f32) -> Color {
+    if db >= HOT_DB {
+        Color::from_rgb(0.9, 0.2, 0.2)
+    } else if db >= WARN_DB {
+        Color::from_rgb(0.9, 0.8, 0.2)
+    } else {
+        Color::from_rgb8(34, 139, 34) // Forest Green
+    }
+}
+
+impl canvas::Program<Message> for LevelMeters {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &(),
+        renderer: &Renderer,
+        _theme: &Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(renderer, bounds.size());
+        let now = Instant::now();
+
+        let bar_width = bounds.width - CLIP_WIDTH - BAR_GAP;
+        let bar_height = (bounds.height - BAR_GAP) / 2.0;
+        let x_of = |db: f32| (db - FLOOR_DB) / (CEILING_DB - FLOOR_DB) * bar_width;
+
+        for (i, channel) in self.display.channels.iter().enumerate() {
+            let y = i as f32 * (bar_height + BAR_GAP);
+
+            frame.fill_rectangle(Point::new(0.0, y), Size::new(bar_width, bar_height), Color::from_rgb8(40, 10, 60));
+
+            let peak_color = level_color(channel.peak_db);
+            frame.fill_rectangle(
+                Point::new(0.0, y),
+                Size::new(x_of(channel.peak_db), bar_height),
+                Color { a: 0.45, ..peak_color },
+            );
+            frame.fill_rectangle(
+                Point::new(0.0, y),
+                Size::new(x_of(channel.rms_db), bar_height),
+                level_color(channel.rms_db),
+            );
+            if channel.hold_db > FLOOR_DB {
+                frame.fill_rectangle(
+                    Point::new(x_of(channel.hold_db) - 1.0, y),
+                    Size::new(2.0, bar_height),
+                    level_color(channel.hold_db),
+                );
+            }
+
+            let clipping = channel.clip_until.is_some_and(|until| now < until);
+            let clip = Path::rectangle(Point::new(bar_width + BAR_GAP, y), Size::new(CLIP_WIDTH, bar_height));
+            frame.fill(
+                &clip,
+                if clipping { Color::from_rgb(1.0, 0.1, 0.1) } else { Color::from_rgb8(60, 30, 80) },
+            );
+        }
+
+        // Ticks every 12 dB
+        let mut db = FLOOR_DB + 12.0;
+        while db < CEILING_DB {
+            frame.fill_rectangle(
+                Point::new(x_of(db), 0.0),
+                Size::new(1.0, bounds.height),
+                Color { a: 0.3, ..Color::WHITE },
+            );
+            db += 12.0;
+        }
+
+        vec![frame.into_geometry()]
+    }
+}
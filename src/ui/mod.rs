@@ -1,62 +1,319 @@
-use iced::widget::{button, column, container, pick_list, row, text, vertical_space};
-use iced::{executor, Application, Color, Command, Element, Length, Theme};
+mod effects_panel;
+mod keyboard;
+mod meter;
+mod qwerty;
+mod roll;
+mod staff;
+mod velocity_curve;
+
+use iced::widget::{button, canvas, checkbox, column, container, pick_list, row, slider, text, text_input, vertical_space};
+use iced::{executor, Application, Color, Command, Element, Length, Subscription, Theme};
 use midir::{MidiInput, MidiInputConnection};
-use std::sync::Arc;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use crate::audio::convolution::MAX_PRE_DELAY_MS;
+use crate::audio::effects::MasterEffects;
+use crate::audio::jingle::StartupJingle;
+use crate::audio::synth_effects::RoomPreset;
 use crate::audio::AudioEngine;
+use crate::midi::transpose::{MAX_OCTAVE_SHIFT, MAX_TRANSPOSE};
+use crate::midi::calibration::Calibration;
+use crate::midi::velocity::{CurveKind, VelocityCurve};
+use crate::audio::metronome::{self, MetronomeSettings, Subdivision};
+use crate::midi::scala::ScalaFiles;
+use crate::midi::tuning::{self, Temperament, TuningSettings, COMMON_REFERENCES};
+use crate::midi::zones::{KeyboardMode, ZoneSettings};
+use crate::midi::file::MidiFile;
+use keyboard::PianoKeyboard;
+use meter::{LevelDisplay, LevelMeters};
+use qwerty::{QwertyAction, QwertyKeyboard};
+use roll::{PianoRoll, RollSource};
+use staff::GrandStaff;
+use velocity_curve::VelocityCurveEditor;
+use crate::settings::Settings;
+use crate::theory::KeySignature;
+use std::time::{Duration, Instant};
+
+/// How long to wait after the last change before writing the settings file,
+/// so dragging the window edge doesn't rewrite it on every frame.
+const SETTINGS_SAVE_DELAY: Duration = Duration::from_secs(1);
+
+/// Top of the volume slider. Past unity the limiter does the rest.
+const MAX_VOLUME: f32 = 1.5;
 
 pub struct ToyPianoApp {
-    audio_engine: Arc<AudioEngine>,
+    audio_engine: Rc<AudioEngine>,
     midi_connection: Option<MidiInputConnection<()>>, // Holds the active connection
     available_ports: Vec<String>,
     selected_port: Option<String>,
+    /// Velocity curve of the selected port
+    velocity_curve: VelocityCurve,
+    /// The velocity calibration in progress, if any
+    calibration: Option<Calibration>,
+    /// Raw note-on velocities from the MIDI input, collected while calibrating
+    raw_velocities: Arc<Mutex<Option<Vec<u8>>>>,
     status_message: String,
+    qwerty: QwertyKeyboard,
+    midi_file_path: String,
+    midi_file: Option<MidiFile>,
+    impulse_response_path: String,
+    scale_path: String,
+    keyboard_mapping_path: String,
+    /// Name of the Scala scale playing, if one is
+    scale_name: Option<String>,
+    /// Serial of the last tapped tempo taken into the settings
+    tapped_serial: Option<u64>,
+    jingle_path: String,
+    show_effects: bool,
+    key_signature: KeySignature,
+    presets: Vec<PresetChoice>,
+    levels: LevelDisplay,
+    settings: Settings,
+    settings_changed: Option<Instant>,
+}
+
+/// What the app starts with.
+pub struct Startup {
+    pub audio_engine: Rc<AudioEngine>,
+    pub settings: Settings,
+    /// MIDI input to connect to instead of the remembered one
+    pub midi_port: Option<String>,
+}
+
+/// A SoundFont preset as shown in the sound picker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresetChoice {
+    bank: u8,
+    program: u8,
+    name: String,
+}
+
+/// A key for the split point picker, named in the current key signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplitChoice {
+    note: u8,
+    key: KeySignature,
+}
+
+impl std::fmt::Display for SplitChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let spelled = crate::theory::spell(self.note, self.key);
+        write!(f, "{}{}", spelled.name(), spelled.octave)
+    }
+}
+
+/// What to call a Scala scale: its description, or the file name if it hasn't one.
+fn scale_name(description: &str, path: &std::path::Path) -> String {
+    if description.is_empty() {
+        path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+    } else {
+        description.to_string()
+    }
+}
+
+/// An A4 reference pitch, Hz.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReferenceChoice(f64);
+
+impl std::fmt::Display for ReferenceChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} Hz", self.0)
+    }
+}
+
+/// The pitch class a temperament is laid out from, named in the current key signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TonicChoice {
+    pitch_class: u8,
+    key: KeySignature,
+}
+
+impl std::fmt::Display for TonicChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&crate::theory::spell(60 + self.pitch_class, self.key).name())
+    }
+}
+
+impl std::fmt::Display for PresetChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:03}:{:03} {}", self.bank, self.program, self.name)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     PortSelected(String),
+    /// The port connected to at startup, which isn't a choice to remember:
+    /// it may come from the command line
+    StartupPort(String),
+    VelocityCurveKindSelected(CurveKind),
+    VelocityCurveChanged(VelocityCurve),
+    StartCalibration,
+    NextCalibrationStep,
+    CancelCalibration,
     Rescan,
     OpenGitHub,
+    KeyPressed(char),
+    KeyReleased(char),
+    /// The window lost focus, so it won't hear keys being let go
+    WindowUnfocused,
+    ScreenKeyPressed(u8, u8),
+    ScreenKeyReleased(u8),
+    MidiFilePathChanged(String),
+    PlayMidiFile,
+    StopMidiFile,
+    KeySignatureSelected(KeySignature),
+    PresetSelected(PresetChoice),
+    VolumeChanged(f32),
+    TransposeChanged(i32),
+    OctaveShiftChanged(i32),
+    ResetTranspose,
+    ZonesChanged(ZoneSettings),
+    TuningChanged(TuningSettings),
+    MetronomeChanged(MetronomeSettings),
+    TapTempo,
+    LearnTapTrigger,
+    ForgetTapTrigger,
+    WrittenSongTempo,
+    JingleToggled(bool),
+    JinglePathChanged(String),
+    LoadJingle,
+    BuiltInJingle,
+    ScalePathChanged(String),
+    KeyboardMappingPathChanged(String),
+    LoadScala,
+    ClearScala,
+    RoomSelected(RoomPreset),
+    ReverbToggled(bool),
+    ReverbSendChanged(f32),
+    ChorusToggled(bool),
+    ChorusSendChanged(f32),
+    ConvolutionToggled(bool),
+    ImpulseResponsePathChanged(String),
+    LoadImpulseResponse,
+    ConvolutionMixChanged(f32),
+    PreDelayChanged(f32),
+    EffectsPanelToggled,
+    MasterEffectsChanged(MasterEffects),
+    WindowResized(u32, u32),
+    CloseRequested(iced::window::Id),
+    Tick,
 }
 
 impl Application for ToyPianoApp {
     type Executor = executor::Default;
     type Message = Message;
     type Theme = Theme;
-    type Flags = Arc<AudioEngine>;
-
-    fn new(audio_engine: Arc<AudioEngine>) -> (Self, Command<Message>) {
-        let ports = match MidiInput::new("Toy Piano UI Input") {
-            Ok(input) => {
-                let ports = input.ports();
-                ports.iter()
-                    .map(|p| input.port_name(p).unwrap_or_else(|_| "Unknown".to_string()))
-                    .collect()
-            },
-            Err(_) => vec![]
-        };
+    type Flags = Startup;
+
+    fn new(startup: Startup) -> (Self, Command<Message>) {
+        let Startup { audio_engine, settings, midi_port } = startup;
+        let ports = crate::midi::list_ports().unwrap_or_default();
+
+        // The port asked for on the command line, else the device used last
+        // time if it's plugged in, otherwise the first one
+        let preferred_port = match &midi_port {
+            Some(wanted) => crate::midi::find_port(&ports, wanted).map(|i| &ports[i]),
+            None => settings.midi_device.as_ref().filter(|name| ports.contains(name)),
+        }
+        .or(ports.first());
 
-        let (selected_port, status_message) = if let Some(first_port) = ports.first() {
+        let (selected_port, status_message) = if let Some(first_port) = preferred_port {
             // We initiate a connection command in the init (handled by update via a new 'Init' message or just simulate selection)
             // But we can't emit a message from 'new' easily without 'Task' (Command).
             // A simple way is to set selected_port here, but the ACTUAL connection logic is in 'update'.
-            // To trigger the connection on startup, we return a Command equivalent to PortSelected.
+            // To trigger the connection on startup, we return a Command with StartupPort.
             (Some(first_port.clone()), format!("Connecting to {}...", first_port))
         } else {
             (None, "Ready. Select a MIDI Input.".to_string())
         };
 
+        audio_engine.set_volume(settings.volume);
+        audio_engine.select_preset(settings.bank, settings.program);
+        audio_engine.set_transpose(settings.transpose);
+        audio_engine.set_octave_shift(settings.octave_shift);
+        audio_engine.set_zones(&settings.zones);
+        audio_engine.set_tuning(&settings.tuning);
+        let scale_name = settings.scala.as_ref().and_then(|files| {
+            match audio_engine.load_scala(files, settings.tuning.reference) {
+                Ok(scale) => Some(scale_name(&scale.description, &files.scale)),
+                Err(e) => {
+                    log::warn!("{:#}", e);
+                    None
+                }
+            }
+        });
+        audio_engine.set_synth_effects(&settings.synth_effects);
+        audio_engine.set_metronome(&settings.metronome);
+        audio_engine.set_tap_trigger(settings.tap_trigger);
+        if let Some(path) = &settings.impulse_response {
+            if let Err(e) = audio_engine.load_impulse_response(path) {
+                log::warn!("{:#}", e);
+            }
+        }
+        audio_engine.set_convolution(settings.convolution);
+        audio_engine.set_master_effects(&settings.master_effects);
+        // Last, so it plays with the sound and tuning picked above
+        if let Err(e) = audio_engine.play_jingle(&settings.startup_jingle) {
+            log::warn!("{:#}", e);
+        }
+        let impulse_response_path = settings
+            .impulse_response
+            .as_ref()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let scale_path = settings
+            .scala
+            .as_ref()
+            .map(|files| files.scale.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let keyboard_mapping_path = settings
+            .scala
+            .as_ref()
+            .and_then(|files| files.mapping.as_ref())
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let jingle_path = settings
+            .startup_jingle
+            .file
+            .as_ref()
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let presets = audio_engine
+            .presets()
+            .into_iter()
+            .map(|(bank, program, name)| PresetChoice { bank, program, name })
+            .collect();
+
         let app = ToyPianoApp {
             audio_engine,
             midi_connection: None,
             available_ports: ports,
             selected_port, // Pre-select in UI
+            velocity_curve: VelocityCurve::Linear,
+            calibration: None,
+            raw_velocities: Arc::new(Mutex::new(None)),
             status_message, 
+            qwerty: QwertyKeyboard::default(),
+            midi_file_path: String::new(),
+            midi_file: None,
+            impulse_response_path,
+            scale_path,
+            keyboard_mapping_path,
+            scale_name,
+            tapped_serial: None,
+            jingle_path,
+            show_effects: false,
+            key_signature: KeySignature::default(),
+            presets,
+            levels: LevelDisplay::default(),
+            settings,
+            settings_changed: None,
         };
 
         // If we have a port, trigger the connection logic immediately
         let command = if let Some(port) = app.selected_port.clone() {
-            Command::perform(async move { port }, Message::PortSelected)
+            Command::perform(async move { port }, Message::StartupPort)
         } else {
             Command::none()
         };
@@ -83,47 +340,374 @@ impl Application for ToyPianoApp {
                }
             }
             Message::PortSelected(port_name) => {
-                self.selected_port = Some(port_name.clone());
-                self.status_message = format!("Connecting to {}...", port_name);
-                
-                // Disconnect old
-                self.midi_connection = None;
-
-                // Connect new
-                if let Ok(input) = MidiInput::new("Toy Piano Input Connection") {
-                     let ports = input.ports();
-                     if let Some(port) = ports.into_iter().find(|p| input.port_name(p).unwrap_or_default() == port_name) {
-                         
-                         let synth = self.audio_engine.get_synthesizer();
-                         
-                        let conn_result = input.connect(
-                            &port,
-                            "toy-piano-input-ui",
-                            move |_stamp, message, _| {
-                                crate::midi::handle_midi_message(message, &synth);
-                            },
-                            (),
-                        );
-                        
-                        match conn_result {
-                            Ok(conn) => {
-                                self.status_message = format!("Connected to {}", port_name);
-                                self.midi_connection = Some(conn);
-                            },
+                if self.connect_port(&port_name) {
+                    self.settings.midi_device = Some(port_name);
+                    self.settings_changed();
+                }
+            }
+            Message::StartupPort(port_name) => {
+                self.connect_port(&port_name);
+            }
+            Message::VelocityCurveKindSelected(kind) => {
+                let curve = self.velocity_curve.convert(kind);
+                return self.update(Message::VelocityCurveChanged(curve));
+            }
+            Message::VelocityCurveChanged(curve) => {
+                self.audio_engine.set_input_velocity_curve(&curve);
+                if let Some(port) = &self.selected_port {
+                    if curve == VelocityCurve::Linear {
+                        self.settings.velocity_curves.remove(port);
+                    } else {
+                        self.settings.velocity_curves.insert(port.clone(), curve.clone());
+                    }
+                    self.settings_changed();
+                }
+                self.velocity_curve = curve;
+            }
+            Message::StartCalibration => {
+                *self.raw_velocities.lock().unwrap() = Some(Vec::new());
+                self.calibration = Some(Calibration::default());
+            }
+            Message::NextCalibrationStep => {
+                if let Some(calibration) = &mut self.calibration {
+                    calibration.advance();
+                    if calibration.dynamic().is_none() {
+                        let fitted = calibration.fit();
+                        self.calibration = None;
+                        *self.raw_velocities.lock().unwrap() = None;
+                        match fitted {
+                            Ok(curve) => {
+                                self.status_message = format!(
+                                    "Calibrated {}",
+                                    self.selected_port.as_deref().unwrap_or("the keyboard")
+                                );
+                                return self.update(Message::VelocityCurveChanged(curve));
+                            }
                             Err(e) => {
-                                self.status_message = format!("Failed to connect: {}", e);
+                                self.status_message = format!("Calibration failed: {:#}", e);
                             }
                         }
-                     }
+                    }
                 }
             }
+            Message::CancelCalibration => {
+                self.calibration = None;
+                *self.raw_velocities.lock().unwrap() = None;
+            }
             Message::OpenGitHub => {
                 let _ = open::that("https://github.com/jergas/toy-piano");
             }
+            Message::KeyPressed(key) => {
+                if let Some(action) = self.qwerty.key_pressed(key) {
+                    self.apply_qwerty_action(action);
+                }
+            }
+            Message::KeyReleased(key) => {
+                if let Some(action) = self.qwerty.key_released(key) {
+                    self.apply_qwerty_action(action);
+                }
+            }
+            Message::WindowUnfocused => {
+                for action in self.qwerty.release_all() {
+                    self.apply_qwerty_action(action);
+                }
+            }
+            Message::ScreenKeyPressed(note, velocity) => {
+                crate::midi::handle_midi_message(&[0x90, note, velocity], &self.audio_engine.midi_target());
+            }
+            Message::ScreenKeyReleased(note) => {
+                crate::midi::handle_midi_message(&[0x80, note, 0], &self.audio_engine.midi_target());
+            }
+            Message::MidiFilePathChanged(path) => {
+                self.midi_file_path = path;
+            }
+            Message::PlayMidiFile => {
+                let path = std::path::Path::new(self.midi_file_path.trim());
+                match MidiFile::load(path) {
+                    Ok(file) => {
+                        self.audio_engine.play_midi_file(&file);
+                        self.status_message = format!(
+                            "Playing {} ({:.0}s)",
+                            path.file_name().unwrap_or_default().to_string_lossy(),
+                            file.duration()
+                        );
+                        self.midi_file = Some(file);
+                    }
+                    Err(e) => {
+                        self.status_message = format!("{:#}", e);
+                    }
+                }
+            }
+            Message::StopMidiFile => {
+                self.audio_engine.stop_playback();
+                self.status_message = "Playback stopped.".to_string();
+            }
+            Message::KeySignatureSelected(key) => {
+                self.key_signature = key;
+            }
+            Message::PresetSelected(preset) => {
+                self.audio_engine.select_preset(preset.bank, preset.program);
+                self.status_message = format!("Sound: {}", preset.name);
+                self.settings.bank = preset.bank;
+                self.settings.program = preset.program;
+                self.settings_changed();
+            }
+            Message::VolumeChanged(volume) => {
+                self.audio_engine.set_volume(volume);
+                self.settings.volume = volume;
+                self.settings_changed();
+            }
+            Message::TransposeChanged(semitones) => {
+                let semitones = semitones.clamp(-MAX_TRANSPOSE, MAX_TRANSPOSE);
+                self.audio_engine.set_transpose(semitones);
+                self.settings.transpose = semitones;
+                self.settings_changed();
+            }
+            Message::OctaveShiftChanged(octaves) => {
+                let octaves = octaves.clamp(-MAX_OCTAVE_SHIFT, MAX_OCTAVE_SHIFT);
+                self.audio_engine.set_octave_shift(octaves);
+                self.settings.octave_shift = octaves;
+                self.settings_changed();
+            }
+            Message::ResetTranspose => {
+                self.audio_engine.set_transpose(0);
+                self.audio_engine.set_octave_shift(0);
+                self.settings.transpose = 0;
+                self.settings.octave_shift = 0;
+                self.settings_changed();
+            }
+            Message::ZonesChanged(zones) => {
+                self.audio_engine.set_zones(&zones);
+                self.settings.zones = zones;
+                self.settings_changed();
+            }
+            Message::TuningChanged(tuning) => {
+                let tuning = TuningSettings {
+                    reference: tuning.reference.clamp(tuning::MIN_REFERENCE, tuning::MAX_REFERENCE),
+                    ..tuning
+                };
+                self.settings.tuning = tuning;
+                self.tuning_changed();
+            }
+            Message::MetronomeChanged(metronome) => {
+                self.audio_engine.set_metronome(&metronome);
+                self.settings.metronome = metronome;
+                self.settings_changed();
+            }
+            Message::TapTempo => {
+                self.audio_engine.tap_tempo();
+            }
+            Message::LearnTapTrigger => {
+                self.audio_engine.midi_target().tap_tempo.learn();
+                self.status_message = "Press the key or pedal to tap the tempo with...".to_string();
+            }
+            Message::ForgetTapTrigger => {
+                self.audio_engine.set_tap_trigger(None);
+                self.settings.tap_trigger = None;
+                self.settings_changed();
+            }
+            Message::WrittenSongTempo => {
+                self.audio_engine.set_song_tempo(None);
+            }
+            Message::JingleToggled(enabled) => {
+                self.settings.startup_jingle.enabled = enabled;
+                self.settings_changed();
+            }
+            Message::JinglePathChanged(path) => {
+                self.jingle_path = path;
+            }
+            Message::LoadJingle => {
+                let path = self.jingle_path.trim();
+                if path.is_empty() {
+                    return self.update(Message::BuiltInJingle);
+                }
+                let jingle = StartupJingle { enabled: true, file: Some(std::path::PathBuf::from(path)) };
+                // Play it now, which also checks it can be played
+                match self.audio_engine.play_jingle(&jingle) {
+                    Ok(()) => {
+                        self.status_message = format!(
+                            "Startup jingle: {}",
+                            std::path::Path::new(path).file_name().unwrap_or_default().to_string_lossy()
+                        );
+                        self.settings.startup_jingle = jingle;
+                        self.settings_changed();
+                    }
+                    Err(e) => {
+                        self.status_message = format!("{:#}", e);
+                    }
+                }
+            }
+            Message::BuiltInJingle => {
+                let jingle = StartupJingle::default();
+                if let Err(e) = self.audio_engine.play_jingle(&jingle) {
+                    self.status_message = format!("{:#}", e);
+                }
+                self.jingle_path.clear();
+                self.settings.startup_jingle = jingle;
+                self.settings_changed();
+            }
+            Message::ScalePathChanged(path) => {
+                self.scale_path = path;
+            }
+            Message::KeyboardMappingPathChanged(path) => {
+                self.keyboard_mapping_path = path;
+            }
+            Message::LoadScala => {
+                let mapping = self.keyboard_mapping_path.trim();
+                let files = ScalaFiles {
+                    scale: std::path::PathBuf::from(self.scale_path.trim()),
+                    mapping: (!mapping.is_empty()).then(|| std::path::PathBuf::from(mapping)),
+                };
+                match self.audio_engine.load_scala(&files, self.settings.tuning.reference) {
+                    Ok(scale) => {
+                        let name = scale_name(&scale.description, &files.scale);
+                        self.status_message = format!("Scale: {} ({} notes)", name, scale.len());
+                        self.scale_name = Some(name);
+                        self.settings.scala = Some(files);
+                        self.settings_changed();
+                    }
+                    Err(e) => {
+                        self.status_message = format!("{:#}", e);
+                    }
+                }
+            }
+            Message::ClearScala => {
+                self.settings.scala = None;
+                self.scale_name = None;
+                self.tuning_changed();
+            }
+            Message::RoomSelected(room) => {
+                self.settings.synth_effects.set_room(room);
+                self.synth_effects_changed();
+            }
+            Message::ReverbToggled(enabled) => {
+                self.settings.synth_effects.reverb = enabled;
+                self.synth_effects_changed();
+            }
+            Message::ReverbSendChanged(amount) => {
+                self.settings.synth_effects.reverb_send = amount as u8;
+                self.synth_effects_changed();
+            }
+            Message::ChorusToggled(enabled) => {
+                self.settings.synth_effects.chorus = enabled;
+                self.synth_effects_changed();
+            }
+            Message::ChorusSendChanged(amount) => {
+                self.settings.synth_effects.chorus_send = amount as u8;
+                self.synth_effects_changed();
+            }
+            Message::ConvolutionToggled(enabled) => {
+                self.settings.convolution.enabled = enabled;
+                self.convolution_changed();
+            }
+            Message::ImpulseResponsePathChanged(path) => {
+                self.impulse_response_path = path;
+            }
+            Message::LoadImpulseResponse => {
+                let path = std::path::PathBuf::from(self.impulse_response_path.trim());
+                match self.audio_engine.load_impulse_response(&path) {
+                    Ok(()) => {
+                        self.status_message = format!(
+                            "Impulse response: {}",
+                            path.file_name().unwrap_or_default().to_string_lossy()
+                        );
+                        self.settings.impulse_response = Some(path);
+                        self.settings.convolution.enabled = true;
+                        self.convolution_changed();
+                    }
+                    Err(e) => {
+                        self.status_message = format!("{:#}", e);
+                    }
+                }
+            }
+            Message::ConvolutionMixChanged(mix) => {
+                self.settings.convolution.mix = mix;
+                self.convolution_changed();
+            }
+            Message::PreDelayChanged(pre_delay_ms) => {
+                self.settings.convolution.pre_delay_ms = pre_delay_ms;
+                self.convolution_changed();
+            }
+            Message::EffectsPanelToggled => {
+                self.show_effects = !self.show_effects;
+            }
+            Message::MasterEffectsChanged(effects) => {
+                self.audio_engine.set_master_effects(&effects);
+                self.settings.master_effects = effects;
+                self.settings_changed();
+            }
+            Message::WindowResized(width, height) => {
+                self.settings.window_width = width as f32;
+                self.settings.window_height = height as f32;
+                self.settings_changed();
+            }
+            Message::CloseRequested(id) => {
+                // A change made within the last second hasn't been saved yet
+                if self.settings_changed.take().is_some() {
+                    if let Err(e) = self.settings.save() {
+                        log::warn!("Failed to save settings: {:#}", e);
+                    }
+                }
+                return iced::window::close(id);
+            }
+            Message::Tick => {
+                if let Some(calibration) = &mut self.calibration {
+                    if let Some(recorded) = self.raw_velocities.lock().unwrap().as_mut() {
+                        recorded.drain(..).for_each(|velocity| calibration.record(velocity));
+                    }
+                }
+                // Tempos tapped on the input device, and a trigger it just learned
+                if let Some(tapped) = self.audio_engine.tapped_tempo() {
+                    if self.tapped_serial != Some(tapped.serial) {
+                        self.tapped_serial = Some(tapped.serial);
+                        self.settings.metronome.bpm = tapped.bpm;
+                        self.settings_changed();
+                    }
+                }
+                let tap_trigger = self.audio_engine.midi_target().tap_tempo.trigger();
+                if tap_trigger != self.settings.tap_trigger {
+                    if let Some(trigger) = tap_trigger {
+                        self.status_message = format!("Tapping the tempo with {}", trigger);
+                    }
+                    self.settings.tap_trigger = tap_trigger;
+                    self.settings_changed();
+                }
+                // The view picks up the latest key state on redraw
+                self.levels.update(self.audio_engine.levels());
+                if self.settings_changed.is_some_and(|t| t.elapsed() >= SETTINGS_SAVE_DELAY) {
+                    self.settings_changed = None;
+                    if let Err(e) = self.settings.save() {
+                        log::warn!("Failed to save settings: {:#}", e);
+                    }
+                }
+            }
         }
         Command::none()
     }
 
+    fn subscription(&self) -> Subscription<Message> {
+        Subscription::batch([
+            iced::keyboard::on_key_press(|key, modifiers| {
+                // Leave shortcuts like Cmd+Q alone
+                if modifiers.command() || modifiers.alt() {
+                    return None;
+                }
+                key_char(&key).map(Message::KeyPressed)
+            }),
+            iced::keyboard::on_key_release(|key, _| key_char(&key).map(Message::KeyReleased)),
+            iced::event::listen_with(|event, _| match event {
+                iced::Event::Window(_, iced::window::Event::Resized { width, height }) => {
+                    Some(Message::WindowResized(width, height))
+                }
+                iced::Event::Window(id, iced::window::Event::CloseRequested) => Some(Message::CloseRequested(id)),
+                iced::Event::Window(_, iced::window::Event::Unfocused) => Some(Message::WindowUnfocused),
+                _ => None,
+            }),
+            // Keep the on-screen keys in step with MIDI arriving from other threads
+            iced::time::every(Duration::from_millis(30)).map(|_| Message::Tick),
+        ])
+    }
+
     fn view(&self) -> Element<'_, Message> {
         // Design: Deep Purple Theme
         let header = text("TOY PIANO")
@@ -134,6 +718,14 @@ impl Application for ToyPianoApp {
             .size(16)
             .style(Color::from_rgb(0.0, 1.0, 0.5)); // Green accent
 
+        let key_state = self.key_state();
+        let chord_name = crate::theory::identify(&key_state.sounding_notes())
+            .map(|chord| chord.name(self.key_signature))
+            .unwrap_or_default();
+        let chord = text(chord_name)
+            .size(28)
+            .style(Color::from_rgb(0.8, 1.0, 0.8));
+
         let port_picker = pick_list(
             self.available_ports.clone(),
             self.selected_port.clone(),
@@ -147,17 +739,549 @@ impl Application for ToyPianoApp {
             .style(iced::theme::Button::Custom(Box::new(ForestGreenButton)))
             .on_press(Message::Rescan);
 
+        let curve_picker = pick_list(&CurveKind::ALL[..], Some(self.velocity_curve.kind()), Message::VelocityCurveKindSelected)
+            .width(Length::Fixed(120.0))
+            .style(iced::theme::PickList::Custom(std::rc::Rc::new(DeepPurplePickList), std::rc::Rc::new(DeepPurpleOverlay)));
+        let curve_controls: Element<'_, Message> = match self.velocity_curve {
+            VelocityCurve::Fixed(velocity) => row![
+                slider(1.0..=127.0, velocity as f32, |v| Message::VelocityCurveChanged(VelocityCurve::Fixed(v as u8)))
+                    .step(1.0)
+                    .width(Length::Fixed(140.0))
+                    .style(iced::theme::Slider::Custom(Box::new(ForestGreenSlider))),
+                text(velocity.to_string()).size(16).style(Color::from_rgb(0.8, 1.0, 0.8)),
+            ]
+            .spacing(10)
+            .align_items(iced::Alignment::Center)
+            .into(),
+            VelocityCurve::Custom(_) => text("click to add points, drag to move, right-click to remove")
+                .size(14)
+                .style(Color::from_rgb(0.6, 0.8, 0.6))
+                .into(),
+            _ => vertical_space().height(0).into(),
+        };
+        let calibrate_button = button("Calibrate...")
+            .style(iced::theme::Button::Custom(Box::new(ForestGreenButton)))
+            .on_press_maybe(
+                (self.midi_connection.is_some() && self.calibration.is_none()).then_some(Message::StartCalibration),
+            );
+        let calibrating = self.calibration.as_ref().and_then(|c| c.dynamic().map(|dynamic| (c, dynamic)));
+        let calibration_panel: Element<'_, Message> = match calibrating {
+            Some((calibration, dynamic)) => {
+                let recorded: Vec<String> = calibration.recorded().iter().map(u8::to_string).collect();
+                column![
+                    text(format!("{}.", dynamic.instruction()))
+                        .size(16)
+                        .style(Color::from_rgb(0.8, 1.0, 0.8)),
+                    row![
+                        text(if recorded.is_empty() {
+                            "Waiting for notes...".to_string()
+                        } else {
+                            format!("Velocities: {}", recorded.join(", "))
+                        })
+                        .size(16)
+                        .style(Color::from_rgb(0.6, 0.8, 0.6)),
+                        button("Next")
+                            .style(iced::theme::Button::Custom(Box::new(ForestGreenButton)))
+                            .on_press_maybe(calibration.can_advance().then_some(Message::NextCalibrationStep)),
+                        button("Cancel")
+                            .style(iced::theme::Button::Custom(Box::new(ForestGreenButton)))
+                            .on_press(Message::CancelCalibration),
+                    ]
+                    .spacing(20)
+                    .align_items(iced::Alignment::Center),
+                ]
+                .spacing(6)
+                .align_items(iced::Alignment::Center)
+                .into()
+            }
+            None => vertical_space().height(0).into(),
+        };
+        let curve_graph = canvas(VelocityCurveEditor::new(self.velocity_curve.clone()))
+            .width(Length::Fixed(90.0))
+            .height(Length::Fixed(60.0));
+
+        let selected_preset = self
+            .presets
+            .iter()
+            .find(|p| p.bank == self.settings.bank && p.program == self.settings.program)
+            .cloned();
+        let preset_picker = pick_list(
+            self.presets.clone(),
+            selected_preset,
+            Message::PresetSelected
+        )
+        .placeholder("Select Sound...")
+        .width(Length::Fixed(300.0))
+        .style(iced::theme::PickList::Custom(std::rc::Rc::new(DeepPurplePickList), std::rc::Rc::new(DeepPurpleOverlay)));
+
+        let volume_slider = slider(0.0..=MAX_VOLUME, self.settings.volume, Message::VolumeChanged)
+            .step(0.01)
+            .width(Length::Fixed(160.0))
+            .style(iced::theme::Slider::Custom(Box::new(ForestGreenSlider)));
+        let volume_label = text(format!("{:.0}%", self.settings.volume * 100.0))
+            .size(16)
+            .width(Length::Fixed(50.0))
+            .style(Color::from_rgb(0.8, 1.0, 0.8));
+
+        let step_button = |label: &'static str, message: Message| {
+            button(text(label).horizontal_alignment(iced::alignment::Horizontal::Center))
+                .width(Length::Fixed(36.0))
+                .style(iced::theme::Button::Custom(Box::new(ForestGreenButton)))
+                .on_press(message)
+        };
+        let step_label = |value: i32| {
+            text(format!("{:+}", value))
+                .size(18)
+                .width(Length::Fixed(40.0))
+                .horizontal_alignment(iced::alignment::Horizontal::Center)
+                .style(Color::from_rgb(0.8, 1.0, 0.8))
+        };
+        let transpose = self.settings.transpose;
+        let octave_shift = self.settings.octave_shift;
+        let reset_transpose = button("Reset")
+            .style(iced::theme::Button::Custom(Box::new(ForestGreenButton)))
+            .on_press_maybe((transpose != 0 || octave_shift != 0).then_some(Message::ResetTranspose));
+
+        let zones = self.settings.zones;
+        let zones_changed = move |change: &dyn Fn(&mut ZoneSettings)| {
+            let mut zones = zones;
+            change(&mut zones);
+            Message::ZonesChanged(zones)
+        };
+        let mode_picker = pick_list(&KeyboardMode::ALL[..], Some(zones.mode), move |mode| {
+            zones_changed(&|z| z.mode = mode)
+        })
+        .width(Length::Fixed(110.0))
+        .style(iced::theme::PickList::Custom(std::rc::Rc::new(DeepPurplePickList), std::rc::Rc::new(DeepPurpleOverlay)));
+        let split_choices: Vec<SplitChoice> =
+            (24..=96).map(|note| SplitChoice { note, key: self.key_signature }).collect();
+        let split_picker = pick_list(
+            split_choices,
+            Some(SplitChoice { note: zones.split_point, key: self.key_signature }),
+            move |choice| zones_changed(&|z| z.split_point = choice.note),
+        )
+        .width(Length::Fixed(90.0))
+        .style(iced::theme::PickList::Custom(std::rc::Rc::new(DeepPurplePickList), std::rc::Rc::new(DeepPurpleOverlay)));
+        let second_preset = self
+            .presets
+            .iter()
+            .find(|p| p.bank == zones.second.bank && p.program == zones.second.program)
+            .cloned();
+        let second_preset_picker = pick_list(self.presets.clone(), second_preset, move |preset| {
+            zones_changed(&|z| (z.second.bank, z.second.program) = (preset.bank, preset.program))
+        })
+        .placeholder("Select Sound...")
+        .width(Length::Fixed(220.0))
+        .style(iced::theme::PickList::Custom(std::rc::Rc::new(DeepPurplePickList), std::rc::Rc::new(DeepPurpleOverlay)));
+        let zone_volume = |volume: u8, set: fn(&mut ZoneSettings, u8)| {
+            slider(0.0..=127.0, volume as f32, move |v| zones_changed(&|z| set(z, v as u8)))
+                .step(1.0)
+                .width(Length::Fixed(90.0))
+                .style(iced::theme::Slider::Custom(Box::new(ForestGreenSlider)))
+        };
+        let zone_transpose = |transpose: i32, set: fn(&mut ZoneSettings, i32)| {
+            let shifted = |by: i32| zones_changed(&|z| set(z, (transpose + by).clamp(-MAX_TRANSPOSE, MAX_TRANSPOSE)));
+            row![step_button("-", shifted(-1)), step_label(transpose), step_button("+", shifted(1))]
+                .spacing(10)
+                .align_items(iced::Alignment::Center)
+        };
+        let (main_name, second_name) = match zones.mode {
+            KeyboardMode::Split => ("Upper:", "Lower:"),
+            _ => ("Main:", "Layer:"),
+        };
+        let zone_rows: Element<'_, Message> = if zones.mode == KeyboardMode::Single {
+            row![
+                text("Keyboard:").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                mode_picker,
+            ]
+            .spacing(20)
+            .align_items(iced::Alignment::Center)
+            .into()
+        } else {
+            let split: Element<'_, Message> = if zones.mode == KeyboardMode::Split {
+                row![text("Split at:").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)), split_picker]
+                    .spacing(10)
+                    .align_items(iced::Alignment::Center)
+                    .into()
+            } else {
+                vertical_space().height(0).into()
+            };
+            column![
+                row![
+                    text("Keyboard:").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                    mode_picker,
+                    split,
+                    text(main_name).size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                    zone_transpose(zones.main_transpose, |z, t| z.main_transpose = t),
+                    zone_volume(zones.main_volume, |z, v| z.main_volume = v),
+                ]
+                .spacing(20)
+                .align_items(iced::Alignment::Center),
+                row![
+                    text(second_name).size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                    second_preset_picker,
+                    zone_transpose(zones.second.transpose, |z, t| z.second.transpose = t),
+                    zone_volume(zones.second.volume, |z, v| z.second.volume = v),
+                ]
+                .spacing(20)
+                .align_items(iced::Alignment::Center),
+            ]
+            .spacing(10)
+            .align_items(iced::Alignment::Center)
+            .into()
+        };
+
+        let tuning = self.settings.tuning;
+        let reference_picker = pick_list(
+            COMMON_REFERENCES.map(ReferenceChoice).to_vec(),
+            Some(ReferenceChoice(tuning.reference)),
+            move |choice| Message::TuningChanged(TuningSettings { reference: choice.0, ..tuning }),
+        )
+        .width(Length::Fixed(110.0))
+        .style(iced::theme::PickList::Custom(std::rc::Rc::new(DeepPurplePickList), std::rc::Rc::new(DeepPurpleOverlay)));
+        let nudge_reference = |by: f64| Message::TuningChanged(TuningSettings { reference: tuning.reference + by, ..tuning });
+        let temperament_picker = pick_list(&Temperament::ALL[..], Some(tuning.temperament), move |temperament| {
+            Message::TuningChanged(TuningSettings { temperament, ..tuning })
+        })
+        .width(Length::Fixed(200.0))
+        .style(iced::theme::PickList::Custom(std::rc::Rc::new(DeepPurplePickList), std::rc::Rc::new(DeepPurpleOverlay)));
+        // Equal temperament sounds the same from any tonic
+        let tonic_picker: Element<'_, Message> = if self.scale_name.is_some() || tuning.temperament == Temperament::Equal {
+            vertical_space().height(0).into()
+        } else {
+            let key = self.key_signature;
+            row![
+                text("in").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                pick_list(
+                    (0..12).map(|pitch_class| TonicChoice { pitch_class, key }).collect::<Vec<_>>(),
+                    Some(TonicChoice { pitch_class: tuning.tonic, key }),
+                    move |choice| Message::TuningChanged(TuningSettings { tonic: choice.pitch_class, ..tuning }),
+                )
+                .width(Length::Fixed(70.0))
+                .style(iced::theme::PickList::Custom(std::rc::Rc::new(DeepPurplePickList), std::rc::Rc::new(DeepPurpleOverlay))),
+            ]
+            .spacing(10)
+            .align_items(iced::Alignment::Center)
+            .into()
+        };
+
+        // A Scala scale replaces the temperament
+        let temperament_or_scale: Element<'_, Message> = match &self.scale_name {
+            Some(name) => text(format!("Scale: {}", name)).size(18).style(Color::from_rgb(0.8, 1.0, 0.8)).into(),
+            None => temperament_picker.into(),
+        };
+        let scale_input = text_input("path/to/scale.scl", &self.scale_path)
+            .on_input(Message::ScalePathChanged)
+            .on_submit(Message::LoadScala)
+            .width(Length::Fixed(220.0))
+            .style(iced::theme::TextInput::Custom(Box::new(DeepPurpleTextInput)));
+        let keyboard_mapping_input = text_input("mapping.kbm (optional)", &self.keyboard_mapping_path)
+            .on_input(Message::KeyboardMappingPathChanged)
+            .on_submit(Message::LoadScala)
+            .width(Length::Fixed(220.0))
+            .style(iced::theme::TextInput::Custom(Box::new(DeepPurpleTextInput)));
+        let load_scale_button = button("Load")
+            .style(iced::theme::Button::Custom(Box::new(ForestGreenButton)))
+            .on_press(Message::LoadScala);
+        let clear_scale_button = button("Clear")
+            .style(iced::theme::Button::Custom(Box::new(ForestGreenButton)))
+            .on_press_maybe(self.scale_name.is_some().then_some(Message::ClearScala));
+
+        let metronome = self.settings.metronome;
+        let metronome_toggle = checkbox("Metronome", metronome.enabled)
+            .on_toggle(move |enabled| Message::MetronomeChanged(MetronomeSettings { enabled, ..metronome }))
+            .text_size(18)
+            .style(iced::theme::Checkbox::Custom(Box::new(ForestGreenCheckbox)));
+        let bpm_slider = slider(metronome::MIN_BPM..=metronome::MAX_BPM, metronome.bpm, move |bpm| {
+            Message::MetronomeChanged(MetronomeSettings { bpm, ..metronome })
+        })
+        .step(1.0)
+        .width(Length::Fixed(140.0))
+        .style(iced::theme::Slider::Custom(Box::new(ForestGreenSlider)));
+        let bpm_label = text(format!("{:.0} BPM", metronome.bpm))
+            .size(16)
+            .width(Length::Fixed(70.0))
+            .style(Color::from_rgb(0.8, 1.0, 0.8));
+        let beats_picker = pick_list(
+            (1..=metronome::MAX_BEATS_PER_BAR).collect::<Vec<_>>(),
+            Some(metronome.beats_per_bar),
+            move |beats_per_bar| Message::MetronomeChanged(MetronomeSettings { beats_per_bar, ..metronome }),
+        )
+        .width(Length::Fixed(60.0))
+        .style(iced::theme::PickList::Custom(std::rc::Rc::new(DeepPurplePickList), std::rc::Rc::new(DeepPurpleOverlay)));
+        let beat_unit_picker = pick_list(&metronome::BEAT_UNITS[..], Some(metronome.beat_unit), move |beat_unit| {
+            Message::MetronomeChanged(MetronomeSettings { beat_unit, ..metronome })
+        })
+        .width(Length::Fixed(60.0))
+        .style(iced::theme::PickList::Custom(std::rc::Rc::new(DeepPurplePickList), std::rc::Rc::new(DeepPurpleOverlay)));
+        let accent_toggle = checkbox("Accent", metronome.accent)
+            .on_toggle(move |accent| Message::MetronomeChanged(MetronomeSettings { accent, ..metronome }))
+            .text_size(18)
+            .style(iced::theme::Checkbox::Custom(Box::new(ForestGreenCheckbox)));
+        let subdivision_picker = pick_list(&Subdivision::ALL[..], Some(metronome.subdivision), move |subdivision| {
+            Message::MetronomeChanged(MetronomeSettings { subdivision, ..metronome })
+        })
+        .width(Length::Fixed(130.0))
+        .style(iced::theme::PickList::Custom(std::rc::Rc::new(DeepPurplePickList), std::rc::Rc::new(DeepPurpleOverlay)));
+        let click_volume_slider = slider(0.0..=1.0, metronome.volume, move |volume| {
+            Message::MetronomeChanged(MetronomeSettings { volume, ..metronome })
+        })
+        .step(0.01)
+        .width(Length::Fixed(90.0))
+        .style(iced::theme::Slider::Custom(Box::new(ForestGreenSlider)));
+
+        let tap_button = button("Tap")
+            .style(iced::theme::Button::Custom(Box::new(ForestGreenButton)))
+            .on_press(Message::TapTempo);
+        let tap_tempo = self.audio_engine.midi_target().tap_tempo;
+        let tap_trigger: Element<'_, Message> = if tap_tempo.is_learning() {
+            text("press a key or pedal...").size(16).style(Color::from_rgb(0.8, 1.0, 0.8)).into()
+        } else {
+            match self.settings.tap_trigger {
+                Some(trigger) => row![
+                    text(format!("or {}", trigger)).size(16).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                    button("Forget")
+                        .style(iced::theme::Button::Custom(Box::new(ForestGreenButton)))
+                        .on_press(Message::ForgetTapTrigger),
+                ]
+                .spacing(10)
+                .align_items(iced::Alignment::Center)
+                .into(),
+                None => button("Learn MIDI...")
+                    .style(iced::theme::Button::Custom(Box::new(ForestGreenButton)))
+                    .on_press_maybe(self.midi_connection.is_some().then_some(Message::LearnTapTrigger))
+                    .into(),
+            }
+        };
+        let song_tempo = self.audio_engine.song_tempo();
+        let song_tempo_label = text(match song_tempo {
+            Some(tempo) => format!("Songs at {:.0} BPM", tempo),
+            None => "Songs as written".to_string(),
+        })
+        .size(16)
+        .style(Color::from_rgb(0.8, 1.0, 0.8));
+        let written_tempo_button = button("As written")
+            .style(iced::theme::Button::Custom(Box::new(ForestGreenButton)))
+            .on_press_maybe(song_tempo.is_some().then_some(Message::WrittenSongTempo));
+
+        let effects = self.settings.synth_effects;
+        let room_picker = pick_list(&RoomPreset::ALL[..], effects.room(), Message::RoomSelected)
+            .placeholder("Custom")
+            .width(Length::Fixed(140.0))
+            .style(iced::theme::PickList::Custom(std::rc::Rc::new(DeepPurplePickList), std::rc::Rc::new(DeepPurpleOverlay)));
+        let send_slider = |amount: u8, on_change: fn(f32) -> Message| {
+            slider(0.0..=127.0, amount as f32, on_change)
+                .step(1.0)
+                .width(Length::Fixed(110.0))
+                .style(iced::theme::Slider::Custom(Box::new(ForestGreenSlider)))
+        };
+        let reverb_toggle = checkbox("Reverb", effects.reverb)
+            .on_toggle(Message::ReverbToggled)
+            .text_size(18)
+            .style(iced::theme::Checkbox::Custom(Box::new(ForestGreenCheckbox)));
+        let chorus_toggle = checkbox("Chorus", effects.chorus)
+            .on_toggle(Message::ChorusToggled)
+            .text_size(18)
+            .style(iced::theme::Checkbox::Custom(Box::new(ForestGreenCheckbox)));
+
+        let convolution = self.settings.convolution;
+        let convolution_toggle = checkbox("Convolution", convolution.enabled)
+            .on_toggle(Message::ConvolutionToggled)
+            .text_size(18)
+            .style(iced::theme::Checkbox::Custom(Box::new(ForestGreenCheckbox)));
+        let impulse_input = text_input("path/to/impulse.wav", &self.impulse_response_path)
+            .on_input(Message::ImpulseResponsePathChanged)
+            .on_submit(Message::LoadImpulseResponse)
+            .width(Length::Fixed(220.0))
+            .style(iced::theme::TextInput::Custom(Box::new(DeepPurpleTextInput)));
+        let load_impulse_button = button("Load")
+            .style(iced::theme::Button::Custom(Box::new(ForestGreenButton)))
+            .on_press(Message::LoadImpulseResponse);
+        let mix_slider = slider(0.0..=1.0, convolution.mix, Message::ConvolutionMixChanged)
+            .step(0.01)
+            .width(Length::Fixed(100.0))
+            .style(iced::theme::Slider::Custom(Box::new(ForestGreenSlider)));
+        let pre_delay_slider = slider(0.0..=MAX_PRE_DELAY_MS, convolution.pre_delay_ms, Message::PreDelayChanged)
+            .step(1.0)
+            .width(Length::Fixed(100.0))
+            .style(iced::theme::Slider::Custom(Box::new(ForestGreenSlider)));
+        let pre_delay_label = text(format!("{:.0} ms", convolution.pre_delay_ms))
+            .size(16)
+            .width(Length::Fixed(60.0))
+            .style(Color::from_rgb(0.8, 1.0, 0.8));
+
+        let effects_button = button(if self.show_effects { "Hide EQ" } else { "EQ / Comp" })
+            .style(iced::theme::Button::Custom(Box::new(ForestGreenButton)))
+            .on_press(Message::EffectsPanelToggled);
+
+        let effects_panel: Element<'_, Message> = if self.show_effects {
+            effects_panel::view(self.settings.master_effects)
+        } else {
+            vertical_space().height(0).into()
+        };
+
+        let file_input = text_input("path/to/song.mid", &self.midi_file_path)
+            .on_input(Message::MidiFilePathChanged)
+            .on_submit(Message::PlayMidiFile)
+            .width(Length::Fixed(300.0))
+            .style(iced::theme::TextInput::Custom(Box::new(DeepPurpleTextInput)));
+
+        let play_button = button("Play")
+            .style(iced::theme::Button::Custom(Box::new(ForestGreenButton)))
+            .on_press(Message::PlayMidiFile);
+
+        let stop_button = button("Stop")
+            .style(iced::theme::Button::Custom(Box::new(ForestGreenButton)))
+            .on_press(Message::StopMidiFile);
+
+        let jingle = &self.settings.startup_jingle;
+        let jingle_toggle = checkbox("Startup jingle", jingle.enabled)
+            .on_toggle(Message::JingleToggled)
+            .text_size(18)
+            .style(iced::theme::Checkbox::Custom(Box::new(ForestGreenCheckbox)));
+        let jingle_input = text_input("built-in, or path/to/jingle.mid", &self.jingle_path)
+            .on_input(Message::JinglePathChanged)
+            .on_submit(Message::LoadJingle)
+            .width(Length::Fixed(300.0))
+            .style(iced::theme::TextInput::Custom(Box::new(DeepPurpleTextInput)));
+        let load_jingle_button = button("Load")
+            .style(iced::theme::Button::Custom(Box::new(ForestGreenButton)))
+            .on_press(Message::LoadJingle);
+        let built_in_jingle_button = button("Built-in")
+            .style(iced::theme::Button::Custom(Box::new(ForestGreenButton)))
+            .on_press_maybe(jingle.file.is_some().then_some(Message::BuiltInJingle));
+
+        let key_picker = pick_list(
+            &KeySignature::ALL[..],
+            Some(self.key_signature),
+            Message::KeySignatureSelected
+        )
+        .width(Length::Fixed(220.0))
+        .style(iced::theme::PickList::Custom(std::rc::Rc::new(DeepPurplePickList), std::rc::Rc::new(DeepPurpleOverlay)));
+
+        let staff = canvas(GrandStaff::new(key_state.sounding_notes(), self.key_signature))
+            .width(Length::Fixed(240.0))
+            .height(Length::Fixed(170.0));
+
+        let roll_source = match (&self.midi_file, self.audio_engine.playback_position()) {
+            (Some(file), Some(position)) => RollSource::File { notes: file.notes(), position },
+            _ => RollSource::Live(Box::new(key_state.clone())),
+        };
+
         let content = column![
             header,
+            vertical_space().height(10),
+            row![status, chord].spacing(30).align_items(iced::Alignment::Center),
             vertical_space().height(20),
-            status,
-            vertical_space().height(40),
             row![
                 text("MIDI Input:").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
                 port_picker,
                 rescan_button
             ].spacing(20).align_items(iced::Alignment::Center),
-            vertical_space().height(60),
+            row![
+                text("Velocity:").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                curve_picker,
+                curve_graph,
+                curve_controls,
+                calibrate_button,
+            ].spacing(20).align_items(iced::Alignment::Center),
+            calibration_panel,
+            row![
+                text("Sound:").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                preset_picker,
+                text("Volume:").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                volume_slider,
+                volume_label,
+                effects_button,
+            ].spacing(20).align_items(iced::Alignment::Center),
+            row![
+                text("Transpose:").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                step_button("-", Message::TransposeChanged(transpose - 1)),
+                step_label(transpose),
+                step_button("+", Message::TransposeChanged(transpose + 1)),
+                text("Octave:").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                step_button("-", Message::OctaveShiftChanged(octave_shift - 1)),
+                step_label(octave_shift),
+                step_button("+", Message::OctaveShiftChanged(octave_shift + 1)),
+                reset_transpose,
+            ].spacing(10).align_items(iced::Alignment::Center),
+            zone_rows,
+            row![
+                text("Tuning: A4 =").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                step_button("-", nudge_reference(-1.0)),
+                reference_picker,
+                step_button("+", nudge_reference(1.0)),
+                temperament_or_scale,
+                tonic_picker,
+            ].spacing(10).align_items(iced::Alignment::Center),
+            row![
+                text("Scala:").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                scale_input,
+                keyboard_mapping_input,
+                load_scale_button,
+                clear_scale_button,
+            ].spacing(10).align_items(iced::Alignment::Center),
+            row![
+                metronome_toggle,
+                bpm_slider,
+                bpm_label,
+                beats_picker,
+                text("/").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                beat_unit_picker,
+                accent_toggle,
+                subdivision_picker,
+                text("Click:").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                click_volume_slider,
+            ].spacing(10).align_items(iced::Alignment::Center),
+            row![
+                text("Tempo:").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                tap_button,
+                tap_trigger,
+                song_tempo_label,
+                written_tempo_button,
+            ].spacing(10).align_items(iced::Alignment::Center),
+            row![
+                text("Room:").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                room_picker,
+                reverb_toggle,
+                send_slider(effects.reverb_send, Message::ReverbSendChanged),
+                chorus_toggle,
+                send_slider(effects.chorus_send, Message::ChorusSendChanged),
+            ].spacing(20).align_items(iced::Alignment::Center),
+            row![
+                convolution_toggle,
+                impulse_input,
+                load_impulse_button,
+                text("Mix:").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                mix_slider,
+                text("Pre-delay:").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                pre_delay_slider,
+                pre_delay_label,
+            ].spacing(20).align_items(iced::Alignment::Center),
+            effects_panel,
+            row![
+                text("MIDI File:").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                file_input,
+                play_button,
+                stop_button
+            ].spacing(20).align_items(iced::Alignment::Center),
+            row![
+                jingle_toggle,
+                jingle_input,
+                load_jingle_button,
+                built_in_jingle_button,
+            ].spacing(20).align_items(iced::Alignment::Center),
+            row![
+                staff,
+                column![
+                    text("Key:").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                    key_picker,
+                    text("Output:").size(20).style(Color::from_rgb(0.8, 1.0, 0.8)),
+                    canvas(LevelMeters::new(self.levels))
+                        .width(Length::Fixed(220.0))
+                        .height(Length::Fixed(20.0)),
+                ].spacing(10),
+            ].spacing(20).align_items(iced::Alignment::Center),
             // About section
             text("plug in your MIDI keyboard, rescan, and select it,")
                 .size(14)
@@ -165,9 +1289,19 @@ impl Application for ToyPianoApp {
             text("or restart the app with your controller plugged in")
                 .size(14)
                 .style(Color::from_rgb(0.6, 0.8, 0.6)),
+            text("no controller? play with A-W-S-E-D... (Z/X octave, C/V velocity, M metronome)")
+                .size(14)
+                .style(Color::from_rgb(0.6, 0.8, 0.6)),
             button("github.com/jergas/toy-piano")
                 .style(iced::theme::Button::Custom(Box::new(LinkButton)))
                 .on_press(Message::OpenGitHub),
+            vertical_space().height(20),
+            canvas(PianoRoll::new(roll_source))
+                .width(Length::Fill)
+                .height(Length::Fill),
+            canvas(PianoKeyboard::new(key_state))
+                .width(Length::Fill)
+                .height(Length::Fixed(120.0)),
         ]
         .spacing(10)
         .padding(40)
@@ -183,6 +1317,126 @@ impl Application for ToyPianoApp {
     }
 }
 
+impl ToyPianoApp {
+    /// Connects to a MIDI input port. Returns whether it worked.
+    fn connect_port(&mut self, port_name: &str) -> bool {
+        self.selected_port = Some(port_name.to_string());
+        self.status_message = format!("Connecting to {}...", port_name);
+
+        // Disconnect old
+        self.midi_connection = None;
+
+        // Connect new
+        let Ok(input) = MidiInput::new("Toy Piano Input Connection") else {
+            return false;
+        };
+        let Some(port) = input.ports().into_iter().find(|p| input.port_name(p).unwrap_or_default() == port_name) else {
+            return false;
+        };
+
+        let target = self.audio_engine.midi_target();
+        let raw_velocities = self.raw_velocities.clone();
+
+        let conn_result = input.connect(
+            &port,
+            "toy-piano-input-ui",
+            move |_stamp, message, _| {
+                if let [status, _, velocity] = *message {
+                    if status & 0xF0 == 0x90 && velocity > 0 {
+                        if let Some(recorded) = raw_velocities.lock().unwrap().as_mut() {
+                            recorded.push(velocity);
+                        }
+                    }
+                }
+                crate::midi::handle_input_message(message, &target);
+            },
+            (),
+        );
+
+        match conn_result {
+            Ok(conn) => {
+                self.status_message = format!("Connected to {}", port_name);
+                self.midi_connection = Some(conn);
+                self.velocity_curve = self.settings.velocity_curves.get(port_name).cloned().unwrap_or_default();
+                self.audio_engine.set_input_velocity_curve(&self.velocity_curve);
+                true
+            }
+            Err(e) => {
+                self.status_message = format!("Failed to connect: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Marks the settings for saving on a later tick.
+    fn settings_changed(&mut self) {
+        self.settings_changed = Some(Instant::now());
+    }
+
+    fn synth_effects_changed(&mut self) {
+        self.audio_engine.set_synth_effects(&self.settings.synth_effects);
+        self.settings_changed();
+    }
+
+    /// Applies the tuning settings, or reloads the Scala scale, whose pitch
+    /// can follow the reference.
+    fn tuning_changed(&mut self) {
+        match &self.settings.scala {
+            Some(files) => {
+                if let Err(e) = self.audio_engine.load_scala(files, self.settings.tuning.reference) {
+                    self.status_message = format!("{:#}", e);
+                }
+            }
+            None => self.audio_engine.set_tuning(&self.settings.tuning),
+        }
+        self.settings_changed();
+    }
+
+    fn convolution_changed(&mut self) {
+        self.audio_engine.set_convolution(self.settings.convolution);
+        self.settings_changed();
+    }
+
+    fn key_state(&self) -> crate::midi::KeyState {
+        self.audio_engine
+            .midi_target()
+            .keys
+            .lock()
+            .map(|keys| keys.clone())
+            .unwrap_or_default()
+    }
+
+    fn apply_qwerty_action(&mut self, action: QwertyAction) {
+        match action {
+            QwertyAction::Midi(message) => {
+                crate::midi::handle_midi_message(&message, &self.audio_engine.midi_target());
+            }
+            QwertyAction::OctaveChanged(octave) => {
+                self.status_message = format!("Computer keyboard octave: C{}", octave);
+            }
+            QwertyAction::VelocityChanged(velocity) => {
+                self.status_message = format!("Computer keyboard velocity: {}", velocity);
+            }
+            QwertyAction::ToggleMetronome => {
+                let metronome = MetronomeSettings {
+                    enabled: !self.settings.metronome.enabled,
+                    ..self.settings.metronome
+                };
+                self.audio_engine.set_metronome(&metronome);
+                self.settings.metronome = metronome;
+                self.status_message = format!("Metronome {}", if metronome.enabled { "on" } else { "off" });
+            }
+        }
+    }
+}
+
+fn key_char(key: &iced::keyboard::Key) -> Option<char> {
+    match key {
+        iced::keyboard::Key::Character(c) => c.chars().next(),
+        _ => None,
+    }
+}
+
 struct DeepPurpleTheme;
 
 impl container::StyleSheet for DeepPurpleTheme {
@@ -245,6 +1499,55 @@ impl button::StyleSheet for LinkButton {
     }
 }
 
+struct DeepPurpleTextInput;
+
+impl text_input::StyleSheet for DeepPurpleTextInput {
+    type Style = Theme;
+
+    fn active(&self, _style: &Self::Style) -> text_input::Appearance {
+        text_input::Appearance {
+            background: iced::Background::Color(Color::from_rgb8(50, 20, 70)), // Lighter Purple
+            border: iced::Border {
+                radius: 4.0.into(),
+                width: 1.0,
+                color: Color::from_rgb8(60, 30, 80),
+            },
+            icon_color: Color::from_rgb(0.5, 0.8, 0.5),
+        }
+    }
+
+    fn focused(&self, style: &Self::Style) -> text_input::Appearance {
+        let active = self.active(style);
+        text_input::Appearance {
+            border: iced::Border {
+                color: Color::from_rgb8(34, 139, 34), // Forest Green when typing
+                ..active.border
+            },
+            ..active
+        }
+    }
+
+    fn placeholder_color(&self, _style: &Self::Style) -> Color {
+        Color::from_rgb(0.5, 0.8, 0.5)
+    }
+
+    fn value_color(&self, _style: &Self::Style) -> Color {
+        Color::from_rgb(0.9, 1.0, 0.9)
+    }
+
+    fn disabled_color(&self, _style: &Self::Style) -> Color {
+        Color::from_rgb(0.4, 0.5, 0.4)
+    }
+
+    fn selection_color(&self, _style: &Self::Style) -> Color {
+        Color::from_rgb8(34, 139, 34)
+    }
+
+    fn disabled(&self, style: &Self::Style) -> text_input::Appearance {
+        self.active(style)
+    }
+}
+
 struct DeepPurplePickList;
 
 impl pick_list::StyleSheet for DeepPurplePickList {
@@ -273,6 +1576,77 @@ impl pick_list::StyleSheet for DeepPurplePickList {
     }
 }
 
+struct ForestGreenSlider;
+
+impl slider::StyleSheet for ForestGreenSlider {
+    type Style = Theme;
+
+    fn active(&self, _style: &Self::Style) -> slider::Appearance {
+        slider::Appearance {
+            rail: slider::Rail {
+                colors: (Color::from_rgb8(34, 139, 34), Color::from_rgb8(60, 30, 80)), // Filled Forest Green, rest Purple
+                width: 4.0,
+                border_radius: 2.0.into(),
+            },
+            handle: slider::Handle {
+                shape: slider::HandleShape::Circle { radius: 7.0 },
+                color: Color::from_rgb(0.8, 1.0, 0.8),
+                border_width: 1.0,
+                border_color: Color::from_rgb8(34, 139, 34),
+            },
+        }
+    }
+
+    fn hovered(&self, style: &Self::Style) -> slider::Appearance {
+        let active = self.active(style);
+        slider::Appearance {
+            handle: slider::Handle {
+                color: Color::WHITE,
+                ..active.handle
+            },
+            ..active
+        }
+    }
+
+    fn dragging(&self, style: &Self::Style) -> slider::Appearance {
+        self.hovered(style)
+    }
+}
+
+struct ForestGreenCheckbox;
+
+impl checkbox::StyleSheet for ForestGreenCheckbox {
+    type Style = Theme;
+
+    fn active(&self, _style: &Self::Style, is_checked: bool) -> checkbox::Appearance {
+        checkbox::Appearance {
+            background: iced::Background::Color(if is_checked {
+                Color::from_rgb8(34, 139, 34) // Forest Green
+            } else {
+                Color::from_rgb8(50, 20, 70) // Lighter Purple
+            }),
+            icon_color: Color::WHITE,
+            border: iced::Border {
+                radius: 4.0.into(),
+                width: 1.0,
+                color: Color::from_rgb8(80, 50, 100),
+            },
+            text_color: Some(Color::from_rgb(0.8, 1.0, 0.8)),
+        }
+    }
+
+    fn hovered(&self, style: &Self::Style, is_checked: bool) -> checkbox::Appearance {
+        let active = self.active(style, is_checked);
+        checkbox::Appearance {
+            border: iced::Border {
+                color: Color::from_rgb(0.8, 1.0, 0.8),
+                ..active.border
+            },
+            ..active
+        }
+    }
+}
+
 struct DeepPurpleOverlay;
 
 impl iced::overlay::menu::StyleSheet for DeepPurpleOverlay {
@@ -0,0 +1,208 @@
+use std::collections::{HashMap, HashSet};
+
+/// Home row plays the white keys, the row above plays the black keys.
+/// Laid out like most DAWs: `a` is C, `w` is C#, ... `k` is the next C.
+const NOTE_KEYS: [(char, i32); 18] = [
+    ('a', 0),  // C
+    ('w', 1),  // C#
+    ('s', 2),  // D
+    ('e', 3),  // D#
+    ('d', 4),  // E
+    ('f', 5),  // F
+    ('t', 6),  // F#
+    ('g', 7),  // G
+    ('y', 8),  // G#
+    ('h', 9),  // A
+    ('u', 10), // A#
+    ('j', 11), // B
+    ('k', 12), // C (next octave)
+    ('o', 13), // C#
+    ('l', 14), // D
+    ('p', 15), // D#
+    (';', 16), // E
+    ('\'', 17), // F
+];
+
+const OCTAVE_DOWN: char = 'z';
+const OCTAVE_UP: char = 'x';
+const VELOCITY_DOWN: char = 'c';
+const VELOCITY_UP: char = 'v';
+const METRONOME: char = 'm';
+
+const MIN_OCTAVE: i32 = -1;
+const MAX_OCTAVE: i32 = 8;
+const VELOCITY_STEP: u8 = 20;
+
+/// What a key press on the computer keyboard turned into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QwertyAction {
+    /// Raw MIDI bytes, ready for `handle_midi_message`.
+    Midi([u8; 3]),
+    OctaveChanged(i32),
+    VelocityChanged(u8),
+    ToggleMetronome,
+}
+
+/// Turns two rows of a computer keyboard into a one-and-a-half octave
+/// chromatic keyboard, for when there is no MIDI controller around.
+pub struct QwertyKeyboard {
+    octave: i32,
+    velocity: u8,
+    // Key -> note that was actually started, so releases still match
+    // after an octave shift while the key is held.
+    held: HashMap<char, u8>,
+    // Octave, velocity and metronome keys that are down, so auto-repeat
+    // doesn't run the octave away or flick the metronome on and off
+    held_controls: HashSet<char>,
+}
+
+impl Default for QwertyKeyboard {
+    fn default() -> Self {
+        QwertyKeyboard {
+            octave: 4, // 'a' is middle C (C4 = 60)
+            velocity: 100,
+            held: HashMap::new(),
+            held_controls: HashSet::new(),
+        }
+    }
+}
+
+impl QwertyKeyboard {
+    pub fn key_pressed(&mut self, key: char) -> Option<QwertyAction> {
+        let key = unshifted(key);
+
+        // Auto-repeat sends the press again while held; only the first one counts.
+        if self.held.contains_key(&key) || self.held_controls.contains(&key) {
+            return None;
+        }
+        if [OCTAVE_DOWN, OCTAVE_UP, VELOCITY_DOWN, VELOCITY_UP, METRONOME].contains(&key) {
+            self.held_controls.insert(key);
+        }
+
+        match key {
+            OCTAVE_DOWN => {
+                self.octave = (self.octave - 1).max(MIN_OCTAVE);
+                Some(QwertyAction::OctaveChanged(self.octave))
+            }
+            OCTAVE_UP => {
+                self.octave = (self.octave + 1).min(MAX_OCTAVE);
+                Some(QwertyAction::OctaveChanged(self.octave))
+            }
+            VELOCITY_DOWN => {
+                self.velocity = self.velocity.saturating_sub(VELOCITY_STEP).max(1);
+                Some(QwertyAction::VelocityChanged(self.velocity))
+            }
+            VELOCITY_UP => {
+                self.velocity = (self.velocity + VELOCITY_STEP).min(127);
+                Some(QwertyAction::VelocityChanged(self.velocity))
+            }
+            METRONOME => Some(QwertyAction::ToggleMetronome),
+            _ => {
+                let offset = NOTE_KEYS.iter().find(|(c, _)| *c == key)?.1;
+                let note = (self.octave + 1) * 12 + offset;
+                if !(0..=127).contains(&note) {
+                    return None;
+                }
+                let note = note as u8;
+                self.held.insert(key, note);
+                Some(QwertyAction::Midi([0x90, note, self.velocity]))
+            }
+        }
+    }
+
+    pub fn key_released(&mut self, key: char) -> Option<QwertyAction> {
+        let key = unshifted(key);
+        self.held_controls.remove(&key);
+        let note = self.held.remove(&key)?;
+        Some(QwertyAction::Midi([0x80, note, 0]))
+    }
+
+    /// Lets go of every key, for when the window loses focus and won't hear
+    /// the releases. Returns note-offs for the notes that were playing.
+    pub fn release_all(&mut self) -> Vec<QwertyAction> {
+        self.held_controls.clear();
+        self.held.drain().map(|(_, note)| QwertyAction::Midi([0x80, note, 0])).collect()
+    }
+}
+
+/// The key as typed without Shift, so a key pressed and released with Shift
+/// changing in between still matches: `A` is `a`, `:` is `;` and `"` is `'`.
+fn unshifted(key: char) -> char {
+    match key {
+        ':' => ';',
+        '"' => '\'',
+        key => key.to_ascii_lowercase(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn octave_stops_at_the_ends() {
+        let mut qwerty = QwertyKeyboard::default();
+        for _ in 0..20 {
+            qwerty.key_pressed(OCTAVE_UP);
+            qwerty.key_released(OCTAVE_UP);
+        }
+        assert_eq!(qwerty.key_pressed(OCTAVE_UP), Some(QwertyAction::OctaveChanged(MAX_OCTAVE)));
+        qwerty.key_released(OCTAVE_UP);
+        for _ in 0..20 {
+            qwerty.key_pressed(OCTAVE_DOWN);
+            qwerty.key_released(OCTAVE_DOWN);
+        }
+        assert_eq!(qwerty.key_pressed(OCTAVE_DOWN), Some(QwertyAction::OctaveChanged(MIN_OCTAVE)));
+    }
+
+    #[test]
+    fn release_after_an_octave_change_stops_the_note_that_started() {
+        let mut qwerty = QwertyKeyboard::default();
+        assert_eq!(qwerty.key_pressed('a'), Some(QwertyAction::Midi([0x90, 60, 100])));
+        qwerty.key_pressed(OCTAVE_UP);
+        assert_eq!(qwerty.key_released('a'), Some(QwertyAction::Midi([0x80, 60, 0])));
+        assert_eq!(qwerty.key_pressed('A'), Some(QwertyAction::Midi([0x90, 72, 100])));
+    }
+
+    #[test]
+    fn auto_repeat_counts_once() {
+        let mut qwerty = QwertyKeyboard::default();
+        assert!(qwerty.key_pressed('a').is_some());
+        assert_eq!(qwerty.key_pressed('a'), None);
+
+        assert_eq!(qwerty.key_pressed(OCTAVE_UP), Some(QwertyAction::OctaveChanged(5)));
+        assert_eq!(qwerty.key_pressed(OCTAVE_UP), None);
+        assert_eq!(qwerty.key_pressed(VELOCITY_DOWN), Some(QwertyAction::VelocityChanged(80)));
+        assert_eq!(qwerty.key_pressed(VELOCITY_DOWN), None);
+        assert_eq!(qwerty.key_pressed(METRONOME), Some(QwertyAction::ToggleMetronome));
+        assert_eq!(qwerty.key_pressed(METRONOME), None);
+
+        // Released and pressed again, it counts again
+        assert_eq!(qwerty.key_released(OCTAVE_UP), None);
+        assert_eq!(qwerty.key_pressed(OCTAVE_UP), Some(QwertyAction::OctaveChanged(6)));
+    }
+
+    #[test]
+    fn shift_between_press_and_release_still_releases() {
+        let mut qwerty = QwertyKeyboard::default();
+        qwerty.key_pressed(';');
+        assert_eq!(qwerty.key_released(':'), Some(QwertyAction::Midi([0x80, 76, 0])));
+        qwerty.key_pressed('"');
+        assert_eq!(qwerty.key_released('\''), Some(QwertyAction::Midi([0x80, 77, 0])));
+    }
+
+    #[test]
+    fn losing_focus_releases_everything() {
+        let mut qwerty = QwertyKeyboard::default();
+        qwerty.key_pressed('a');
+        qwerty.key_pressed('d');
+        qwerty.key_pressed(OCTAVE_UP);
+        let mut released = qwerty.release_all();
+        released.sort_by_key(|action| format!("{:?}", action));
+        assert_eq!(released, vec![QwertyAction::Midi([0x80, 60, 0]), QwertyAction::Midi([0x80, 64, 0])]);
+        assert!(qwerty.release_all().is_empty());
+        // Nothing is still held, so the keys work at once when the window is back
+        assert_eq!(qwerty.key_pressed('a'), Some(QwertyAction::Midi([0x90, 72, 100])));
+        assert_eq!(qwerty.key_pressed(OCTAVE_UP), Some(QwertyAction::OctaveChanged(6)));
+    }
+}
@@ -0,0 +1,113 @@
+use iced::mouse;
+use iced::widget::canvas::{self, Frame, Geometry};
+use iced::{Color, Point, Rectangle, Renderer, Size, Theme};
+use std::time::Instant;
+
+use super::keyboard::{KeyLayout, HIGHEST_NOTE, LOWEST_NOTE};
+use super::Message;
+use crate::midi::file::FileNote;
+use crate::midi::KeyState;
+
+/// How many seconds of notes fit between the top of the roll and the keys.
+const VISIBLE_SECONDS: f32 = 3.0;
+
+/// What the roll is showing: notes played live rise up from the keys, notes
+/// from a MIDI file fall down toward them.
+pub enum RollSource<'a> {
+    Live(Box<KeyState>),
+    File { notes: &'a [FileNote], position: f64 },
+}
+
+/// The piano roll that sits right above the on-screen keyboard, lined up with its keys.
+pub struct PianoRoll<'a> {
+    source: RollSource<'a>,
+}
+
+impl<'a> PianoRoll<'a> {
+    pub fn new(source: RollSource<'a>) -> Self {
+        PianoRoll { source }
+    }
+}
+
+/// Soft notes are purple, loud notes are bright green.
+fn velocity_color(velocity: u8) -> Color {
+    let t = velocity as f32 / 127.0;
+    let soft = Color::from_rgb8(120, 80, 200);
+    let loud = Color::from_rgb8(60, 230, 100);
+    Color::from_rgb(
+        soft.r + (loud.r - soft.r) * t,
+        soft.g + (loud.g - soft.g) * t,
+        soft.b + (loud.b - soft.b) * t,
+    )
+}
+
+/// Draws a note bar spanning `bottom..top` seconds away from the keys
+/// (0 = touching the keys), clipped to the frame.
+fn draw_bar(frame: &mut Frame, layout: &KeyLayout, note: u8, velocity: u8, bottom: f32, top: f32) {
+    if !(LOWEST_NOTE..=HIGHEST_NOTE).contains(&note) {
+        return;
+    }
+    let height = frame.height();
+    let pixels_per_second = height / VISIBLE_SECONDS;
+    let y_bottom = (height - bottom * pixels_per_second).clamp(0.0, height);
+    let y_top = (height - top * pixels_per_second).clamp(0.0, height);
+    if y_bottom - y_top < 1.0 {
+        return;
+    }
+
+    let key = layout.key_rect(note);
+    frame.fill_rectangle(
+        Point::new(key.x + 1.0, y_top),
+        Size::new((key.width - 2.0).max(1.0), y_bottom - y_top),
+        velocity_color(velocity),
+    );
+}
+
+impl<'a> canvas::Program<Message> for PianoRoll<'a> {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &(),
+        renderer: &Renderer,
+        _theme: &Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(renderer, bounds.size());
+        let layout = KeyLayout::new(bounds.size());
+
+        // Faint guide line at every C, to help find your place
+        for note in (LOWEST_NOTE..=HIGHEST_NOTE).filter(|n| n % 12 == 0) {
+            let x = layout.key_rect(note).x;
+            frame.fill_rectangle(
+                Point::new(x, 0.0),
+                Size::new(1.0, bounds.height),
+                Color::from_rgba8(150, 110, 200, 0.25),
+            );
+        }
+
+        match &self.source {
+            RollSource::Live(keys) => {
+                let now = Instant::now();
+                for played in keys.history() {
+                    let age = |t: Instant| now.duration_since(t).as_secs_f32();
+                    let bottom = played.end.map(age).unwrap_or(0.0);
+                    draw_bar(&mut frame, &layout, played.note, played.velocity, bottom, age(played.start));
+                }
+            }
+            RollSource::File { notes, position } => {
+                for note in notes.iter() {
+                    let bottom = (note.start - position) as f32;
+                    let top = bottom + note.duration as f32;
+                    if top < 0.0 || bottom > VISIBLE_SECONDS {
+                        continue;
+                    }
+                    draw_bar(&mut frame, &layout, note.note, note.velocity, bottom, top);
+                }
+            }
+        }
+
+        vec![frame.into_geometry()]
+    }
+}
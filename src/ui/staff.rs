@@ -0,0 +1,242 @@
+use iced::alignment;
+use iced::mouse;
+use iced::widget::canvas::{self, path, Frame, Geometry, Path, Stroke, Text};
+use iced::{Color, Point, Radians, Rectangle, Renderer, Theme, Vector};
+
+use super::Message;
+use crate::theory::{spell, KeySignature, SpelledNote};
+
+const STEP: f32 = 4.0; // half the distance between two staff lines
+const TOP_MARGIN: f32 = 40.0; // room for ledger lines above the treble staff
+const STAFF_GAP: f32 = 4.0 * 2.0 * STEP; // space between the two staves
+
+// Diatonic steps (see `SpelledNote::step`) of the outer lines of each staff
+const TREBLE_BOTTOM: i32 = 30; // E4
+const TREBLE_TOP: i32 = 38; // F5
+const BASS_BOTTOM: i32 = 18; // G2
+const BASS_TOP: i32 = 26; // A3
+
+// Middle C and up goes on the treble staff
+const SPLIT_NOTE: u8 = 60;
+
+const CLEF_X: f32 = 12.0;
+const KEY_SIGNATURE_X: f32 = 44.0;
+const ACCIDENTAL_WIDTH: f32 = 8.0;
+const NOTEHEAD_WIDTH: f32 = 11.0;
+
+// Where the signature's sharps and flats go on the treble staff, in the order
+// they are written. The bass staff uses the same notes two octaves lower.
+const SHARP_STEPS: [i32; 7] = [38, 35, 39, 36, 33, 37, 34];
+const FLAT_STEPS: [i32; 7] = [34, 37, 33, 36, 32, 35, 31];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Clef {
+    Treble,
+    Bass,
+}
+
+impl Clef {
+    fn bottom(self) -> i32 {
+        match self {
+            Clef::Treble => TREBLE_BOTTOM,
+            Clef::Bass => BASS_BOTTOM,
+        }
+    }
+
+    fn top(self) -> i32 {
+        match self {
+            Clef::Treble => TREBLE_TOP,
+            Clef::Bass => BASS_TOP,
+        }
+    }
+
+    fn middle(self) -> i32 {
+        (self.bottom() + self.top()) / 2
+    }
+
+    fn bottom_line_y(self) -> f32 {
+        let treble_bottom = TOP_MARGIN + (TREBLE_TOP - TREBLE_BOTTOM) as f32 * STEP;
+        match self {
+            Clef::Treble => treble_bottom,
+            Clef::Bass => treble_bottom + STAFF_GAP + (BASS_TOP - BASS_BOTTOM) as f32 * STEP,
+        }
+    }
+
+    fn y(self, step: i32) -> f32 {
+        self.bottom_line_y() - (step - self.bottom()) as f32 * STEP
+    }
+}
+
+fn accidental_glyph(alteration: i8) -> &'static str {
+    match alteration {
+        1 => "♯",
+        -1 => "♭",
+        _ => "♮",
+    }
+}
+
+/// A grand staff showing the notes currently sounding as one chord, spelled for the chosen key.
+pub struct GrandStaff {
+    notes: Vec<u8>,
+    key: KeySignature,
+}
+
+impl GrandStaff {
+    pub fn new(notes: Vec<u8>, key: KeySignature) -> Self {
+        GrandStaff { notes, key }
+    }
+}
+
+fn glyph(frame: &mut Frame, content: &str, position: Point, size: f32, color: Color) {
+    frame.fill_text(Text {
+        content: content.to_string(),
+        position,
+        color,
+        size: size.into(),
+        horizontal_alignment: alignment::Horizontal::Center,
+        vertical_alignment: alignment::Vertical::Center,
+        ..Text::default()
+    });
+}
+
+fn draw_staff(frame: &mut Frame, clef: Clef, key: KeySignature, ink: Color) {
+    let line = Stroke::default().with_color(ink).with_width(1.0);
+    for step in (clef.bottom()..=clef.top()).step_by(2) {
+        let y = clef.y(step);
+        frame.stroke(&Path::line(Point::new(0.0, y), Point::new(frame.width(), y)), line.clone());
+    }
+
+    match clef {
+        Clef::Treble => glyph(frame, "𝄞", Point::new(CLEF_X + 6.0, clef.y(33)), 44.0, ink),
+        Clef::Bass => glyph(frame, "𝄢", Point::new(CLEF_X + 6.0, clef.y(23)), 30.0, ink),
+    }
+
+    let (steps, alteration) = if key.fifths() >= 0 { (SHARP_STEPS, 1) } else { (FLAT_STEPS, -1) };
+    let octave_down = if clef == Clef::Bass { 14 } else { 0 };
+    for (i, _) in key.accidentals().iter().enumerate() {
+        let x = KEY_SIGNATURE_X + i as f32 * ACCIDENTAL_WIDTH;
+        glyph(frame, accidental_glyph(alteration), Point::new(x, clef.y(steps[i] - octave_down)), 16.0, ink);
+    }
+}
+
+/// Draws one chord on one staff: stacked noteheads sharing a single stem.
+fn draw_chord(frame: &mut Frame, clef: Clef, x: f32, mut notes: Vec<SpelledNote>, ink: Color) {
+    if notes.is_empty() {
+        return;
+    }
+    notes.sort_by_key(|n| n.step());
+    notes.dedup_by_key(|n| (n.step(), n.alteration));
+
+    let lowest = notes[0].step();
+    let highest = notes[notes.len() - 1].step();
+    // Stem goes down when the chord sits mostly above the middle line
+    let stem_up = lowest + highest < 2 * clef.middle();
+    let line = Stroke::default().with_color(ink).with_width(1.0);
+
+    // Ledger lines, a little wider than the noteheads
+    let ledger = |frame: &mut Frame, step: i32| {
+        let y = clef.y(step);
+        frame.stroke(
+            &Path::line(Point::new(x - 4.0, y), Point::new(x + NOTEHEAD_WIDTH * 2.0 + 4.0, y)),
+            line.clone(),
+        );
+    };
+    for step in (lowest..clef.bottom()).filter(|s| s % 2 == clef.bottom() % 2) {
+        ledger(frame, step);
+    }
+    for step in (clef.top() + 1..=highest).filter(|s| s % 2 == clef.top() % 2) {
+        ledger(frame, step);
+    }
+
+    // In a second, one of the two notes moves to the other side of the stem
+    let mut flipped = vec![false; notes.len()];
+    let order: Vec<usize> = if stem_up {
+        (0..notes.len()).collect()
+    } else {
+        (0..notes.len()).rev().collect()
+    };
+    for pair in order.windows(2) {
+        let (prev, this) = (pair[0], pair[1]);
+        if (notes[this].step() - notes[prev].step()).abs() == 1 && !flipped[prev] {
+            flipped[this] = true;
+        }
+    }
+
+    // Stem on the right going up, on the left going down
+    let stem_x = if stem_up { x + NOTEHEAD_WIDTH } else { x };
+    let mut accidental_column = 0;
+    for (i, note) in notes.iter().enumerate() {
+        let y = clef.y(note.step());
+        let side = match (flipped[i], stem_up) {
+            (false, _) => 0.0,
+            (true, true) => NOTEHEAD_WIDTH,
+            (true, false) => -NOTEHEAD_WIDTH,
+        };
+        let center = Point::new(x + NOTEHEAD_WIDTH / 2.0 + side, y);
+        let head = Path::new(|p: &mut path::Builder| {
+            p.ellipse(path::arc::Elliptical {
+                center,
+                radii: Vector::new(NOTEHEAD_WIDTH / 2.0, STEP * 0.9),
+                rotation: Radians(-0.35),
+                start_angle: Radians(0.0),
+                end_angle: Radians(std::f32::consts::TAU),
+            });
+        });
+        frame.fill(&head, ink);
+
+        if note.show_accidental {
+            // Stagger accidentals so neighbouring ones don't overlap
+            let offset = 10.0 + (accidental_column % 2) as f32 * ACCIDENTAL_WIDTH;
+            let left_edge = x.min(x + side);
+            glyph(frame, accidental_glyph(note.alteration), Point::new(left_edge - offset, y), 16.0, ink);
+            accidental_column += 1;
+        }
+    }
+
+    let stem_length = 7.0 * STEP;
+    let (start, end) = if stem_up {
+        (clef.y(lowest), clef.y(highest) - stem_length)
+    } else {
+        (clef.y(highest), clef.y(lowest) + stem_length)
+    };
+    frame.stroke(
+        &Path::line(Point::new(stem_x, start), Point::new(stem_x, end)),
+        line.with_width(1.5),
+    );
+}
+
+impl canvas::Program<Message> for GrandStaff {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &(),
+        renderer: &Renderer,
+        _theme: &Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(renderer, bounds.size());
+        let ink = Color::from_rgb(0.8, 1.0, 0.8);
+
+        draw_staff(&mut frame, Clef::Treble, self.key, ink);
+        draw_staff(&mut frame, Clef::Bass, self.key, ink);
+
+        // The brace line joining the two staves
+        frame.stroke(
+            &Path::line(
+                Point::new(0.5, Clef::Treble.y(TREBLE_TOP)),
+                Point::new(0.5, Clef::Bass.y(BASS_BOTTOM)),
+            ),
+            Stroke::default().with_color(ink).with_width(2.0),
+        );
+
+        let (treble, bass): (Vec<u8>, Vec<u8>) = self.notes.iter().partition(|n| **n >= SPLIT_NOTE);
+        let spelled = |notes: Vec<u8>| notes.into_iter().map(|n| spell(n, self.key)).collect();
+        let x = KEY_SIGNATURE_X + 7.0 * ACCIDENTAL_WIDTH + 40.0;
+        draw_chord(&mut frame, Clef::Treble, x, spelled(treble), ink);
+        draw_chord(&mut frame, Clef::Bass, x, spelled(bass), ink);
+
+        vec![frame.into_geometry()]
+    }
+}
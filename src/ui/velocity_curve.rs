@@ -0,0 +1,173 @@
+use iced::mouse;
+use iced::widget::canvas::{self, event, Event, Frame, Geometry, Path, Stroke};
+use iced::{Color, Point, Rectangle, Renderer, Size, Theme};
+
+use super::Message;
+use crate::midi::velocity::VelocityCurve;
+
+/// How close, in pixels, a click has to be to grab a point.
+const GRAB_RADIUS: f32 = 8.0;
+const POINT_RADIUS: f32 = 4.0;
+
+/// Graph of a velocity curve, keyboard velocity across, played velocity up.
+/// Custom curves can be edited: click to add a point, drag to move one,
+/// right-click to remove one.
+pub struct VelocityCurveEditor {
+    curve: VelocityCurve,
+}
+
+impl VelocityCurveEditor {
+    pub fn new(curve: VelocityCurve) -> Self {
+        VelocityCurveEditor { curve }
+    }
+}
+
+#[derive(Default)]
+pub struct EditorState {
+    /// Index of the point being dragged
+    dragging: Option<usize>,
+}
+
+fn to_screen(size: Size, (input, output): (u8, u8)) -> Point {
+    Point::new(
+        input as f32 / 127.0 * size.width,
+        size.height - output as f32 / 127.0 * size.height,
+    )
+}
+
+fn from_screen(size: Size, position: Point) -> (u8, u8) {
+    let input = (position.x / size.width * 127.0).round().clamp(0.0, 127.0) as u8;
+    let output = ((size.height - position.y) / size.height * 127.0).round().clamp(1.0, 127.0) as u8;
+    (input, output)
+}
+
+impl canvas::Program<Message> for VelocityCurveEditor {
+    type State = EditorState;
+
+    fn update(
+        &self,
+        state: &mut EditorState,
+        event: Event,
+        bounds: Rectangle,
+        cursor: mouse::Cursor,
+    ) -> (event::Status, Option<Message>) {
+        let VelocityCurve::Custom(points) = &self.curve else {
+            return (event::Status::Ignored, None);
+        };
+        let size = bounds.size();
+        let nearest = |position: Point| {
+            points
+                .iter()
+                .position(|&point| to_screen(size, point).distance(position) <= GRAB_RADIUS)
+        };
+        let changed = |points: Vec<(u8, u8)>| Some(Message::VelocityCurveChanged(VelocityCurve::Custom(points)));
+
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                let Some(position) = cursor.position_in(bounds) else {
+                    return (event::Status::Ignored, None);
+                };
+                if let Some(index) = nearest(position) {
+                    state.dragging = Some(index);
+                    return (event::Status::Captured, None);
+                }
+                let point = from_screen(size, position);
+                let index = points.partition_point(|&(x, _)| x < point.0);
+                if points.get(index).is_some_and(|&(x, _)| x == point.0) {
+                    return (event::Status::Captured, None);
+                }
+                let mut points = points.clone();
+                points.insert(index, point);
+                state.dragging = Some(index);
+                (event::Status::Captured, changed(points))
+            }
+            Event::Mouse(mouse::Event::CursorMoved { .. }) => {
+                let (Some(index), Some(position)) = (state.dragging, cursor.position_from(bounds.position())) else {
+                    return (event::Status::Ignored, None);
+                };
+                let (input, output) = from_screen(size, position);
+                let mut points = points.clone();
+                let last = points.len() - 1;
+                // The ends stay at 0 and 127; the rest can't pass their neighbours
+                let input = if index == 0 || index == last {
+                    points[index].0
+                } else {
+                    input.clamp(points[index - 1].0 + 1, points[index + 1].0 - 1)
+                };
+                points[index] = (input, output);
+                (event::Status::Captured, changed(points))
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                let was_dragging = state.dragging.take().is_some();
+                let status = if was_dragging { event::Status::Captured } else { event::Status::Ignored };
+                (status, None)
+            }
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Right)) => {
+                let index = cursor.position_in(bounds).and_then(nearest);
+                match index {
+                    Some(index) if index > 0 && index < points.len() - 1 => {
+                        let mut points = points.clone();
+                        points.remove(index);
+                        (event::Status::Captured, changed(points))
+                    }
+                    _ => (event::Status::Ignored, None),
+                }
+            }
+            _ => (event::Status::Ignored, None),
+        }
+    }
+
+    fn draw(
+        &self,
+        _state: &EditorState,
+        renderer: &Renderer,
+        _theme: &Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(renderer, bounds.size());
+        let size = bounds.size();
+
+        frame.fill_rectangle(Point::ORIGIN, size, Color::from_rgb8(40, 10, 60));
+        // Linear, for reference
+        frame.stroke(
+            &Path::line(to_screen(size, (0, 0)), to_screen(size, (127, 127))),
+            Stroke::default().with_width(1.0).with_color(Color { a: 0.3, ..Color::WHITE }),
+        );
+
+        let curve = Path::new(|builder| {
+            builder.move_to(to_screen(size, (0, self.curve.apply(0))));
+            for input in 1..=127 {
+                builder.line_to(to_screen(size, (input, self.curve.apply(input))));
+            }
+        });
+        frame.stroke(
+            &curve,
+            Stroke::default().with_width(2.0).with_color(Color::from_rgb8(34, 139, 34)), // Forest Green
+        );
+
+        if let VelocityCurve::Custom(points) = &self.curve {
+            for &point in points {
+                frame.fill(
+                    &Path::circle(to_screen(size, point), POINT_RADIUS),
+                    Color::from_rgb(0.8, 1.0, 0.8),
+                );
+            }
+        }
+
+        vec![frame.into_geometry()]
+    }
+
+    fn mouse_interaction(
+        &self,
+        state: &EditorState,
+        bounds: Rectangle,
+        cursor: mouse::Cursor,
+    ) -> mouse::Interaction {
+        match (&self.curve, cursor.position_in(bounds)) {
+            _ if state.dragging.is_some() => mouse::Interaction::Grabbing,
+            (VelocityCurve::Custom(_), Some(_)) => mouse::Interaction::Crosshair,
+            _ => mouse::Interaction::default(),
+        }
+    }
+}
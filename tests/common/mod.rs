@@ -0,0 +1,197 @@
+//! Helpers shared by the integration tests.
+
+// Each test binary uses its own subset of these
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use toy_piano::audio::null::NullOutput;
+use toy_piano::{AudioConfig, AudioEngine};
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+/// Path to a tiny SoundFont with one preset (bank 0, program 0): a looped
+/// 440 Hz sine wave with a 0.2 second release. Written once per test binary, so the tests don't need
+/// the real piano SoundFont, which isn't in the repository.
+pub fn test_soundfont() -> PathBuf {
+    static PATH: OnceLock<PathBuf> = OnceLock::new();
+    PATH.get_or_init(|| {
+        let path = std::env::temp_dir().join(format!("toy-piano-test-{}.sf2", std::process::id()));
+        std::fs::write(&path, sine_soundfont()).expect("failed to write test SoundFont");
+        path
+    })
+    .clone()
+}
+
+pub fn null_engine(output: NullOutput) -> AudioEngine {
+    let config = AudioConfig {
+        soundfont: Some(test_soundfont()),
+        ..AudioConfig::default()
+    };
+    AudioEngine::init_null(&config, NullOutput { sample_rate: SAMPLE_RATE, ..output }).expect("failed to start engine")
+}
+
+pub fn frames(seconds: f64) -> usize {
+    (seconds * SAMPLE_RATE as f64) as usize
+}
+
+/// Notes the engine shows as sounding, after transpose and zones.
+pub fn sounding(engine: &AudioEngine) -> Vec<u8> {
+    engine.midi_target().keys.lock().unwrap().sounding_notes()
+}
+
+/// Renders `seconds`, long enough for the release tails to die away, then
+/// checks that what comes after is silent.
+pub fn assert_silent_after(engine: &AudioEngine, seconds: f64) {
+    engine.render(frames(seconds)).unwrap();
+    let peak = peak(&engine.render(frames(0.1)).unwrap());
+    assert!(peak < 1.0e-4, "still sounding {} seconds on, peak {}", seconds, peak);
+}
+
+pub fn peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0.0, |peak, s| peak.max(s.abs()))
+}
+
+pub fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+/// Pitch of the left channel, from the time between its first and last
+/// upward zero crossings, interpolated between samples.
+pub fn frequency(samples: &[f32]) -> f32 {
+    let left: Vec<f32> = samples.iter().step_by(2).copied().collect();
+    let crossings: Vec<f64> = left
+        .windows(2)
+        .enumerate()
+        .filter(|(_, w)| w[0] < 0.0 && w[1] >= 0.0)
+        .map(|(i, w)| i as f64 + (w[0] / (w[0] - w[1])) as f64)
+        .collect();
+    match (crossings.first(), crossings.last()) {
+        (Some(first), Some(last)) if last > first => {
+            ((crossings.len() - 1) as f64 * SAMPLE_RATE as f64 / (last - first)) as f32
+        }
+        _ => 0.0,
+    }
+}
+
+/// A one-track Standard MIDI File at 120 BPM and 480 ticks per quarter note:
+/// each event is the ticks since the one before and a channel message.
+pub fn midi_file(events: &[(u32, [u8; 3])]) -> Vec<u8> {
+    let mut track = vec![0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20];
+    for (delta, message) in events {
+        // Variable-length delta, seven bits at a time
+        let mut bytes = vec![(*delta & 0x7F) as u8];
+        let mut rest = *delta >> 7;
+        while rest > 0 {
+            bytes.insert(0, (rest & 0x7F) as u8 | 0x80);
+            rest >>= 7;
+        }
+        track.extend_from_slice(&bytes);
+        track.extend_from_slice(message);
+    }
+    track.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+
+    let mut file = b"MThd".to_vec();
+    file.extend_from_slice(&6u32.to_be_bytes());
+    file.extend_from_slice(&[0, 0, 0, 1, 0x01, 0xE0]);
+    file.extend_from_slice(b"MTrk");
+    file.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    file.extend_from_slice(&track);
+    file
+}
+
+fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut out = id.to_vec();
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+fn list(kind: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+    let mut data = kind.to_vec();
+    for c in chunks {
+        data.extend_from_slice(c);
+    }
+    chunk(b"LIST", &data)
+}
+
+fn name(s: &str) -> [u8; 20] {
+    let mut out = [0; 20];
+    out[..s.len()].copy_from_slice(s.as_bytes());
+    out
+}
+
+fn sine_soundfont() -> Vec<u8> {
+    // 44000 Hz makes a 440 Hz period exactly 100 samples, so the loop is seamless
+    const SAMPLE_RATE: u32 = 44000;
+    const LENGTH: u32 = 1000;
+    let mut smpl = Vec::new();
+    for i in 0..LENGTH + 46 {
+        // The spec wants 46 zero samples after each sample
+        let value = if i < LENGTH {
+            ((i as f32 / 100.0 * std::f32::consts::TAU).sin() * 16000.0) as i16
+        } else {
+            0
+        };
+        smpl.extend_from_slice(&value.to_le_bytes());
+    }
+
+    let u16s = |values: &[u16]| values.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<u8>>();
+
+    let mut phdr = Vec::new();
+    for (preset, bag) in [("Sine", 0u16), ("EOP", 1)] {
+        phdr.extend_from_slice(&name(preset));
+        phdr.extend_from_slice(&u16s(&[0, 0, bag])); // program, bank, first zone
+        phdr.extend_from_slice(&[0; 12]); // library, genre, morphology
+    }
+    let pbag = u16s(&[0, 0, 1, 0]);
+    let pgen = u16s(&[41, 0, 0, 0]); // instrument 0, then the terminator
+    let pmod = vec![0; 10];
+
+    let mut inst = Vec::new();
+    for (instrument, bag) in [("Sine", 0u16), ("EOI", 1)] {
+        inst.extend_from_slice(&name(instrument));
+        inst.extend_from_slice(&u16s(&[bag]));
+    }
+    let ibag = u16s(&[0, 0, 3, 0]);
+    // 0.2 s release (in timecents), loop continuously, sample 0, terminator
+    let igen = u16s(&[38, -2786i16 as u16, 54, 1, 53, 0, 0, 0]);
+    let imod = vec![0; 10];
+
+    let mut shdr = Vec::new();
+    for (sample, end, end_loop, pitch) in [("Sine", LENGTH, LENGTH, 69u8), ("EOS", 0, 0, 0)] {
+        shdr.extend_from_slice(&name(sample));
+        for value in [0, end, 0, end_loop, SAMPLE_RATE] {
+            shdr.extend_from_slice(&value.to_le_bytes());
+        }
+        shdr.extend_from_slice(&[pitch, 0]);
+        shdr.extend_from_slice(&u16s(&[0, 1])); // no link, mono
+    }
+
+    let info = list(b"INFO", &[chunk(b"ifil", &u16s(&[2, 1])), chunk(b"INAM", b"Test\0\0")]);
+    let sdta = list(b"sdta", &[chunk(b"smpl", &smpl)]);
+    let pdta = list(
+        b"pdta",
+        &[
+            chunk(b"phdr", &phdr),
+            chunk(b"pbag", &pbag),
+            chunk(b"pmod", &pmod),
+            chunk(b"pgen", &pgen),
+            chunk(b"inst", &inst),
+            chunk(b"ibag", &ibag),
+            chunk(b"imod", &imod),
+            chunk(b"igen", &igen),
+            chunk(b"shdr", &shdr),
+        ],
+    );
+
+    let mut body = b"sfbk".to_vec();
+    body.extend_from_slice(&info);
+    body.extend_from_slice(&sdta);
+    body.extend_from_slice(&pdta);
+    chunk(b"RIFF", &body)
+}
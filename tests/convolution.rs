@@ -0,0 +1,106 @@
+mod common;
+
+use common::{frames, null_engine, peak, SAMPLE_RATE};
+use std::path::PathBuf;
+use toy_piano::audio::convolution::{ConvolutionSettings, BLOCK};
+use toy_piano::audio::null::NullOutput;
+use toy_piano::audio::wav;
+use toy_piano::handle_midi_message;
+
+/// Writes an impulse response that is a single click, so convolving with it
+/// just delays the signal.
+fn click_impulse(name: &str, channels: u16) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("toy-piano-{}-{}.wav", name, std::process::id()));
+    let mut samples = vec![0.0; 256 * channels as usize];
+    samples[..channels as usize].fill(1.0);
+    if channels == 2 {
+        // Click on the left only
+        samples[1] = 0.0;
+    }
+    wav::write(&path, &samples, channels, SAMPLE_RATE).unwrap();
+    path
+}
+
+/// Plays a note and renders `seconds` of it, with the convolution set up by `setup`.
+fn play(seconds: f64, setup: impl FnOnce(&toy_piano::AudioEngine)) -> Vec<f32> {
+    let engine = null_engine(NullOutput::default());
+    setup(&engine);
+    // Let the mix fade in before there's anything to hear
+    engine.render(frames(0.05)).unwrap();
+    handle_midi_message(&[0x90, 69, 60], &engine.midi_target());
+    engine.render(frames(seconds)).unwrap()
+}
+
+fn assert_close(actual: &[f32], expected: &[f32]) {
+    assert_eq!(actual.len(), expected.len());
+    let worst = actual.iter().zip(expected).map(|(a, e)| (a - e).abs()).fold(0.0, f32::max);
+    assert!(worst < 1.0e-3, "off by up to {}", worst);
+}
+
+#[test]
+fn fully_wet_click_delays_by_the_pre_delay() {
+    let impulse = click_impulse("mono-click", 1);
+    let dry = play(0.5, |_| {});
+    let wet = play(0.5, |engine| {
+        engine.load_impulse_response(&impulse).unwrap();
+        engine.set_convolution(ConvolutionSettings { enabled: true, mix: 1.0, pre_delay_ms: 100.0 });
+    });
+
+    let delay = frames(0.1) * 2;
+    assert!(peak(&wet[..delay]) < 1.0e-6, "wet signal came early");
+    assert_close(&wet[delay..], &dry[..dry.len() - delay]);
+}
+
+#[test]
+fn short_pre_delays_still_pay_one_block_of_latency() {
+    let impulse = click_impulse("latency-click", 1);
+    let dry = play(0.2, |_| {});
+    let wet = play(0.2, |engine| {
+        engine.load_impulse_response(&impulse).unwrap();
+        engine.set_convolution(ConvolutionSettings { enabled: true, mix: 1.0, pre_delay_ms: 0.0 });
+    });
+
+    let delay = BLOCK * 2;
+    assert_close(&wet[delay..], &dry[..dry.len() - delay]);
+}
+
+#[test]
+fn stereo_impulse_keeps_its_channels_apart() {
+    let impulse = click_impulse("stereo-click", 2);
+    let dry = play(0.3, |_| {});
+    let wet = play(0.3, |engine| {
+        engine.load_impulse_response(&impulse).unwrap();
+        engine.set_convolution(ConvolutionSettings { enabled: true, mix: 1.0, pre_delay_ms: 0.0 });
+    });
+
+    let left = |samples: &[f32]| samples.iter().step_by(2).copied().collect::<Vec<f32>>();
+    let right: Vec<f32> = wet.iter().skip(1).step_by(2).copied().collect();
+    assert_close(&left(&wet)[BLOCK..], &left(&dry)[..dry.len() / 2 - BLOCK]);
+    assert!(peak(&right) < 1.0e-6);
+}
+
+#[test]
+fn dry_mix_and_disabled_leave_the_signal_alone() {
+    let impulse = click_impulse("dry-click", 1);
+    let dry = play(0.3, |_| {});
+    let no_mix = play(0.3, |engine| {
+        engine.load_impulse_response(&impulse).unwrap();
+        engine.set_convolution(ConvolutionSettings { enabled: true, mix: 0.0, pre_delay_ms: 0.0 });
+    });
+    let disabled = play(0.3, |engine| {
+        engine.load_impulse_response(&impulse).unwrap();
+        engine.set_convolution(ConvolutionSettings { enabled: false, mix: 1.0, pre_delay_ms: 0.0 });
+    });
+
+    assert_close(&no_mix, &dry);
+    assert_close(&disabled, &dry);
+}
+
+#[test]
+fn bad_impulse_response_is_an_error() {
+    let engine = null_engine(NullOutput::default());
+    let path = std::env::temp_dir().join(format!("toy-piano-not-a-wav-{}.wav", std::process::id()));
+    std::fs::write(&path, b"definitely not RIFF").unwrap();
+    assert!(engine.load_impulse_response(&path).is_err());
+    assert!(!engine.has_impulse_response());
+}
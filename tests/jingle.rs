@@ -0,0 +1,81 @@
+mod common;
+
+use std::path::PathBuf;
+
+use common::{assert_silent_after, frames, midi_file, null_engine, peak, sounding};
+use toy_piano::audio::jingle::StartupJingle;
+use toy_piano::audio::null::NullOutput;
+use toy_piano::midi::file::MidiFile;
+
+fn jingle_file(name: &str, events: &[(u32, [u8; 3])]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("toy-piano-{}-{}.mid", name, std::process::id()));
+    std::fs::write(&path, midi_file(events)).unwrap();
+    path
+}
+
+#[test]
+fn built_in_jingle_plays_through_the_sequencer() {
+    let engine = null_engine(NullOutput::default());
+    engine.play_jingle(&StartupJingle::default()).unwrap();
+    // Nothing happens until the audio thread gets to it
+    assert!(sounding(&engine).is_empty());
+
+    assert!(peak(&engine.render(frames(0.05)).unwrap()) > 0.01);
+    assert_eq!(sounding(&engine), vec![67]);
+    assert_silent_after(&engine, 2.5);
+    assert!(sounding(&engine).is_empty());
+}
+
+#[test]
+fn turned_off_it_stays_quiet() {
+    let engine = null_engine(NullOutput::default());
+    let jingle = StartupJingle { enabled: false, ..StartupJingle::default() };
+    engine.play_jingle(&jingle).unwrap();
+    assert_eq!(peak(&engine.render(frames(1.0)).unwrap()), 0.0);
+}
+
+#[test]
+fn a_midi_file_replaces_the_built_in_one() {
+    // Middle C for half a second
+    let path = jingle_file("jingle", &[(0, [0x90, 60, 100]), (480, [0x80, 60, 0])]);
+    let engine = null_engine(NullOutput::default());
+    engine.play_jingle(&StartupJingle { enabled: true, file: Some(path) }).unwrap();
+
+    engine.render(frames(0.1)).unwrap();
+    assert_eq!(sounding(&engine), vec![60]);
+    engine.render(frames(0.5)).unwrap();
+    assert!(sounding(&engine).is_empty());
+}
+
+#[test]
+fn songs_leave_the_jingle_alone() {
+    // Middle C for half a second, with the pedal down from the start
+    let path = jingle_file("pedal-jingle", &[(0, [0xB0, 64, 127]), (0, [0x90, 60, 100]), (480, [0x80, 60, 0])]);
+    let song = MidiFile::parse(&midi_file(&[(0, [0x90, 72, 100]), (1920, [0x80, 72, 0])])).unwrap();
+    let engine = null_engine(NullOutput::default());
+    engine.play_jingle(&StartupJingle { enabled: true, file: Some(path) }).unwrap();
+    engine.play_midi_file(&song);
+    engine.set_song_tempo(Some(30.0));
+
+    engine.render(frames(0.1)).unwrap();
+    assert_eq!(sounding(&engine), vec![60]);
+    // Stopping the song neither releases the jingle's note nor drops the rest of it
+    engine.stop_playback();
+    assert_eq!(engine.playback_position(), None);
+    assert_eq!(sounding(&engine), vec![60]);
+
+    // Still half a second long at a quarter of the speed, and without the pedal holding it
+    engine.render(frames(0.45)).unwrap();
+    assert!(sounding(&engine).is_empty());
+}
+
+#[test]
+fn long_or_missing_files_are_errors() {
+    let engine = null_engine(NullOutput::default());
+    // Twelve seconds
+    let long = jingle_file("long-jingle", &[(0, [0x90, 60, 100]), (11520, [0x80, 60, 0])]);
+    assert!(engine.play_jingle(&StartupJingle { enabled: true, file: Some(long) }).is_err());
+    let missing = PathBuf::from("no/such/jingle.mid");
+    assert!(engine.play_jingle(&StartupJingle { enabled: true, file: Some(missing) }).is_err());
+    assert_eq!(peak(&engine.render(frames(0.5)).unwrap()), 0.0);
+}
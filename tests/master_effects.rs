@@ -0,0 +1,60 @@
+mod common;
+
+use common::{frames, null_engine, rms};
+use toy_piano::audio::effects::eq::BandKind;
+use toy_piano::audio::effects::MasterEffects;
+use toy_piano::audio::null::NullOutput;
+use toy_piano::handle_midi_message;
+
+/// RMS of the test SoundFont's 440 Hz note once it has settled, through `effects`.
+fn loudness(effects: MasterEffects) -> f32 {
+    let engine = null_engine(NullOutput::default());
+    engine.set_master_effects(&effects);
+    handle_midi_message(&[0x90, 69, 60], &engine.midi_target());
+    engine.render(frames(0.3)).unwrap();
+    rms(&engine.render(frames(0.2)).unwrap())
+}
+
+fn eq_peak(frequency: f32, gain_db: f32) -> MasterEffects {
+    let mut effects = MasterEffects::default();
+    effects.eq.bypass = false;
+    effects.eq.bands[1].kind = BandKind::Peak;
+    effects.eq.bands[1].frequency = frequency;
+    effects.eq.bands[1].gain_db = gain_db;
+    effects
+}
+
+#[test]
+fn eq_peak_on_the_note_boosts_it() {
+    let ratio = loudness(eq_peak(440.0, 6.0)) / loudness(MasterEffects::default());
+    assert!((ratio - 2.0).abs() < 0.05, "+6 dB gave a ratio of {}", ratio);
+}
+
+#[test]
+fn eq_peak_far_from_the_note_leaves_it_alone() {
+    let ratio = loudness(eq_peak(6000.0, 12.0)) / loudness(MasterEffects::default());
+    assert!((ratio - 1.0).abs() < 0.05, "a 6 kHz peak changed 440 Hz by {}", ratio);
+}
+
+#[test]
+fn bypassed_effects_do_nothing() {
+    let mut effects = eq_peak(440.0, 12.0);
+    effects.eq.bypass = true;
+    effects.compressor.threshold_db = -40.0;
+    assert_eq!(loudness(effects), loudness(MasterEffects::default()));
+}
+
+#[test]
+fn compressor_turns_down_what_goes_over_the_threshold() {
+    let mut effects = MasterEffects::default();
+    effects.compressor.bypass = false;
+    effects.compressor.threshold_db = -50.0;
+    effects.compressor.ratio = 4.0;
+    let ratio = loudness(effects) / loudness(MasterEffects::default());
+    assert!(ratio < 0.5, "compressed note kept {} of its level", ratio);
+
+    // Makeup gain brings it back up
+    effects.compressor.makeup_db = 6.0;
+    let with_makeup = loudness(effects) / loudness(MasterEffects::default());
+    assert!((with_makeup / ratio - 2.0).abs() < 0.05);
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use common::{frames, null_engine, peak, rms};
+use toy_piano::audio::null::NullOutput;
+use toy_piano::handle_midi_message;
+
+#[test]
+fn full_chord_is_limited_below_full_scale() {
+    let engine = null_engine(NullOutput::default());
+    engine.set_volume(1.5);
+    for note in 48..84 {
+        handle_midi_message(&[0x90, note, 127], &engine.midi_target());
+    }
+
+    let output = engine.render(frames(1.0)).unwrap();
+    let ceiling = 10f32.powf(-0.5 / 20.0);
+    assert!(rms(&output) > 0.1, "chord should be loud enough to need limiting");
+    assert!(peak(&output) <= ceiling + 1.0e-4, "peak {} above the limiter ceiling", peak(&output));
+}
+
+#[test]
+fn volume_scales_a_quiet_note() {
+    let loudness = |volume: f32| {
+        let engine = null_engine(NullOutput::default());
+        engine.set_volume(volume);
+        handle_midi_message(&[0x90, 69, 40], &engine.midi_target());
+        engine.render(frames(0.2)).unwrap();
+        rms(&engine.render(frames(0.2)).unwrap())
+    };
+
+    let ratio = loudness(0.5) / loudness(1.0);
+    assert!((ratio - 0.5).abs() < 0.02, "half volume gave {} of the level", ratio);
+}
+
+#[test]
+fn volume_changes_glide_instead_of_jumping() {
+    let engine = null_engine(NullOutput::default());
+    handle_midi_message(&[0x90, 69, 100], &engine.midi_target());
+    let before = engine.render(frames(0.2)).unwrap();
+
+    engine.set_volume(0.0);
+    let after = engine.render(frames(0.2)).unwrap();
+
+    // The first few milliseconds are still fading out...
+    assert!(peak(&after[..frames(0.005) * 2]) > peak(&before) * 0.2);
+    // ...and it's silent well before the end
+    assert!(peak(&after[after.len() / 2..]) < 1.0e-3);
+}
+
+#[test]
+fn meter_reports_levels_and_clipping() {
+    let engine = null_engine(NullOutput::default());
+    assert_eq!(engine.levels()[0].peak, 0.0);
+
+    handle_midi_message(&[0x90, 69, 60], &engine.midi_target());
+    let output = engine.render(frames(0.2)).unwrap();
+    let [left, right] = engine.levels();
+    assert_eq!(left.peak.max(right.peak), peak(&output));
+    assert!(left.rms > 0.0 && left.rms <= left.peak);
+    assert!(!left.clipped && !right.clipped);
+
+    engine.set_volume(1.5);
+    for note in 48..84 {
+        handle_midi_message(&[0x90, note, 127], &engine.midi_target());
+    }
+    engine.render(frames(0.2)).unwrap();
+    assert!(engine.levels().iter().any(|level| level.clipped));
+    // Reading resets the peak and clip light
+    assert!(engine.levels().iter().all(|level| !level.clipped && level.peak == 0.0));
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use common::{frames, null_engine, peak, SAMPLE_RATE};
+use toy_piano::audio::metronome::{MetronomeSettings, Subdivision};
+use toy_piano::audio::null::NullOutput;
+use toy_piano::AudioEngine;
+
+/// Renders `seconds` in uneven blocks, like a sound card that doesn't divide
+/// the beat evenly, and returns the left channel.
+fn render_left(engine: &AudioEngine, seconds: f64) -> Vec<f32> {
+    let mut left = Vec::new();
+    for block in [333, 1000, 64, 4096].into_iter().cycle() {
+        if left.len() >= frames(seconds) {
+            break;
+        }
+        left.extend(engine.render(block).unwrap().iter().step_by(2));
+    }
+    left.truncate(frames(seconds));
+    left
+}
+
+/// Frames where a click starts: the first sound after a stretch of silence.
+fn onsets(left: &[f32]) -> Vec<usize> {
+    let mut onsets = Vec::new();
+    let mut silent = usize::MAX;
+    for (i, sample) in left.iter().enumerate() {
+        if sample.abs() > 1.0e-6 {
+            if silent > 100 {
+                onsets.push(i);
+            }
+            silent = 0;
+        } else {
+            silent = silent.saturating_add(1);
+        }
+    }
+    onsets
+}
+
+fn start(engine: &AudioEngine, settings: MetronomeSettings) {
+    engine.set_metronome(&MetronomeSettings { enabled: true, ..settings });
+}
+
+#[test]
+fn clicks_land_on_the_beat_without_drift() {
+    let engine = null_engine(NullOutput::default());
+    start(&engine, MetronomeSettings { bpm: 97.0, ..MetronomeSettings::default() });
+    let clicks = onsets(&render_left(&engine, 10.0));
+
+    let interval = 60.0 / 97.0 * SAMPLE_RATE as f64;
+    assert_eq!(clicks.len(), (10.0 * 97.0 / 60.0f64).ceil() as usize);
+    for (n, onset) in clicks.iter().enumerate() {
+        let expected = clicks[0] as f64 + n as f64 * interval;
+        assert!((*onset as f64 - expected).abs() <= 1.0, "click {} at {}, expected {}", n, onset, expected);
+    }
+}
+
+#[test]
+fn downbeat_is_accented() {
+    let engine = null_engine(NullOutput::default());
+    start(&engine, MetronomeSettings { bpm: 120.0, beats_per_bar: 3, ..MetronomeSettings::default() });
+    let left = render_left(&engine, 3.0);
+    let beat = frames(0.5);
+    let levels: Vec<f32> = (0..6).map(|n| peak(&left[n * beat..n * beat + frames(0.05)])).collect();
+
+    assert!(levels[0] > levels[1] * 1.2);
+    assert!((levels[1] - levels[2]).abs() < 1.0e-3);
+    assert!((levels[0] - levels[3]).abs() < 1.0e-3);
+}
+
+#[test]
+fn subdivisions_click_between_beats() {
+    let engine = null_engine(NullOutput::default());
+    start(
+        &engine,
+        MetronomeSettings { bpm: 120.0, subdivision: Subdivision::Triplets, ..MetronomeSettings::default() },
+    );
+    let clicks = onsets(&render_left(&engine, 2.0));
+    assert_eq!(clicks.len(), 12);
+    let spacing = clicks[1] - clicks[0];
+    assert!(spacing.abs_diff(SAMPLE_RATE as usize / 6) <= 1);
+}
+
+#[test]
+fn stopping_silences_it() {
+    let engine = null_engine(NullOutput::default());
+    start(&engine, MetronomeSettings::default());
+    assert!(peak(&engine.render(frames(0.1)).unwrap()) > 0.1);
+
+    engine.set_metronome(&MetronomeSettings::default());
+    engine.render(frames(0.1)).unwrap();
+    assert_eq!(peak(&engine.render(frames(1.0)).unwrap()), 0.0);
+}
@@ -0,0 +1,140 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use common::{frames, null_engine, peak, rms};
+use toy_piano::audio::null::{Clock, NullOutput, Sink};
+use toy_piano::audio::synth_effects::{RoomPreset, SynthEffects};
+use toy_piano::{handle_midi_message, AudioEngine};
+
+/// Anything quieter than this counts as silence.
+const SILENCE: f32 = 1.0e-4;
+
+/// Renders `seconds` of audio in 10 ms blocks and returns when the last audible one ended.
+fn last_sound(engine: &AudioEngine, seconds: f64) -> f64 {
+    let block = frames(0.01);
+    let mut last = 0.0;
+    for i in 1..=(seconds * 100.0) as usize {
+        if peak(&engine.render(block).unwrap()) >= SILENCE {
+            last = i as f64 * 0.01;
+        }
+    }
+    last
+}
+
+#[test]
+fn silent_until_something_is_played() {
+    let engine = null_engine(NullOutput::default());
+    assert_eq!(peak(&engine.render(frames(0.5)).unwrap()), 0.0);
+}
+
+#[test]
+fn note_sounds_then_decays_after_release() {
+    let engine = null_engine(NullOutput::default());
+    let target = engine.midi_target();
+
+    handle_midi_message(&[0x90, 69, 100], &target);
+    let held = engine.render(frames(0.5)).unwrap();
+    assert_eq!(held.len(), frames(0.5) * 2);
+    assert!(rms(&held[held.len() / 2..]) > 0.01, "held note is too quiet");
+
+    handle_midi_message(&[0x80, 69, 0], &target);
+    // The release and reverb tail, then at least a second of silence
+    let decay = last_sound(&engine, 5.0);
+    assert!(decay > 0.1, "note stopped dead instead of releasing");
+    assert!(decay < 4.0, "note never died away");
+}
+
+#[test]
+fn sustain_pedal_holds_released_notes() {
+    let engine = null_engine(NullOutput::default());
+    let target = engine.midi_target();
+
+    handle_midi_message(&[0xB0, 64, 127], &target);
+    handle_midi_message(&[0x90, 69, 100], &target);
+    engine.render(frames(0.1)).unwrap();
+    handle_midi_message(&[0x80, 69, 0], &target);
+    assert!(rms(&engine.render(frames(0.5)).unwrap()) > 0.01, "pedal didn't hold the note");
+
+    handle_midi_message(&[0xB0, 64, 0], &target);
+    assert!(last_sound(&engine, 5.0) < 4.0, "note never died away after the pedal");
+}
+
+#[test]
+fn memory_sink_gets_what_was_rendered() {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let engine = null_engine(NullOutput {
+        sink: Sink::Memory(buffer.clone()),
+        ..NullOutput::default()
+    });
+
+    handle_midi_message(&[0x90, 69, 100], &engine.midi_target());
+    let first = engine.render(256).unwrap();
+    let second = engine.render(256).unwrap();
+
+    let recorded = buffer.lock().unwrap();
+    assert_eq!(recorded.len(), 1024);
+    assert_eq!(&recorded[..512], &first[..]);
+    assert_eq!(&recorded[512..], &second[..]);
+}
+
+#[test]
+fn wav_sink_writes_a_complete_file() {
+    let path = std::env::temp_dir().join(format!("toy-piano-null-{}.wav", std::process::id()));
+    let engine = null_engine(NullOutput {
+        sink: Sink::Wav(path.clone()),
+        ..NullOutput::default()
+    });
+
+    handle_midi_message(&[0x90, 69, 100], &engine.midi_target());
+    engine.render(1000).unwrap();
+    drop(engine);
+
+    let data = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(&data[..4], b"RIFF");
+    assert_eq!(&data[8..12], b"WAVE");
+    let data_len = u32::from_le_bytes(data[40..44].try_into().unwrap());
+    assert_eq!(data_len, 1000 * 2 * 2);
+    assert_eq!(data.len(), 44 + data_len as usize);
+    assert!(data[44..].iter().any(|b| *b != 0));
+}
+
+#[test]
+fn timer_clock_renders_in_the_background() {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let engine = null_engine(NullOutput {
+        sink: Sink::Memory(buffer.clone()),
+        clock: Clock::Timer,
+        ..NullOutput::default()
+    });
+
+    handle_midi_message(&[0x90, 69, 100], &engine.midi_target());
+    std::thread::sleep(Duration::from_millis(300));
+    drop(engine);
+
+    let recorded = buffer.lock().unwrap();
+    assert!(!recorded.is_empty(), "timer never rendered");
+    assert!(peak(&recorded) > 0.01);
+}
+
+#[test]
+fn reverb_send_sets_the_length_of_the_tail() {
+    let tail = |room: &str| {
+        let engine = null_engine(NullOutput::default());
+        let mut effects = SynthEffects::default();
+        effects.set_room(RoomPreset::ALL.into_iter().find(|r| r.name == room).unwrap());
+        engine.set_synth_effects(&effects);
+
+        handle_midi_message(&[0x90, 69, 100], &engine.midi_target());
+        engine.render(frames(0.3)).unwrap();
+        handle_midi_message(&[0x80, 69, 0], &engine.midi_target());
+        last_sound(&engine, 5.0)
+    };
+
+    let dry = tail("Dry");
+    let cathedral = tail("Cathedral");
+    assert!(dry < 0.5, "dry tail lasted {}s", dry);
+    assert!(cathedral > dry + 0.5, "cathedral tail {}s isn't longer than dry {}s", cathedral, dry);
+}
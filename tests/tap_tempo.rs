@@ -0,0 +1,59 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{frames, midi_file, null_engine, SAMPLE_RATE};
+use toy_piano::audio::metronome::MetronomeSettings;
+use toy_piano::audio::null::NullOutput;
+use toy_piano::midi::file::MidiFile;
+use toy_piano::midi::tap_tempo::TapTrigger;
+
+#[test]
+fn footswitch_taps_set_the_metronome_and_songs() {
+    let engine = null_engine(NullOutput::default());
+    engine.set_metronome(&MetronomeSettings { enabled: true, ..MetronomeSettings::default() });
+    engine.set_tap_trigger(Some(TapTrigger::Control(67)));
+    let target = engine.midi_target();
+
+    // Pressed every 400 ms is 150 BPM
+    let start = Instant::now();
+    for i in 0..3 {
+        let now = start + Duration::from_millis(400 * i);
+        assert!(target.tap_tempo.handle_input_at(&[0xB0, 67, 127], now));
+        assert!(target.tap_tempo.handle_input_at(&[0xB0, 67, 0], now + Duration::from_millis(100)));
+    }
+    let tapped = engine.tapped_tempo().expect("no tempo tapped");
+    assert!((tapped.bpm - 150.0).abs() < 1e-9, "{}", tapped.bpm);
+
+    // Picked up by the audio thread on the next block
+    engine.render(64).unwrap();
+    assert_eq!(engine.song_tempo(), Some(tapped.bpm));
+    let left: Vec<f32> = engine.render(frames(3.0)).unwrap().into_iter().step_by(2).collect();
+    let clicks: Vec<usize> = (1..left.len()).filter(|&i| left[i - 1] == 0.0 && left[i] != 0.0).collect();
+    let interval = 60.0 / tapped.bpm * SAMPLE_RATE as f64;
+    assert!(clicks.len() >= 6, "{:?}", clicks);
+    for pair in clicks.windows(2) {
+        assert!(((pair[1] - pair[0]) as f64 - interval).abs() <= 1.0, "{:?}", clicks);
+    }
+}
+
+#[test]
+fn song_tempo_changes_the_playback_speed() {
+    // Four quarter notes of 120 BPM: two seconds as written
+    let file = MidiFile::parse(&midi_file(&[(0, [0x90, 69, 100]), (1920, [0x80, 69, 0])])).unwrap();
+    assert_eq!(file.tempo(), 120.0);
+    let engine = null_engine(NullOutput::default());
+    engine.set_song_tempo(Some(240.0));
+    engine.play_midi_file(&file);
+
+    // Two seconds of lead-in, which doesn't speed up, then half a second at double speed
+    engine.render(frames(2.5)).unwrap();
+    let position = engine.playback_position().unwrap();
+    assert!((position - 1.0).abs() < 0.01, "{}", position);
+
+    // Back to as written, from where it got to
+    engine.set_song_tempo(None);
+    engine.render(frames(0.5)).unwrap();
+    let position = engine.playback_position().unwrap();
+    assert!((position - 1.5).abs() < 0.01, "{}", position);
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use common::{assert_silent_after, frames, frequency, null_engine, peak, sounding};
+use toy_piano::audio::null::NullOutput;
+use toy_piano::handle_midi_message;
+
+#[test]
+fn transpose_and_octave_shift_move_the_pitch() {
+    let engine = null_engine(NullOutput::default());
+    engine.set_transpose(7);
+    engine.set_octave_shift(-1);
+    // A4 (440 Hz) comes out as E4
+    handle_midi_message(&[0x90, 69, 80], &engine.midi_target());
+    let output = engine.render(frames(0.5)).unwrap();
+
+    let expected = 440.0 * 2f32.powf((7.0 - 12.0) / 12.0);
+    assert!((frequency(&output) - expected).abs() < 3.0, "got {} Hz", frequency(&output));
+    assert_eq!(sounding(&engine), vec![64]);
+}
+
+#[test]
+fn changing_transpose_while_held_does_not_stick_notes() {
+    let engine = null_engine(NullOutput::default());
+    engine.set_transpose(2);
+    handle_midi_message(&[0x90, 60, 80], &engine.midi_target());
+    engine.render(frames(0.1)).unwrap();
+
+    engine.set_transpose(-3);
+    handle_midi_message(&[0x80, 60, 0], &engine.midi_target());
+    assert!(sounding(&engine).is_empty());
+
+    // Past the release time there's nothing left playing
+    assert_silent_after(&engine, 1.0);
+}
+
+#[test]
+fn repeated_note_on_after_a_change_releases_the_old_pitch() {
+    let engine = null_engine(NullOutput::default());
+    handle_midi_message(&[0x90, 60, 80], &engine.midi_target());
+    engine.set_transpose(5);
+    handle_midi_message(&[0x90, 60, 80], &engine.midi_target());
+    assert_eq!(sounding(&engine), vec![65]);
+
+    handle_midi_message(&[0x80, 60, 0], &engine.midi_target());
+    assert!(sounding(&engine).is_empty());
+}
+
+#[test]
+fn notes_shifted_off_the_keyboard_are_dropped() {
+    let engine = null_engine(NullOutput::default());
+    engine.set_transpose(24);
+    engine.set_octave_shift(3);
+    handle_midi_message(&[0x90, 100, 80], &engine.midi_target());
+    assert!(sounding(&engine).is_empty());
+    assert!(peak(&engine.render(frames(0.1)).unwrap()) < 1.0e-6);
+    handle_midi_message(&[0x80, 100, 0], &engine.midi_target());
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use common::{frames, frequency, null_engine};
+use toy_piano::audio::null::NullOutput;
+use toy_piano::midi::scala::ScalaFiles;
+use toy_piano::midi::tuning::{Temperament, Tuning, TuningSettings};
+use toy_piano::{handle_midi_message, AudioEngine};
+
+/// Pitch of what's playing, once the attack has settled.
+fn measure(engine: &AudioEngine) -> f32 {
+    engine.render(frames(0.1)).unwrap();
+    frequency(&engine.render(frames(0.5)).unwrap())
+}
+
+#[test]
+fn baroque_pitch_lowers_a4() {
+    let engine = null_engine(NullOutput::default());
+    engine.set_tuning(&TuningSettings { reference: 415.0, ..TuningSettings::default() });
+    handle_midi_message(&[0x90, 69, 80], &engine.midi_target());
+    let a4 = measure(&engine);
+    assert!((a4 - 415.0).abs() < 0.5, "got {} Hz", a4);
+}
+
+#[test]
+fn temperament_tunes_each_note() {
+    let settings = TuningSettings { temperament: Temperament::Meantone, ..TuningSettings::default() };
+    let tuning = Tuning::new(&settings);
+    let engine = null_engine(NullOutput::default());
+    engine.set_tuning(&settings);
+    for key in [60, 64, 66] {
+        handle_midi_message(&[0x90, key, 80], &engine.midi_target());
+        let played = measure(&engine);
+        let expected = tuning.frequency(key).unwrap() as f32;
+        assert!((played - expected).abs() < 0.5, "key {}: got {} Hz, expected {}", key, played, expected);
+        handle_midi_message(&[0x80, key, 0], &engine.midi_target());
+        engine.render(frames(1.0)).unwrap();
+    }
+}
+
+#[test]
+fn held_notes_keep_their_tuning_when_others_play() {
+    let settings = TuningSettings { temperament: Temperament::Meantone, ..TuningSettings::default() };
+    let engine = null_engine(NullOutput::default());
+    engine.set_tuning(&settings);
+
+    // C4 needs a different fine tune from E4, so it mustn't retune E4's channel
+    handle_midi_message(&[0x90, 64, 80], &engine.midi_target());
+    handle_midi_message(&[0x90, 60, 80], &engine.midi_target());
+    engine.render(frames(0.1)).unwrap();
+    handle_midi_message(&[0x80, 60, 0], &engine.midi_target());
+    engine.render(frames(1.0)).unwrap();
+
+    let expected = Tuning::new(&settings).frequency(64).unwrap() as f32;
+    let e4 = measure(&engine);
+    assert!((e4 - expected).abs() < 0.5, "got {} Hz, expected {}", e4, expected);
+}
+
+#[test]
+fn scala_scale_retunes_the_keyboard() {
+    // Quarter tones: each key is 50 cents above the last, from middle C
+    let steps: String = (1..=24).map(|step| format!("{}.0\n", step * 50)).collect();
+    let path = std::env::temp_dir().join(format!("toy-piano-test-{}.scl", std::process::id()));
+    std::fs::write(&path, format!("! 24-EDO\n24 tone equal temperament\n24\n{}", steps)).unwrap();
+
+    let engine = null_engine(NullOutput::default());
+    let files = ScalaFiles { scale: path.clone(), mapping: None };
+    let scale = engine.load_scala(&files, 440.0).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(scale.len(), 24);
+
+    // Key 78 is 18 quarter tones, 900 cents, above middle C: A4
+    handle_midi_message(&[0x90, 78, 80], &engine.midi_target());
+    let a4 = measure(&engine);
+    assert!((a4 - 440.0).abs() < 0.5, "got {} Hz", a4);
+    handle_midi_message(&[0x80, 78, 0], &engine.midi_target());
+    engine.render(frames(1.0)).unwrap();
+
+    handle_midi_message(&[0x90, 61, 80], &engine.midi_target());
+    let expected = 261.6256 * 2f32.powf(50.0 / 1200.0);
+    let played = measure(&engine);
+    assert!((played - expected).abs() < 0.5, "got {} Hz, expected {}", played, expected);
+}
+
+#[test]
+fn missing_scala_file_keeps_the_old_tuning() {
+    let engine = null_engine(NullOutput::default());
+    let files = ScalaFiles { scale: "/nonexistent/scale.scl".into(), mapping: None };
+    assert!(engine.load_scala(&files, 440.0).is_err());
+
+    handle_midi_message(&[0x90, 69, 80], &engine.midi_target());
+    assert!((measure(&engine) - 440.0).abs() < 0.5);
+}
+
+#[test]
+fn mts_single_note_change_retunes_the_key() {
+    let engine = null_engine(NullOutput::default());
+    // A4 to a quarter tone above, real-time single note tuning change
+    let sysex = [0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, 0x01, 69, 69, 0x40, 0x00, 0xF7];
+    handle_midi_message(&sysex, &engine.midi_target());
+
+    handle_midi_message(&[0x90, 69, 80], &engine.midi_target());
+    let expected = 440.0 * 2f32.powf(0.5 / 12.0);
+    let played = measure(&engine);
+    assert!((played - expected).abs() < 0.5, "got {} Hz, expected {}", played, expected);
+}
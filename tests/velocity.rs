@@ -0,0 +1,33 @@
+mod common;
+
+use common::null_engine;
+use toy_piano::audio::null::NullOutput;
+use toy_piano::midi::handle_input_message;
+use toy_piano::midi::velocity::VelocityCurve;
+use toy_piano::handle_midi_message;
+
+#[test]
+fn input_curve_applies_to_the_device_only() {
+    let engine = null_engine(NullOutput::default());
+    engine.set_input_velocity_curve(&VelocityCurve::Fixed(100));
+    let target = engine.midi_target();
+
+    handle_input_message(&[0x90, 60, 30], &target);
+    // On-screen keys, files and the computer keyboard go straight in
+    handle_midi_message(&[0x90, 64, 30], &target);
+
+    let keys = target.keys.lock().unwrap();
+    assert_eq!(keys.velocity(60), Some(100));
+    assert_eq!(keys.velocity(64), Some(30));
+}
+
+#[test]
+fn note_off_as_zero_velocity_note_on_still_releases() {
+    let engine = null_engine(NullOutput::default());
+    engine.set_input_velocity_curve(&VelocityCurve::Fixed(100));
+    let target = engine.midi_target();
+
+    handle_input_message(&[0x90, 60, 30], &target);
+    handle_input_message(&[0x90, 60, 0], &target);
+    assert!(target.keys.lock().unwrap().sounding_notes().is_empty());
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use common::{assert_silent_after, frames, frequency, null_engine, peak, rms, sounding};
+use toy_piano::audio::null::NullOutput;
+use toy_piano::midi::zones::{KeyboardMode, ZoneSettings};
+use toy_piano::handle_midi_message;
+
+/// Zones that both play the test SoundFont's only preset.
+fn zones(mode: KeyboardMode) -> ZoneSettings {
+    let mut zones = ZoneSettings { mode, ..ZoneSettings::default() };
+    zones.second.program = 0;
+    zones
+}
+
+#[test]
+fn split_sends_low_keys_to_the_second_zone() {
+    let engine = null_engine(NullOutput::default());
+    engine.set_zones(&ZoneSettings { split_point: 60, ..zones(KeyboardMode::Split) });
+
+    handle_midi_message(&[0x90, 48, 80], &engine.midi_target());
+    handle_midi_message(&[0x90, 64, 80], &engine.midi_target());
+    // The lower zone is an octave down; the upper one plays as is
+    assert_eq!(sounding(&engine), vec![36, 64]);
+    assert!(peak(&engine.render(frames(0.1)).unwrap()) > 0.01);
+
+    handle_midi_message(&[0x80, 48, 0], &engine.midi_target());
+    handle_midi_message(&[0x80, 64, 0], &engine.midi_target());
+    assert!(sounding(&engine).is_empty());
+}
+
+#[test]
+fn layer_plays_both_zones() {
+    let engine = null_engine(NullOutput::default());
+    engine.set_zones(&zones(KeyboardMode::Layer));
+
+    handle_midi_message(&[0x90, 60, 80], &engine.midi_target());
+    assert_eq!(sounding(&engine), vec![48, 60]);
+
+    handle_midi_message(&[0x80, 60, 0], &engine.midi_target());
+    assert!(sounding(&engine).is_empty());
+}
+
+#[test]
+fn changing_mode_while_held_does_not_stick_notes() {
+    let engine = null_engine(NullOutput::default());
+    engine.set_zones(&zones(KeyboardMode::Layer));
+    handle_midi_message(&[0x90, 60, 80], &engine.midi_target());
+    engine.render(frames(0.1)).unwrap();
+
+    engine.set_zones(&zones(KeyboardMode::Single));
+    handle_midi_message(&[0x80, 60, 0], &engine.midi_target());
+    assert!(sounding(&engine).is_empty());
+
+    // Past the release time there's nothing left on either channel
+    assert_silent_after(&engine, 2.0);
+}
+
+#[test]
+fn sustain_pedal_holds_both_zones() {
+    let engine = null_engine(NullOutput::default());
+    engine.set_zones(&zones(KeyboardMode::Split));
+
+    handle_midi_message(&[0xB0, 64, 127], &engine.midi_target());
+    handle_midi_message(&[0x90, 48, 80], &engine.midi_target());
+    handle_midi_message(&[0x90, 72, 80], &engine.midi_target());
+    handle_midi_message(&[0x80, 48, 0], &engine.midi_target());
+    handle_midi_message(&[0x80, 72, 0], &engine.midi_target());
+
+    // Well past the release time, both are still held by the pedal
+    engine.render(frames(1.0)).unwrap();
+    assert!(peak(&engine.render(frames(0.1)).unwrap()) > 0.01);
+
+    handle_midi_message(&[0xB0, 64, 0], &engine.midi_target());
+    assert_silent_after(&engine, 2.0);
+}
+
+/// Level of a low (second zone) and a high (main zone) note in split mode, the
+/// second zone quieter, after `controls` from the input device.
+fn split_levels(controls: &[[u8; 3]]) -> (f32, f32) {
+    let engine = null_engine(NullOutput::default());
+    let mut settings = ZoneSettings { split_point: 60, ..zones(KeyboardMode::Split) };
+    settings.second.volume = 40;
+    engine.set_zones(&settings);
+    for control in controls {
+        handle_midi_message(control, &engine.midi_target());
+    }
+    let level = |note: u8| {
+        handle_midi_message(&[0x90, note, 50], &engine.midi_target());
+        let level = rms(&engine.render(frames(0.2)).unwrap());
+        handle_midi_message(&[0x80, note, 0], &engine.midi_target());
+        engine.render(frames(1.0)).unwrap();
+        level
+    };
+    (level(48), level(72))
+}
+
+#[test]
+fn device_volume_keeps_each_zones_level() {
+    let (second, main) = split_levels(&[]);
+    assert!(second < main * 0.5, "{} vs {}", second, main);
+
+    // A fader all the way up leaves both where the zones put them
+    let (full_second, full_main) = split_levels(&[[0xB0, 7, 127]]);
+    assert!((full_second - second).abs() < second * 0.01, "{} vs {}", full_second, second);
+    assert!((full_main - main).abs() < main * 0.01, "{} vs {}", full_main, main);
+
+    // Halfway down turns both down alike
+    let (half_second, half_main) = split_levels(&[[0xB0, 7, 64], [0xB0, 11, 127]]);
+    assert!(half_second < second * 0.5 && half_main < main * 0.5);
+    let (ratio, half_ratio) = (second / main, half_second / half_main);
+    assert!((half_ratio - ratio).abs() < ratio * 0.05, "{} vs {}", half_ratio, ratio);
+}
+
+#[test]
+fn device_data_entry_does_not_retune() {
+    let engine = null_engine(NullOutput::default());
+    // Fine tune up by nearly a semitone, through RPN 1
+    for control in [[0xB0, 101, 0], [0xB0, 100, 1], [0xB0, 6, 127], [0xB0, 38, 127]] {
+        handle_midi_message(&control, &engine.midi_target());
+    }
+    handle_midi_message(&[0x90, 69, 80], &engine.midi_target());
+    let pitch = frequency(&engine.render(frames(0.5)).unwrap());
+    assert!((pitch - 440.0).abs() < 2.0, "got {} Hz", pitch);
+}